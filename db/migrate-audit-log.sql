@@ -0,0 +1,20 @@
+-- Audit log: run once against a DB created before audit_log existed. New
+-- DBs get it from struct-features-capsules.sql.
+BEGIN TRANSACTION;
+
+-- One row per write made by the bot. `actor` is the Discord id of whoever ran
+-- the command, NULL for the bot's own jobs and caches.
+CREATE TABLE IF NOT EXISTS "audit_log" (
+    "id"         INTEGER,
+    "actor"      INTEGER,
+    "action"     TEXT NOT NULL,
+    "target"     TEXT NOT NULL,
+    "old_value"  TEXT,
+    "new_value"  TEXT,
+    "created_at" TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY("id" AUTOINCREMENT)
+);
+CREATE INDEX IF NOT EXISTS "idx_audit_log_action" ON "audit_log" ("action");
+CREATE INDEX IF NOT EXISTS "idx_audit_log_actor" ON "audit_log" ("actor");
+
+COMMIT;
@@ -0,0 +1,15 @@
+-- Season calendar cache: run once against a DB created before calendar
+-- existed. New DBs get it from struct-features-capsules.sql.
+BEGIN TRANSACTION;
+
+-- Filled by the bot from ESPN, one row per week (1..=22 like `picks.week`),
+-- dates are RFC 3339 UTC
+CREATE TABLE IF NOT EXISTS "calendar" (
+    "season"     INTEGER NOT NULL,
+    "week"       INTEGER NOT NULL,
+    "start_date" TEXT NOT NULL,
+    "end_date"   TEXT NOT NULL,
+    PRIMARY KEY("season", "week")
+);
+
+COMMIT;
@@ -0,0 +1,26 @@
+-- Capsule repick history: run once against a DB created before
+-- capsule_history and pools.repick_weight existed. New DBs get both from
+-- struct-features-capsules.sql.
+BEGIN TRANSACTION;
+
+CREATE TABLE IF NOT EXISTS "capsule_history" (
+    "id"         INTEGER PRIMARY KEY AUTOINCREMENT,
+    "capsuleid"  INTEGER NOT NULL,
+    "season"     INTEGER NOT NULL,
+    "poolerid"   INTEGER NOT NULL,
+
+    "type"       INTEGER,
+    "conference" INTEGER,
+    "division"   INTEGER,
+    "slot"       INTEGER,
+    "old_team"   TEXT NOT NULL,
+    "new_team"   TEXT NOT NULL,
+    "changed_at" TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT "CapsuleId_FK" FOREIGN KEY("capsuleid") REFERENCES "capsules"("id") ON DELETE CASCADE,
+    CONSTRAINT "PoolerId_FK" FOREIGN KEY("poolerid") REFERENCES "poolers"("id") ON DELETE CASCADE
+);
+
+-- Percentage of the points a repicked capsule slot is worth (100 = no penalty)
+ALTER TABLE "pools" ADD COLUMN "repick_weight" INTEGER NOT NULL DEFAULT (100);
+
+COMMIT;
@@ -0,0 +1,11 @@
+-- Capsule lock date: run once against a DB created before capsule_locks
+-- existed. New DBs get it from struct-features-capsules.sql.
+BEGIN TRANSACTION;
+
+-- One row per season, `locked_at` is an RFC 3339 UTC date (e.g. 2025-09-04T20:00:00Z)
+CREATE TABLE IF NOT EXISTS "capsule_locks" (
+    "season"    INTEGER PRIMARY KEY,
+    "locked_at" TEXT NOT NULL
+);
+
+COMMIT;
@@ -0,0 +1,14 @@
+-- Final capsule scores: run once against a DB created before capsule_scores
+-- existed. New DBs get it from struct-features-capsules.sql.
+BEGIN TRANSACTION;
+
+-- Written once the Super Bowl is final, `/eliminatoires recorriger` clears a season
+CREATE TABLE IF NOT EXISTS "capsule_scores" (
+    "poolerid" INTEGER NOT NULL,
+    "season"   INTEGER NOT NULL,
+    "score"    INTEGER NOT NULL,
+    PRIMARY KEY("poolerid", "season"),
+    CONSTRAINT "PoolerId_FK" FOREIGN KEY("poolerid") REFERENCES "poolers"("id") ON DELETE CASCADE
+);
+
+COMMIT;
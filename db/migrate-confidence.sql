@@ -0,0 +1,10 @@
+-- Confidence-points mode: run once against a DB created before pools.mode
+-- and match_picks.confidence existed. New DBs get both from
+-- struct-features-capsules.sql.
+BEGIN TRANSACTION;
+
+-- 0 = classic picks, 1 = confidence points (each pick ranked 1..N)
+ALTER TABLE "pools" ADD COLUMN "mode" INTEGER NOT NULL DEFAULT (0);
+ALTER TABLE "match_picks" ADD COLUMN "confidence" INTEGER;
+
+COMMIT;
@@ -0,0 +1,12 @@
+-- Default picks: run once against a DB created before pools.default_picks
+-- existed. New DBs get the columns from struct-features-capsules.sql.
+BEGIN TRANSACTION;
+
+-- 0 = none, 1 = home teams, 2 = favorite team then home teams, 3 = pool majority
+ALTER TABLE "pools" ADD COLUMN "default_picks" INTEGER NOT NULL DEFAULT (0);
+-- Percentage of the points an automatic pick is worth (100 = no penalty)
+ALTER TABLE "pools" ADD COLUMN "auto_pick_weight" INTEGER NOT NULL DEFAULT (100);
+-- 1 when the pick was filled in by the bot for a pooler who missed the deadline
+ALTER TABLE "match_picks" ADD COLUMN "auto" INTEGER NOT NULL DEFAULT (0);
+
+COMMIT;
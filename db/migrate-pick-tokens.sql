@@ -0,0 +1,21 @@
+-- Purpose-bound, expiring pick tokens: run once against a DB created before
+-- they existed. New DBs get the table from struct-features-capsules.sql.
+-- Outstanding links stop working, poolers just ask the bot for a new one.
+BEGIN TRANSACTION;
+
+DROP TABLE IF EXISTS "pick_tokens";
+
+-- `token` is a random string from the bot, `expires_at` is RFC 3339 UTC.
+-- A pooler holds at most one token per purpose and week.
+CREATE TABLE "pick_tokens" (
+    "token"      TEXT PRIMARY KEY,
+    "poolerid"   INTEGER NOT NULL,
+    "purpose"    TEXT NOT NULL CHECK ("purpose" IN ('picks', 'picks-edit', 'capsule', 'capsule-repicks')),
+    "season"     INTEGER NOT NULL,
+    "week"       INTEGER NOT NULL,
+    "expires_at" TEXT NOT NULL,
+    CONSTRAINT "PoolerId_FK" FOREIGN KEY("poolerid") REFERENCES "poolers"("id") ON DELETE CASCADE,
+    CONSTRAINT "uq_pick_tokens_purpose" UNIQUE ("poolerid", "purpose", "season", "week")
+);
+
+COMMIT;
@@ -0,0 +1,8 @@
+-- Favorite team on picks: run once against a DB created before picks.favteam
+-- existed. New DBs get the column from struct-features-capsules.sql.
+BEGIN TRANSACTION;
+
+-- The pooler's favorite team when the picks went in, NULL on older picks
+ALTER TABLE "picks" ADD COLUMN "favteam" TEXT;
+
+COMMIT;
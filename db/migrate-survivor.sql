@@ -0,0 +1,21 @@
+-- Survivor side game: run once against a DB created before survivor_picks
+-- existed. New DBs get it from struct-features-capsules.sql.
+BEGIN TRANSACTION;
+
+-- One team per pooler and week, never the same team twice in a season.
+-- `outcome` stays NULL until the game is final: 1 survived, 0 eliminated
+CREATE TABLE IF NOT EXISTS "survivor_picks" (
+    "id"       INTEGER,
+    "season"   INTEGER NOT NULL,
+    "week"     INTEGER NOT NULL,
+    "poolerid" INTEGER NOT NULL,
+    "matchid"  TEXT NOT NULL,
+    "team"     TEXT NOT NULL,
+    "outcome"  INTEGER,
+    PRIMARY KEY("id" AUTOINCREMENT),
+    CONSTRAINT "PoolerId_FK" FOREIGN KEY("poolerid") REFERENCES "poolers"("id") ON DELETE CASCADE,
+    CONSTRAINT "uq_survivor_pooler_week" UNIQUE ("season", "week", "poolerid"),
+    CONSTRAINT "uq_survivor_pooler_team" UNIQUE ("season", "poolerid", "team")
+);
+
+COMMIT;
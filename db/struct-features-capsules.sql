@@ -1,5 +1,24 @@
 BEGIN TRANSACTION;
 
+CREATE TABLE IF NOT EXISTS "audit_log" (
+    "id"         INTEGER,
+    "actor"      INTEGER,
+    "action"     TEXT NOT NULL,
+    "target"     TEXT NOT NULL,
+    "old_value"  TEXT,
+    "new_value"  TEXT,
+    "created_at" TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY("id" AUTOINCREMENT)
+);
+CREATE INDEX IF NOT EXISTS "idx_audit_log_action" ON "audit_log" ("action");
+CREATE INDEX IF NOT EXISTS "idx_audit_log_actor" ON "audit_log" ("actor");
+CREATE TABLE IF NOT EXISTS "calendar" (
+    "season"     INTEGER NOT NULL,
+    "week"       INTEGER NOT NULL,
+    "start_date" TEXT NOT NULL,
+    "end_date"   TEXT NOT NULL,
+    PRIMARY KEY("season", "week")
+);
 CREATE TABLE IF NOT EXISTS "capsules" (
     "id"         INTEGER PRIMARY KEY AUTOINCREMENT,
     "season"     INTEGER NOT NULL DEFAULT 2000,
@@ -12,6 +31,33 @@ CREATE TABLE IF NOT EXISTS "capsules" (
     "team"       TEXT,
     CONSTRAINT "PoolerId_FK" FOREIGN KEY("poolerid") REFERENCES "poolers"("id")
 );
+CREATE TABLE IF NOT EXISTS "capsule_history" (
+    "id"         INTEGER PRIMARY KEY AUTOINCREMENT,
+    "capsuleid"  INTEGER NOT NULL,
+    "season"     INTEGER NOT NULL,
+    "poolerid"   INTEGER NOT NULL,
+
+    "type"       INTEGER,
+    "conference" INTEGER,
+    "division"   INTEGER,
+    "slot"       INTEGER,
+    "old_team"   TEXT NOT NULL,
+    "new_team"   TEXT NOT NULL,
+    "changed_at" TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT "CapsuleId_FK" FOREIGN KEY("capsuleid") REFERENCES "capsules"("id") ON DELETE CASCADE,
+    CONSTRAINT "PoolerId_FK" FOREIGN KEY("poolerid") REFERENCES "poolers"("id") ON DELETE CASCADE
+);
+CREATE TABLE IF NOT EXISTS "capsule_locks" (
+    "season"    INTEGER PRIMARY KEY,
+    "locked_at" TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS "capsule_scores" (
+    "poolerid" INTEGER NOT NULL,
+    "season"   INTEGER NOT NULL,
+    "score"    INTEGER NOT NULL,
+    PRIMARY KEY("poolerid", "season"),
+    CONSTRAINT "PoolerId_FK" FOREIGN KEY("poolerid") REFERENCES "poolers"("id") ON DELETE CASCADE
+);
 CREATE TABLE IF NOT EXISTS "features" (
     "id"     INTEGER,
     "season" INTEGER NOT NULL DEFAULT (2000),
@@ -22,11 +68,14 @@ CREATE TABLE IF NOT EXISTS "features" (
     PRIMARY KEY("id" AUTOINCREMENT)
 );
 CREATE TABLE IF NOT EXISTS "pick_tokens" (
-    "poolerid" INTEGER PRIMARY KEY,
-    "token"    INTEGER NOT NULL UNIQUE,
-    "season"   INTEGER NOT NULL,
-    "week"     INTEGER NOT NULL,
-    CONSTRAINT "PoolerId_FK" FOREIGN KEY("poolerid") REFERENCES "poolers"("id") ON DELETE CASCADE
+    "token"      TEXT PRIMARY KEY,
+    "poolerid"   INTEGER NOT NULL,
+    "purpose"    TEXT NOT NULL CHECK ("purpose" IN ('picks', 'picks-edit', 'capsule', 'capsule-repicks')),
+    "season"     INTEGER NOT NULL,
+    "week"       INTEGER NOT NULL,
+    "expires_at" TEXT NOT NULL,
+    CONSTRAINT "PoolerId_FK" FOREIGN KEY("poolerid") REFERENCES "poolers"("id") ON DELETE CASCADE,
+    CONSTRAINT "uq_pick_tokens_purpose" UNIQUE ("poolerid", "purpose", "season", "week")
 );
 CREATE TABLE IF NOT EXISTS "picks" (
     "id"          INTEGER,
@@ -36,6 +85,7 @@ CREATE TABLE IF NOT EXISTS "picks" (
     "scorecache"  INTEGER,
     "featurepick" INTEGER,
     "featcache"   INTEGER,
+    "favteam"     TEXT,
     PRIMARY KEY("id" AUTOINCREMENT),
     CONSTRAINT "PoolerId_FK" FOREIGN KEY("poolerid") REFERENCES "poolers"("id") ON DELETE SET NULL,
     CONSTRAINT "uq_picks_pooler_week" UNIQUE ("season", "week", "poolerid")
@@ -44,6 +94,8 @@ CREATE TABLE IF NOT EXISTS "match_picks" (
     "pickid"  INTEGER,
     "matchid" TEXT,
     "team"    TEXT,
+    "confidence" INTEGER,
+    "auto"    INTEGER NOT NULL DEFAULT (0),
     PRIMARY KEY("pickid", "matchid"),
     CONSTRAINT "PickId_FK" FOREIGN KEY("pickid") REFERENCES "picks"("id") ON DELETE CASCADE
 );
@@ -62,8 +114,25 @@ CREATE TABLE IF NOT EXISTS "pools" (
     "id"   INTEGER,
     "name" TEXT,
     "motp" TEXT,
+    "mode" INTEGER NOT NULL DEFAULT (0),
+    "repick_weight" INTEGER NOT NULL DEFAULT (100),
+    "default_picks" INTEGER NOT NULL DEFAULT (0),
+    "auto_pick_weight" INTEGER NOT NULL DEFAULT (100),
     PRIMARY KEY("id" AUTOINCREMENT)
 );
+CREATE TABLE IF NOT EXISTS "survivor_picks" (
+    "id"       INTEGER,
+    "season"   INTEGER NOT NULL,
+    "week"     INTEGER NOT NULL,
+    "poolerid" INTEGER NOT NULL,
+    "matchid"  TEXT NOT NULL,
+    "team"     TEXT NOT NULL,
+    "outcome"  INTEGER,
+    PRIMARY KEY("id" AUTOINCREMENT),
+    CONSTRAINT "PoolerId_FK" FOREIGN KEY("poolerid") REFERENCES "poolers"("id") ON DELETE CASCADE,
+    CONSTRAINT "uq_survivor_pooler_week" UNIQUE ("season", "week", "poolerid"),
+    CONSTRAINT "uq_survivor_pooler_team" UNIQUE ("season", "poolerid", "team")
+);
 CREATE TABLE IF NOT EXISTS "users" (
     "id"        INTEGER,
     "email"     TEXT UNIQUE,
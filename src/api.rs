@@ -0,0 +1,482 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use chrono::{SecondsFormat, Utc};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use serde_json::{Value, json};
+use tracing::error;
+
+use library::autopick::started_default_picks;
+use library::config::Config;
+use library::database::{Actor, CapsuleSlot, DB, TokenPurpose};
+use library::football::{Conference, PoolMode, Week, get_week};
+use library::standings::match_json;
+use library::validation::{
+    CAPSULE_CHAMPION, CAPSULE_TOP_SEED, CAPSULE_WILDCARD, CAPSULE_WINNER, CapsuleSubmission, ConferenceCapsule,
+    capsule_changes, parse_picks, validate_capsule, validate_picks,
+};
+
+use crate::server;
+use crate::shutdown::Shutdown;
+
+type ApiResult = hyper::http::Result<Response<Body>>;
+
+// Capsule winners are keyed by division in `Division` order
+const DIVISION_KEYS: [&str; 4] = ["North", "South", "East", "West"];
+
+// Called by the web pick app, bind it on localhost next to it
+pub async fn serve(addr: SocketAddr, db: DB, config: &'static Config, shutdown: Arc<Shutdown>) {
+    server::serve("API", addr, move |req| route(req, db.clone(), config, shutdown.clone())).await;
+}
+
+async fn route(req: Request<Body>, db: DB, config: &Config, shutdown: Arc<Shutdown>) -> Result<Response<Body>, Infallible> {
+    // Submissions are drained on shutdown like interactions, the DB closes right after
+    let Some(_permit) = shutdown.track().await else {
+        return Ok(error_response(StatusCode::SERVICE_UNAVAILABLE, "Le bot redémarre, réessaie dans un instant.")
+            .unwrap_or_else(|_| Response::new(Body::empty())));
+    };
+
+    let method = req.method().clone();
+    let query = req.uri().query().unwrap_or_default().to_owned();
+    let path: Vec<String> = req.uri().path()
+        .trim_matches('/')
+        .split('/')
+        .map(str::to_owned)
+        .collect();
+
+    let res = match (&method, path.iter().map(String::as_str).collect::<Vec<_>>().as_slice()) {
+        (&Method::GET, ["api", "tokens", token]) => resolve_token(&db, token, &query).await,
+        (&Method::GET, ["api", "matches", season, week]) => week_matches(&config.data_url, season, week).await,
+        (&Method::POST, ["api", "picks", token]) => match read_json(req).await {
+            Some(body) => submit_picks(&db, &config.data_url, token, &body).await,
+            None => error_response(StatusCode::BAD_REQUEST, "Le corps de la requête n'est pas du JSON valide."),
+        },
+        (&Method::POST, ["api", "picks-edit", token]) => match read_json(req).await {
+            Some(body) => edit_picks(&db, &config.data_url, token, &body).await,
+            None => error_response(StatusCode::BAD_REQUEST, "Le corps de la requête n'est pas du JSON valide."),
+        },
+        (&Method::POST, ["api", "capsules", token]) => match read_json(req).await {
+            Some(body) => submit_capsule(&db, token, &body).await,
+            None => error_response(StatusCode::BAD_REQUEST, "Le corps de la requête n'est pas du JSON valide."),
+        },
+        (&Method::POST, ["api", "capsule-repicks", token]) => match read_json(req).await {
+            Some(body) => repick_capsule(&db, token, &body).await,
+            None => error_response(StatusCode::BAD_REQUEST, "Le corps de la requête n'est pas du JSON valide."),
+        },
+        _ => error_response(StatusCode::NOT_FOUND, "Route inconnue."),
+    };
+
+    Ok(res.unwrap_or_else(|_| Response::new(Body::empty())))
+}
+
+// `?purpose=capsule`, weekly picks when not given
+async fn resolve_token(db: &DB, token: &str, query: &str) -> ApiResult {
+    let purpose = query.split('&')
+        .find_map(|pair| pair.strip_prefix("purpose="))
+        .unwrap_or(TokenPurpose::Picks.as_str());
+    let Ok(purpose) = purpose.parse::<TokenPurpose>() else {
+        return error_response(StatusCode::BAD_REQUEST, "Type de lien inconnu.");
+    };
+
+    let pick = match db.validate_pick_token(token, purpose).await {
+        Ok(Some(pick)) => pick,
+        Ok(None) => return error_response(StatusCode::NOT_FOUND, "Ce lien est invalide ou expiré."),
+        Err(e) => return internal_error("resolve the token", e),
+    };
+    let pooler = match db.fetch_pooler(&pick.poolerid).await {
+        Ok(pooler) => pooler,
+        Err(e) => return internal_error("fetch the pooler", e),
+    };
+    let mode = match db.fetch_pool_mode(&pooler.poolid).await {
+        Ok(mode) => mode,
+        Err(e) => return internal_error("fetch the pool mode", e),
+    };
+    let feature = db.fetch_feature(pick.season, pick.week).await.ok();
+
+    // What the link is about to change, for the page to start from
+    let current = match purpose {
+        TokenPurpose::PicksEdit => match db.fetch_pick(&pick.season, &pick.week, &pick.poolerid).await {
+            Ok(current) => {
+                let ranks = current.confidences.unwrap_or_default();
+                let picks: serde_json::Map<_, _> = current.picks.unwrap_or_default().into_iter()
+                    .map(|(matchid, team)| {
+                        let rank = ranks.get(&matchid);
+                        (matchid, json!({ "team": team, "confidence": rank }))
+                    })
+                    .collect();
+                json!({ "picks": picks, "featpick": current.featpick })
+            },
+            Err(_) => return error_response(StatusCode::NOT_FOUND, "Aucun choix à modifier pour cette semaine."),
+        },
+        TokenPurpose::CapsuleRepicks => match db.fetch_capsule_slots(&pick.poolerid, &pick.season).await {
+            Ok(slots) => json!({ "capsule": capsule_json(&slots), "repicks": pooler.repicks }),
+            Err(e) => return internal_error("fetch the capsule", e),
+        },
+        TokenPurpose::Picks | TokenPurpose::Capsule => Value::Null,
+    };
+
+    json_response(StatusCode::OK, json!({
+        "purpose": purpose.as_str(),
+        "season": pick.season,
+        "week": pick.week,
+        "expires_at": pick.expires_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+        "pooler": {
+            "id": pooler.id,
+            "name": pooler.name,
+            "favteam": pooler.favteam,
+            "avatar": pooler.avatar,
+        },
+        "mode": match mode {
+            PoolMode::Classic => "classic",
+            PoolMode::Confidence => "confidence",
+        },
+        "feature": feature.map(|f| json!({ "match": f.matchid, "target": f.target })),
+        "current": current,
+    }))
+}
+
+async fn week_matches(data_url: &str, season: &str, week: &str) -> ApiResult {
+    let (Ok(season), Some(week)) = (season.parse::<u16>(), week.parse::<i64>().ok()) else {
+        return error_response(StatusCode::BAD_REQUEST, "Saison ou semaine invalide.");
+    };
+    let Ok(week) = Week::in_season(&season, week) else {
+        return error_response(StatusCode::BAD_REQUEST, "Saison ou semaine invalide.");
+    };
+
+    let matches: Vec<_> = match get_week(data_url, &season, &week).await {
+        Ok(matches) => matches.iter().map(match_json).collect(),
+        Err(e) => return internal_error("fetch the matches", e),
+    };
+
+    json_response(StatusCode::OK, json!({ "season": season, "week": week.number(), "matches": matches }))
+}
+
+// { "picks": { "<matchid>": "<team>" }, "confidences": { "<matchid>": <rank> }, "featpick": 0 | 1 }
+async fn submit_picks(db: &DB, data_url: &str, token: &str, body: &Value) -> ApiResult {
+    let pick = match db.validate_pick_token(token, TokenPurpose::Picks).await {
+        Ok(Some(pick)) => pick,
+        Ok(None) => return error_response(StatusCode::NOT_FOUND, "Ce lien est invalide ou expiré."),
+        Err(e) => return internal_error("resolve the token", e),
+    };
+    let pooler = match db.fetch_pooler(&pick.poolerid).await {
+        Ok(pooler) => pooler,
+        Err(e) => return internal_error("fetch the pooler", e),
+    };
+    let mode = match db.fetch_pool_mode(&pooler.poolid).await {
+        Ok(mode) => mode,
+        Err(e) => return internal_error("fetch the pool mode", e),
+    };
+    let Ok(week) = Week::in_season(&pick.season, pick.week) else {
+        return error_response(StatusCode::BAD_REQUEST, "Semaine invalide.");
+    };
+    let matches = match get_week(data_url, &pick.season, &week).await {
+        Ok(matches) => matches,
+        Err(e) => return internal_error("fetch the matches", e),
+    };
+
+    let mut picks = match parse_picks(&body_picks(body)) {
+        Ok(picks) => picks,
+        Err(errors) => return invalid_response(errors.iter().map(|e| e.to_string()).collect()),
+    };
+    // The favorite team's game isn't on the form, it's filled in here until it
+    // kicks off, the default picks cover it after that
+    let now = Utc::now();
+    if let Some(fav) = pooler.favteam {
+        if let Some(m) = matches.iter().find(|m| m.date > now && (m.away_team == fav || m.home_team == fav)) {
+            picks.entry(m.id_event.clone()).or_insert(fav);
+        }
+    }
+
+    let ranks = body_ranks(body);
+    let ranks = (mode == PoolMode::Confidence).then_some(&ranks);
+
+    // Games that kicked off need no pick, a late pooler still gets the others
+    if let Err(errors) = validate_picks(&matches, &picks, pooler.favteam, ranks, Some(now)) {
+        return invalid_response(errors.iter().map(|e| e.to_string()).collect());
+    }
+
+    let autos = match started_default_picks(db, &pooler.poolid, &pick, &matches, pooler.favteam, &picks, now).await {
+        Ok(autos) => autos,
+        Err(e) => return internal_error("fill the started games", e),
+    };
+    let featpick = body_featpick(body);
+
+    match db.submit_picks(actor(&pooler.discordid), token, &pick, &picks, &autos, ranks, featpick).await {
+        Ok(true) => json_response(StatusCode::CREATED, json!({ "submitted": picks.len(), "defaulted": autos.len() })),
+        Ok(false) => error_response(StatusCode::CONFLICT, "Les choix de cette semaine sont déjà faits."),
+        Err(e) => internal_error("save the picks", e),
+    }
+}
+
+// Same body as a submission. Games that kicked off keep their pick and rank,
+// and so does the favorite team's game, whatever the form sent for them
+async fn edit_picks(db: &DB, data_url: &str, token: &str, body: &Value) -> ApiResult {
+    let pick = match db.validate_pick_token(token, TokenPurpose::PicksEdit).await {
+        Ok(Some(pick)) => pick,
+        Ok(None) => return error_response(StatusCode::NOT_FOUND, "Ce lien est invalide ou expiré."),
+        Err(e) => return internal_error("resolve the token", e),
+    };
+    let pooler = match db.fetch_pooler(&pick.poolerid).await {
+        Ok(pooler) => pooler,
+        Err(e) => return internal_error("fetch the pooler", e),
+    };
+    let mode = match db.fetch_pool_mode(&pooler.poolid).await {
+        Ok(mode) => mode,
+        Err(e) => return internal_error("fetch the pool mode", e),
+    };
+    let current = match db.fetch_pick(&pick.season, &pick.week, &pick.poolerid).await {
+        Ok(current) => current,
+        Err(_) => return error_response(StatusCode::NOT_FOUND, "Aucun choix à modifier pour cette semaine."),
+    };
+    let Ok(week) = Week::in_season(&pick.season, pick.week) else {
+        return error_response(StatusCode::BAD_REQUEST, "Semaine invalide.");
+    };
+    let matches = match get_week(data_url, &pick.season, &week).await {
+        Ok(matches) => matches,
+        Err(e) => return internal_error("fetch the matches", e),
+    };
+
+    let edited = match parse_picks(&body_picks(body)) {
+        Ok(picks) => picks,
+        Err(errors) => return invalid_response(errors.iter().map(|e| e.to_string()).collect()),
+    };
+
+    let now = Utc::now();
+    let open: Vec<_> = matches.iter()
+        .filter(|m| m.date > now && !pooler.favteam.is_some_and(|fav| m.away_team == fav || m.home_team == fav))
+        .map(|m| &m.id_event)
+        .collect();
+
+    let mut picks = current.picks.clone().unwrap_or_default();
+    for (matchid, team) in edited {
+        if open.contains(&&matchid) {
+            picks.insert(matchid, team);
+        }
+    }
+
+    let mut ranks = current.confidences.clone().unwrap_or_default();
+    for (matchid, rank) in body_ranks(body) {
+        if open.contains(&&matchid) {
+            ranks.insert(matchid, rank);
+        }
+    }
+    let ranks = (mode == PoolMode::Confidence).then_some(&ranks);
+
+    // The favorite team's game can't change here, it was checked on submission
+    if let Err(errors) = validate_picks(&matches, &picks, None, ranks, None) {
+        return invalid_response(errors.iter().map(|e| e.to_string()).collect());
+    }
+
+    let feature = db.fetch_feature(pick.season, pick.week).await.ok();
+    let feature_open = feature.is_some_and(|f| matches.iter().any(|m| m.id_event == f.matchid && m.date > now));
+    let featpick = match feature_open {
+        true => body_featpick(body).or(current.featpick),
+        false => current.featpick,
+    };
+
+    match db.edit_picks(actor(&pooler.discordid), token, &current, &picks, ranks, featpick).await {
+        Ok(true) => json_response(StatusCode::OK, json!({ "edited": picks.len() })),
+        Ok(false) => error_response(StatusCode::CONFLICT, "Ce lien a déjà servi."),
+        Err(e) => internal_error("save the edited picks", e),
+    }
+}
+
+// { "afc": { "winners": { "North": "BAL", ... }, "wildcards": [...], "top_seed": "KC" }, "nfc": { ... }, "champion": "KC" }
+async fn submit_capsule(db: &DB, token: &str, body: &Value) -> ApiResult {
+    let pick = match db.validate_pick_token(token, TokenPurpose::Capsule).await {
+        Ok(Some(pick)) => pick,
+        Ok(None) => return error_response(StatusCode::NOT_FOUND, "Ce lien est invalide ou expiré."),
+        Err(e) => return internal_error("resolve the token", e),
+    };
+    match db.fetch_capsule_lock(&pick.season).await {
+        Ok(Some(locked_at)) if locked_at <= Utc::now() =>
+            return error_response(StatusCode::FORBIDDEN, "Les capsules sont verrouillées pour cette saison."),
+        Ok(_) => {},
+        Err(e) => return internal_error("fetch the capsule lock", e),
+    }
+
+    let capsule = CapsuleSubmission {
+        afc: conference_capsule(body.get("afc")),
+        nfc: conference_capsule(body.get("nfc")),
+        champion: text(body.get("champion")),
+    };
+    let slots = match validate_capsule(&capsule) {
+        Ok(slots) => slots,
+        Err(errors) => return invalid_response(errors.iter().map(|e| e.to_string()).collect()),
+    };
+
+    let pooler = match db.fetch_pooler(&pick.poolerid).await {
+        Ok(pooler) => pooler,
+        Err(e) => return internal_error("fetch the pooler", e),
+    };
+    match db.submit_capsule(actor(&pooler.discordid), token, &pick, &slots).await {
+        Ok(true) => json_response(StatusCode::CREATED, json!({ "submitted": slots.len() })),
+        Ok(false) => error_response(StatusCode::CONFLICT, "La capsule de cette saison est déjà remplie."),
+        Err(e) => internal_error("save the capsule", e),
+    }
+}
+
+// Same body as a capsule. Top seeds and champion left out keep the filed
+// ones, each changed slot costs one of the pooler's repicks
+async fn repick_capsule(db: &DB, token: &str, body: &Value) -> ApiResult {
+    let pick = match db.validate_pick_token(token, TokenPurpose::CapsuleRepicks).await {
+        Ok(Some(pick)) => pick,
+        Ok(None) => return error_response(StatusCode::NOT_FOUND, "Ce lien est invalide ou expiré."),
+        Err(e) => return internal_error("resolve the token", e),
+    };
+    match db.fetch_capsule_lock(&pick.season).await {
+        Ok(Some(locked_at)) if locked_at <= Utc::now() =>
+            return error_response(StatusCode::FORBIDDEN, "Les capsules sont verrouillées pour cette saison."),
+        Ok(_) => {},
+        Err(e) => return internal_error("fetch the capsule lock", e),
+    }
+
+    let pooler = match db.fetch_pooler(&pick.poolerid).await {
+        Ok(pooler) => pooler,
+        Err(e) => return internal_error("fetch the pooler", e),
+    };
+    let current = match db.fetch_capsule_slots(&pick.poolerid, &pick.season).await {
+        Ok(current) => current,
+        Err(e) => return internal_error("fetch the capsule", e),
+    };
+    let filed = |kind: i32, conference: Option<i32>| current.iter()
+        .find(|s| s.kind == kind && conference.is_none_or(|c| s.conference == c))
+        .map(|s| s.team.code().to_owned())
+        .unwrap_or_default();
+
+    let mut capsule = CapsuleSubmission {
+        afc: conference_capsule(body.get("afc")),
+        nfc: conference_capsule(body.get("nfc")),
+        champion: text(body.get("champion")),
+    };
+    if capsule.afc.top_seed.is_empty() {
+        capsule.afc.top_seed = filed(CAPSULE_TOP_SEED, Some(Conference::AFC as i32));
+    }
+    if capsule.nfc.top_seed.is_empty() {
+        capsule.nfc.top_seed = filed(CAPSULE_TOP_SEED, Some(Conference::NFC as i32));
+    }
+    if capsule.champion.is_empty() {
+        capsule.champion = filed(CAPSULE_CHAMPION, None);
+    }
+
+    let slots = match validate_capsule(&capsule) {
+        Ok(slots) => slots,
+        Err(errors) => return invalid_response(errors.iter().map(|e| e.to_string()).collect()),
+    };
+    let changes = capsule_changes(&current, &slots);
+    if changes.is_empty() {
+        return json_response(StatusCode::OK, json!({ "repicked": 0 }));
+    }
+    if changes.len() > pooler.repicks.max(0) as usize {
+        return invalid_response(vec![format!("{} changements demandés, il t'en reste {}.", changes.len(), pooler.repicks)]);
+    }
+
+    match db.repick_capsule(actor(&pooler.discordid), token, &pick, &changes).await {
+        Ok(true) => json_response(StatusCode::OK, json!({ "repicked": changes.len() })),
+        Ok(false) => error_response(StatusCode::CONFLICT, "Ce lien a déjà servi."),
+        Err(e) => internal_error("save the repicks", e),
+    }
+}
+
+// The shape `conference_capsule` reads, built from the filed rows
+fn capsule_json(slots: &[CapsuleSlot]) -> Value {
+    let conference = |conf: Conference| {
+        let mut winners = serde_json::Map::new();
+        let mut wildcards = vec![Value::Null; 3];
+        let mut top_seed = Value::Null;
+        for s in slots.iter().filter(|s| s.conference == conf as i32) {
+            match s.kind {
+                CAPSULE_WINNER => {
+                    let div = DIVISION_KEYS.get(s.division as usize).copied().unwrap_or_default();
+                    winners.insert(div.to_owned(), json!(s.team));
+                },
+                CAPSULE_WILDCARD => if let Some(slot) = wildcards.get_mut(s.slot as usize) {
+                    *slot = json!(s.team);
+                },
+                CAPSULE_TOP_SEED => top_seed = json!(s.team),
+                _ => {},
+            }
+        }
+        json!({ "winners": winners, "wildcards": wildcards, "top_seed": top_seed })
+    };
+
+    json!({
+        "afc": conference(Conference::AFC),
+        "nfc": conference(Conference::NFC),
+        "champion": slots.iter().find(|s| s.kind == CAPSULE_CHAMPION).map(|s| s.team),
+    })
+}
+
+fn conference_capsule(value: Option<&Value>) -> ConferenceCapsule {
+    let value = value.unwrap_or(&Value::Null);
+    let winners = value.get("winners").unwrap_or(&Value::Null);
+    let wildcards = value.get("wildcards").unwrap_or(&Value::Null);
+
+    ConferenceCapsule {
+        winners: DIVISION_KEYS.map(|div| text(winners.get(div))),
+        wildcards: [0, 1, 2].map(|slot| text(wildcards.get(slot))),
+        top_seed: text(value.get("top_seed")),
+    }
+}
+
+fn body_picks(body: &Value) -> HashMap<String, String> {
+    object(body, "picks")
+        .filter_map(|(matchid, team)| Some((matchid.clone(), team.as_str()?.to_owned())))
+        .collect()
+}
+
+fn body_ranks(body: &Value) -> HashMap<String, u32> {
+    object(body, "confidences")
+        .filter_map(|(matchid, rank)| Some((matchid.clone(), u32::try_from(rank.as_u64()?).ok()?)))
+        .collect()
+}
+
+fn body_featpick(body: &Value) -> Option<u32> {
+    body.get("featpick")
+        .and_then(Value::as_u64)
+        .filter(|p| *p <= 1)
+        .map(|p| p as u32)
+}
+
+fn actor(discordid: &Option<i64>) -> Actor {
+    discordid.map_or(Actor::Bot, Actor::User)
+}
+
+fn text(value: Option<&Value>) -> String {
+    value.and_then(Value::as_str).unwrap_or_default().to_owned()
+}
+
+fn object<'a>(body: &'a Value, key: &str) -> impl Iterator<Item = (&'a String, &'a Value)> {
+    body.get(key)
+        .and_then(Value::as_object)
+        .into_iter()
+        .flatten()
+}
+
+async fn read_json(req: Request<Body>) -> Option<Value> {
+    let bytes = hyper::body::to_bytes(req.into_body()).await.ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+fn json_response(status: StatusCode, body: Value) -> ApiResult {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(body.to_string()))
+}
+
+fn error_response(status: StatusCode, message: &str) -> ApiResult {
+    json_response(status, json!({ "error": message }))
+}
+
+// Every problem at once, the page shows them all
+fn invalid_response(errors: Vec<String>) -> ApiResult {
+    json_response(StatusCode::UNPROCESSABLE_ENTITY, json!({ "error": "Soumission invalide.", "details": errors }))
+}
+
+fn internal_error(what: &str, e: anyhow::Error) -> ApiResult {
+    error!("API could not {} : {}", what, e);
+    error_response(StatusCode::INTERNAL_SERVER_ERROR, "Une erreur s'est produite.")
+}
@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use tracing::warn;
+
+use crate::database::{DB, PickToken, WeekPicks};
+use crate::football::{DefaultPicks, Match, PoolMode, Team, Week, get_week};
+use crate::validation::validate_picks;
+
+// The home team whenever the policy has nothing better to go on
+pub fn default_picks(
+    policy: &DefaultPicks,
+    matches: &[Match],
+    favteam: Option<Team>,
+    submitted: &[WeekPicks]) -> HashMap<String, Team> {
+
+    matches.iter()
+        .map(|m| {
+            let team = match (policy, favteam) {
+                (DefaultPicks::FavTeamHome, Some(fav)) if m.away_team == fav || m.home_team == fav => fav,
+                (DefaultPicks::PoolMajority, _) => majority_pick(m, submitted).unwrap_or(m.home_team),
+                _ => m.home_team,
+            };
+            (m.id_event.clone(), team)
+        })
+        .collect()
+}
+
+// Only the picks poolers made themselves count, ties are left to the caller
+fn majority_pick(game: &Match, submitted: &[WeekPicks]) -> Option<Team> {
+    let (mut away, mut home) = (0, 0);
+    for p in submitted.iter().filter(|p| !p.autos.contains(&game.id_event)) {
+        match p.picks.as_ref().and_then(|picks| picks.get(&game.id_event)) {
+            Some(team) if *team == game.away_team => away += 1,
+            Some(team) if *team == game.home_team => home += 1,
+            _ => {},
+        }
+    }
+
+    match away.cmp(&home) {
+        std::cmp::Ordering::Greater => Some(game.away_team),
+        std::cmp::Ordering::Less => Some(game.home_team),
+        std::cmp::Ordering::Equal => None,
+    }
+}
+
+// Confidence pools still need a full set of ranks, given in kickoff order
+pub fn default_ranks(matches: &[Match]) -> HashMap<String, u32> {
+    let mut ordered: Vec<_> = matches.iter().collect();
+    ordered.sort_by_key(|m| m.date);
+
+    ordered.iter()
+        .enumerate()
+        .map(|(i, m)| (m.id_event.clone(), i as u32 + 1))
+        .collect()
+}
+
+// A pooler submitting once some games kicked off gets the pool's default picks
+// for the ones left without a pick, like `fill_default_picks` gives no-shows
+pub async fn started_default_picks(
+    db: &DB,
+    poolid: &i64,
+    pick: &PickToken,
+    matches: &[Match],
+    favteam: Option<Team>,
+    picks: &HashMap<String, Team>,
+    deadline: DateTime<Utc>) -> Result<HashMap<String, Team>> {
+
+    let started: Vec<_> = matches.iter()
+        .filter(|m| m.date <= deadline && !picks.contains_key(&m.id_event))
+        .cloned()
+        .collect();
+    if started.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let policy = db.fetch_default_picks(poolid).await?;
+    if policy == DefaultPicks::None {
+        return Ok(HashMap::new());
+    }
+
+    let submitted = db.fetch_picks(poolid, &pick.season, &pick.week).await?;
+    Ok(default_picks(&policy, &started, favteam, &submitted))
+}
+
+// Picks are locked at the first kickoff of the week, anyone still without picks
+// by then gets the pool's default ones. Returns how many poolers were filled in.
+pub async fn fill_default_picks(db: &DB, data_url: &str, poolid: &i64, season: &u16, week: &Week) -> Result<usize> {
+    let policy = db.fetch_default_picks(poolid).await?;
+    if policy == DefaultPicks::None {
+        return Ok(0);
+    }
+
+    let matches = get_week(data_url, season, week).await?;
+    let locked = matches.iter()
+        .map(|m| m.date)
+        .min()
+        .is_some_and(|kickoff| kickoff <= Utc::now());
+    if !locked {
+        return Ok(0);
+    }
+
+    let submitted = db.fetch_picks(poolid, season, &week.number()).await?;
+    let missing: Vec<_> = submitted.iter().filter(|p| p.pickid.is_none()).collect();
+    if missing.is_empty() {
+        return Ok(0);
+    }
+
+    let favteams = db.fetch_pooler_favteams(poolid).await?;
+    let ranks = match db.fetch_pool_mode(poolid).await? {
+        PoolMode::Confidence => Some(default_ranks(&matches)),
+        PoolMode::Classic => None,
+    };
+
+    let mut filled = 0;
+    for pooler in missing {
+        let favteam = favteams.get(&pooler.poolerid).copied().flatten();
+        let picks = default_picks(&policy, &matches, favteam, &submitted);
+
+        // Only the favorite-team policy promises to follow the favorite-team rule
+        let forced = favteam.filter(|_| policy == DefaultPicks::FavTeamHome);
+        if let Err(errors) = validate_picks(&matches, &picks, forced, ranks.as_ref(), None) {
+            warn!("Skipping default picks for pooler {} : {:?}", pooler.poolerid, errors);
+            continue;
+        }
+
+        if db.insert_auto_picks(season, &week.number(), &pooler.poolerid, &picks, ranks.as_ref()).await? {
+            filled += 1;
+        }
+    }
+
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use chrono::TimeDelta;
+    use Team::*;
+
+    fn game(id: &str, away_team: Team, home_team: Team, hours: i64) -> Match {
+        Match {
+            id_event: id.to_owned(),
+            away_team,
+            home_team,
+            away_score: None,
+            home_score: None,
+            date: Utc::now() + TimeDelta::hours(hours),
+            away_record: None,
+            home_record: None,
+        }
+    }
+
+    fn week() -> Vec<Match> {
+        vec![game("1", Chiefs, Ravens, 5), game("2", Patriots, Bills, 2)]
+    }
+
+    fn submitted(poolerid: i64, picks: &[(&str, Team)], autos: &[&str]) -> WeekPicks {
+        WeekPicks {
+            pickid: Some(poolerid),
+            poolerid,
+            name: format!("pooler {}", poolerid),
+            week: 1,
+            picks: Some(picks.iter().map(|(id, team)| (id.to_string(), *team)).collect()),
+            counts: None,
+            confidences: None,
+            autos: autos.iter().map(|id| id.to_string()).collect::<HashSet<_>>(),
+            featpick: None,
+            cached: None,
+            featcached: None,
+            favteam: None,
+        }
+    }
+
+    fn team(picks: &HashMap<String, Team>, id: &str) -> Team {
+        picks[id]
+    }
+
+    #[test]
+    fn home_teams_by_default() {
+        let picks = default_picks(&DefaultPicks::HomeTeams, &week(), Some(Patriots), &[]);
+        assert_eq!((team(&picks, "1"), team(&picks, "2")), (Ravens, Bills));
+    }
+
+    #[test]
+    fn favorite_team_comes_first() {
+        let picks = default_picks(&DefaultPicks::FavTeamHome, &week(), Some(Patriots), &[]);
+        assert_eq!((team(&picks, "1"), team(&picks, "2")), (Ravens, Patriots));
+    }
+
+    #[test]
+    fn majority_ignores_default_picks_and_ties_go_home() {
+        let others = [
+            submitted(1, &[("1", Chiefs), ("2", Patriots)], &[]),
+            submitted(2, &[("1", Chiefs), ("2", Bills)], &[]),
+            submitted(3, &[("1", Ravens), ("2", Patriots)], &["2"]),
+        ];
+
+        let picks = default_picks(&DefaultPicks::PoolMajority, &week(), None, &others);
+        assert_eq!((team(&picks, "1"), team(&picks, "2")), (Chiefs, Bills));
+    }
+
+    #[test]
+    fn ranks_follow_kickoff_order() {
+        let ranks = default_ranks(&week());
+        assert_eq!((ranks["2"], ranks["1"]), (1, 2));
+    }
+}
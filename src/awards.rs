@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use core::fmt::Display;
+
+use crate::database::SeasonPicks;
+use crate::football::{CapsuleResults, Match, StandingsEntry, Team, Week};
+
+#[derive(Debug)]
+pub struct Award {
+    pub title: &'static str,
+    pub winners: String,
+    pub detail: String,
+}
+
+impl Display for Award {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} - {} ({})", self.title, self.winners, self.detail)
+    }
+}
+
+// Every name sharing the best value wins, ties are not broken
+fn leaders<'a, K: PartialOrd + Copy>(values: impl IntoIterator<Item = (&'a str, K)>) -> Option<(String, K)> {
+    let values: Vec<_> = values.into_iter().collect();
+    let best = values.iter().fold(None, |best, &(_, k)| match best {
+        Some(b) if b >= k => Some(b),
+        _ => Some(k),
+    })?;
+
+    let mut names: Vec<&str> = values.iter()
+        .filter(|&&(_, k)| k == best)
+        .map(|&(name, _)| name)
+        .collect();
+    names.sort_unstable();
+    Some((names.join(", "), best))
+}
+
+fn winner(m: &Match) -> Option<Team> {
+    match (m.away_score, m.home_score) {
+        (Some(0), Some(0)) => None,
+        (Some(a), Some(h)) if a > h => Some(m.away_team),
+        (Some(a), Some(h)) if h > a => Some(m.home_team),
+        _ => None,
+    }
+}
+
+pub fn calc_awards(
+    weeks: &SeasonPicks,
+    matches: &HashMap<Week, Vec<Match>>,
+    standings: &[StandingsEntry],
+    capsules: &[CapsuleResults]) -> Vec<Award> {
+
+    let mut unique_hits = HashMap::<&str, u32>::new();
+    let mut ou_hits = HashMap::<&str, u32>::new();
+    let mut blamed = HashMap::<&str, u32>::new();
+
+    for (week, feat, picks) in weeks {
+        let Some(week_matches) = matches.get(week) else {
+            continue;
+        };
+
+        for m in week_matches {
+            let winner = winner(m);
+
+            for pick in picks {
+                let (Some(pooler_picks), Some(counts)) = (&pick.picks, &pick.counts) else {
+                    continue;
+                };
+                let Some(choice) = pooler_picks.get(&m.id_event) else {
+                    continue;
+                };
+
+                match winner {
+                    Some(w) if w == *choice && counts.get(&m.id_event) == Some(&1) => {
+                        *unique_hits.entry(pick.name.as_str()).or_default() += 1;
+                    },
+                    Some(w) if w != *choice => *blamed.entry(choice.code()).or_default() += 1,
+                    _ => {},
+                }
+            }
+
+            if let Some(f) = feat.as_ref().filter(|f| f.matchid == m.id_event) {
+                let (Some(a), Some(h)) = (m.away_score, m.home_score) else {
+                    continue;
+                };
+
+                for pick in picks {
+                    let Some(featpick) = pick.featpick else {
+                        continue;
+                    };
+
+                    let hit = match featpick {
+                        1 => a + h > f.target as u64,
+                        _ => a + h > 0 && a + h <= f.target as u64,
+                    };
+                    let hits = ou_hits.entry(pick.name.as_str()).or_default();
+                    if hit { *hits += 1; }
+                }
+            }
+        }
+    }
+
+    let mut awards = Vec::<Award>::new();
+
+    if let Some((winners, hits)) = leaders(unique_hits.iter().map(|(&n, &h)| (n, h))) {
+        awards.push(Award {
+            title: "Loup solitaire",
+            winners,
+            detail: format!("{} choix uniques gagnants", hits),
+        });
+    }
+
+    if let Some((winners, hits)) = leaders(ou_hits.iter().map(|(&n, &h)| (n, h))) {
+        awards.push(Award {
+            title: "Devin des O/U",
+            winners,
+            detail: format!("{} O/U réussis", hits),
+        });
+    }
+
+    let best_week = standings.iter().flat_map(|e| e.scores.iter().copied()).max();
+    if let Some(best) = best_week.filter(|&score| score > 0) {
+        let winners: Vec<String> = standings.iter()
+            .flat_map(|e| e.scores.iter().enumerate()
+                .filter(move |&(_, &score)| score == best)
+                .map(move |(i, _)| (e.name.as_str(), i)))
+            .map(|(name, i)| format!("{} ({})", name, weeks.get(i).map_or(String::new(), |w| w.0.label())))
+            .collect();
+
+        awards.push(Award {
+            title: "Meilleure semaine",
+            winners: winners.join(", "),
+            detail: format!("{} pts", best),
+        });
+    }
+
+    let deviations = standings.iter()
+        .filter(|e| e.scores.len() > 1)
+        .map(|e| {
+            let n = e.scores.len() as f64;
+            let mean = e.scores.iter().sum::<u32>() as f64 / n;
+            let variance = e.scores.iter().map(|&s| (s as f64 - mean).powi(2)).sum::<f64>() / n;
+            (e.name.as_str(), -variance.sqrt())
+        });
+    if let Some((winners, deviation)) = leaders(deviations) {
+        awards.push(Award {
+            title: "Métronome",
+            winners,
+            detail: format!("écart-type de {:.2} pts par semaine", -deviation),
+        });
+    }
+
+    let comebacks = standings.iter().filter_map(|e| {
+        let worst = *e.ranks.iter().max()?;
+        Some((e.name.as_str(), worst - e.rank()))
+    });
+    if let Some((winners, gain)) = leaders(comebacks).filter(|&(_, gain)| gain > 0) {
+        awards.push(Award {
+            title: "Remontée de l'année",
+            winners,
+            detail: format!("+{} places depuis son pire rang", gain),
+        });
+    }
+
+    if let Some((team, losses)) = leaders(blamed.iter().map(|(&t, &l)| (t, l))) {
+        awards.push(Award {
+            title: "Équipe la plus blâmée",
+            winners: team,
+            detail: format!("{} choix perdus dans le pool", losses),
+        });
+    }
+
+    if let Some((winners, score)) = leaders(capsules.iter().map(|c| (c.name.as_str(), c.score))) {
+        awards.push(Award {
+            title: "Champion de la capsule",
+            winners,
+            detail: format!("{} pts", score),
+        });
+    }
+
+    awards
+}
@@ -0,0 +1,119 @@
+use std::collections::BTreeMap;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use anyhow::{ anyhow, Result };
+use chrono::{ DateTime, Datelike, Utc };
+use tracing::info;
+
+use crate::database::DB;
+use crate::football::{Week, WeekDates, get_calendar};
+use crate::metrics;
+
+// ESPN publishes the next season's calendar once, some time in the off-season
+const UNPUBLISHED_RECHECK: Duration = Duration::from_secs(6 * 3600);
+
+// Seasons ESPN had no calendar for yet and when it was asked, every command
+// would go back to ESPN for the next season during the off-season otherwise
+static UNPUBLISHED: Mutex<BTreeMap<u16, Instant>> = Mutex::new(BTreeMap::new());
+
+// ESPN is only asked once per season, the dates don't move afterwards.
+// Empty while the season isn't published.
+pub async fn season_calendar(db: &DB, data_url: &str, season: &u16) -> Result<Vec<WeekDates>> {
+    let cached = db.fetch_calendar(season).await?;
+    let unpublished = UNPUBLISHED.lock().unwrap_or_else(PoisonError::into_inner)
+        .get(season)
+        .is_some_and(|asked| asked.elapsed() < UNPUBLISHED_RECHECK);
+    metrics::record_cache("calendar", !cached.is_empty() || unpublished);
+    if !cached.is_empty() || unpublished {
+        return Ok(cached);
+    }
+
+    let calendar = get_calendar(data_url, season).await?;
+    if calendar.is_empty() {
+        UNPUBLISHED.lock().unwrap_or_else(PoisonError::into_inner).insert(*season, Instant::now());
+        return Ok(calendar);
+    }
+    db.cache_calendar(season, &calendar).await?;
+    info!("Cached {} weeks for season {}", calendar.len(), season);
+
+    Ok(calendar)
+}
+
+// A season runs from september to february. The next one takes over once the
+// Super Bowl is over and ESPN has published its calendar.
+pub async fn current_season(db: &DB, data_url: &str) -> Result<u16> {
+    let now = Utc::now();
+    let year = now.year() as u16;
+
+    let previous = season_calendar(db, data_url, &(year - 1)).await?;
+    if previous.last().is_some_and(|w| now <= w.end) {
+        return Ok(year - 1);
+    }
+
+    match season_calendar(db, data_url, &year).await {
+        Ok(calendar) if !calendar.is_empty() => Ok(year),
+        _ => Ok(year - 1),
+    }
+}
+
+// The week being played, or the next one between two weeks
+pub fn week_at(calendar: &[WeekDates], date: DateTime<Utc>) -> Option<Week> {
+    calendar.iter()
+        .find(|w| date <= w.end)
+        .or(calendar.last())
+        .map(|w| w.week)
+}
+
+pub async fn current_week(db: &DB, data_url: &str) -> Result<(u16, Week)> {
+    let season = current_season(db, data_url).await?;
+    let calendar = season_calendar(db, data_url, &season).await?;
+
+    let week = week_at(&calendar, Utc::now())
+        .ok_or_else(|| anyhow!("Empty calendar for season {}", season))?;
+
+    Ok((season, week))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(month: u32, day: u32) -> DateTime<Utc> {
+        let year = if month < 6 { 2025 } else { 2024 };
+        Utc.with_ymd_and_hms(year, month, day, 12, 0, 0).unwrap()
+    }
+
+    // Weeks run from wednesday to tuesday, the last two with a gap like the Pro Bowl break
+    fn calendar() -> Vec<WeekDates> {
+        vec![
+            WeekDates { week: Week::Regular(1), start: at(9, 4), end: at(9, 10) },
+            WeekDates { week: Week::Regular(2), start: at(9, 11), end: at(9, 17) },
+            WeekDates { week: Week::Conference, start: at(1, 22), end: at(1, 28) },
+            WeekDates { week: Week::SuperBowl, start: at(2, 5), end: at(2, 11) },
+        ]
+    }
+
+    #[test]
+    fn before_the_season_is_week_one() {
+        assert_eq!(week_at(&calendar(), at(8, 1)), Some(Week::Regular(1)));
+    }
+
+    #[test]
+    fn during_and_between_weeks() {
+        assert_eq!(week_at(&calendar(), at(9, 13)), Some(Week::Regular(2)));
+        assert_eq!(week_at(&calendar(), at(9, 10)), Some(Week::Regular(1)));
+        // Past the end of week 1, before the start of week 2
+        let between = Utc.with_ymd_and_hms(2024, 9, 10, 20, 0, 0).unwrap();
+        assert_eq!(week_at(&calendar(), between), Some(Week::Regular(2)));
+        assert_eq!(week_at(&calendar(), at(2, 1)), Some(Week::SuperBowl));
+    }
+
+    #[test]
+    fn playoffs_and_after() {
+        assert_eq!(week_at(&calendar(), at(1, 25)), Some(Week::Conference));
+        assert_eq!(week_at(&calendar(), at(3, 1)), Some(Week::SuperBowl));
+        assert_eq!(week_at(&[], at(9, 13)), None);
+    }
+}
@@ -0,0 +1,73 @@
+use serenity::model::application::interaction::application_command::CommandDataOption;
+use serenity::model::application::interaction::autocomplete::AutocompleteInteraction;
+use serenity::prelude::*;
+use tracing::{error, warn};
+
+use library::config::Config;
+use library::database::DB;
+use library::football::Team;
+
+// Discord refuses more suggestions than this in a single response
+const MAX_CHOICES: usize = 25;
+
+// Subcommands nest their options one level down, the focused one can be anywhere
+fn find_focused(options: &[CommandDataOption]) -> Option<&CommandDataOption> {
+    options.iter().find_map(|opt| match opt.focused {
+        true => Some(opt),
+        false => find_focused(&opt.options),
+    })
+}
+
+fn team_choices(input: &str) -> Vec<(String, String)> {
+    let input = input.trim().to_lowercase();
+
+    Team::all()
+        .filter(|t| t.code().to_lowercase().starts_with(&input)
+            || t.name().to_lowercase().contains(&input)
+            || t.aliases().iter().any(|a| a.to_lowercase().starts_with(&input)))
+        .map(|t| (format!("{} ({})", t.name(), t.code()), t.code().to_owned()))
+        .take(MAX_CHOICES)
+        .collect()
+}
+
+async fn pooler_choices(input: &str, db: &DB, poolid: &i64) -> Vec<(String, String)> {
+    let input = input.trim().to_lowercase();
+
+    match db.fetch_pooler_names(poolid).await {
+        Ok(names) => names.into_iter()
+            .filter(|name| name.to_lowercase().contains(&input))
+            .map(|name| (name.clone(), name))
+            .take(MAX_CHOICES)
+            .collect(),
+        Err(e) => {
+            error!("Could not fetch poolers for pool {} : {}", poolid, e);
+            Vec::new()
+        },
+    }
+}
+
+pub async fn run(ctx: Context, autocomplete: &AutocompleteInteraction, db: &DB, config: &Config) {
+    let Some(focused) = find_focused(&autocomplete.data.options) else {
+        return;
+    };
+    let input = focused.value.as_ref().and_then(|v| v.as_str()).unwrap_or_default();
+
+    let choices = match focused.name.as_str() {
+        "équipe" => team_choices(input),
+        "pooler" => pooler_choices(input, db, &config.pool_id).await,
+        other    => {
+            warn!("No suggestions for option '{}' of /{}", other, autocomplete.data.name);
+            Vec::new()
+        },
+    };
+
+    if let Err(reason) = autocomplete.create_autocomplete_response(&ctx.http, |res| {
+        for (name, value) in &choices {
+            res.add_string_choice(name, value);
+        }
+        res
+    })
+    .await {
+        error!("Cannot respond to autocomplete : {:?}", reason);
+    }
+}
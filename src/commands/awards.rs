@@ -0,0 +1,101 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use serenity::async_trait;
+use serenity::builder::CreateApplicationCommand;
+use serenity::model::application::interaction::InteractionResponseType;
+use serenity::model::application::interaction::application_command::ApplicationCommandInteraction;
+use serenity::model::prelude::command::CommandType;
+use serenity::prelude::*;
+use tracing::error;
+
+use library::awards::calc_awards;
+use library::calendar::current_season;
+use library::config::Config;
+use library::database::DB;
+use library::football::{PoolMode, calc_playoff_picture, calc_results, calc_standings_history, get_playoff_picture, get_week};
+
+pub struct Awards;
+
+#[async_trait]
+impl super::SlashCommand for Awards {
+    fn name(&self) -> &'static str {
+        "trophees"
+    }
+
+    fn register<'a>(&self, command: &'a mut CreateApplicationCommand) -> &'a mut CreateApplicationCommand {
+        register(command)
+    }
+
+    async fn run(&self, ctx: Context, command: &ApplicationCommandInteraction, db: &DB, config: &Config) -> Result<()> {
+        run(ctx, command, db, config).await
+    }
+}
+
+fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command
+        .description("Remet les trophées de fin de saison aux poolers")
+        .kind(CommandType::ChatInput)
+}
+
+async fn run(ctx: Context, command: &ApplicationCommandInteraction, db: &DB, config: &Config) -> Result<()> {
+    let poolid = config.pool_id;
+    let season = current_season(db, &config.data_url).await?;
+
+    if let Err(reason) = command.create_interaction_response(&ctx.http, |res| {
+        res
+            .kind(InteractionResponseType::DeferredChannelMessageWithSource)
+            .interaction_response_data(|m| m
+                .content("Calcul ...")
+            )
+    })
+    .await {
+        error!("Cannot respond to slash command : {:?}", reason);
+    }
+
+    let (weeks, _) = db.fetch_season(&poolid, &season).await?;
+
+    let mode = db.fetch_pool_mode(&poolid).await.unwrap_or(PoolMode::Classic);
+    let auto_weight = db.fetch_auto_pick_weight(&poolid).await.unwrap_or(100);
+    let mut matches = HashMap::new();
+    let mut season_results = Vec::with_capacity(weeks.len());
+    for (week, feat, picks) in &weeks {
+        let week_matches = get_week(&config.data_url, &season, week).await?;
+        let results = calc_results(week, &week_matches, picks, feat, &mode, &auto_weight).await;
+
+        for r in results.iter().filter(|r| r.cache) {
+            db.cache_results(&r.pickid.unwrap(), &r.score, &r.featscore).await?;
+        }
+        matches.insert(*week, week_matches);
+        season_results.push((*week, results));
+    }
+    let standings = calc_standings_history(&season_results, &HashMap::new());
+
+    let picture = get_playoff_picture(&config.standings_url, &config.data_url, season).await?;
+    let cap_results = if picture.reg_season_over {
+        let capsules = db.fetch_capsule(&season, &poolid).await.unwrap_or_default();
+        let repick_weight = db.fetch_repick_weight(&poolid).await.unwrap_or(100);
+        let results = calc_playoff_picture(&picture, &capsules, &repick_weight);
+
+        for r in results.iter().filter(|r| r.cache) {
+            db.cache_capsule_score(&r.poolerid, &season, &r.score).await?;
+        }
+        results
+    } else {
+        Vec::new()
+    };
+
+    let awards = calc_awards(&weeks, &matches, &standings, &cap_results);
+    let message = awards.iter().fold(format!("## Trophées de la saison {}", season), |m, award| {
+        format!("{}\n:trophy: **{}** : {} — {}", m, award.title, award.winners, award.detail)
+    });
+
+    if let Err(reason) = command.edit_original_interaction_response(&ctx.http, |res| {
+        res.content(message)
+    })
+    .await {
+        error!("Cannot respond to slash command : {:?}", reason);
+    }
+
+    Ok(())
+}
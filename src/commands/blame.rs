@@ -1,17 +1,36 @@
-use std::env;
-
+use anyhow::{anyhow, Result};
+use serenity::async_trait;
 use serenity::builder::CreateApplicationCommand;
 use serenity::model::application::interaction::InteractionResponseType;
 use serenity::model::application::interaction::application_command::ApplicationCommandInteraction;
 use serenity::model::prelude::command::{CommandOptionType, CommandType};
 use serenity::prelude::*;
+use tracing::{debug, error};
 
+use library::calendar::current_season;
+use library::config::Config;
 use library::database::DB;
-use library::football::{calc_blame, get_team_id, get_schedule};
+use library::football::{Team, calc_blame, get_schedule};
+
+pub struct Blame;
+
+#[async_trait]
+impl super::SlashCommand for Blame {
+    fn name(&self) -> &'static str {
+        "blame"
+    }
+
+    fn register<'a>(&self, command: &'a mut CreateApplicationCommand) -> &'a mut CreateApplicationCommand {
+        register(command)
+    }
 
-pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    async fn run(&self, ctx: Context, command: &ApplicationCommandInteraction, db: &DB, config: &Config) -> Result<()> {
+        run(ctx, command, db, config).await
+    }
+}
+
+fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
     command
-        .name("blame")
         .description("Blamer une équipe - Calcule la difference de points gagné/perdu par une équipe")
         .kind(CommandType::ChatInput)
         .create_option(|opt| {
@@ -20,47 +39,45 @@ pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicatio
                 .kind(CommandOptionType::String)
                 .description("L'équipe à blamer")
                 .required(true)
+                .set_autocomplete(true)
         })
 }
 
-pub async fn run(ctx: Context, command: &ApplicationCommandInteraction, db: &DB) {
-    let poolid = env::var("POOL_ID")
-        .expect("![Handler] Could not find env var 'POOL_ID'").parse::<i64>()
-        .expect("![Handler] Could not parse pool_id to int");
-    let season = env::var("CONF_SEASON")
-        .expect("[picks] Cannot find 'CONF_SEASON' in env").parse::<u16>()
-        .expect("[picks] Could not parse 'CONF_SEASON' to u16");
+async fn run(ctx: Context, command: &ApplicationCommandInteraction, db: &DB, config: &Config) -> Result<()> {
+    let poolid = config.pool_id;
+    let season = current_season(db, &config.data_url).await?;
 
-    let team = command.data.options.first().unwrap().clone().value.unwrap();
-    let teamid = get_team_id(team.as_str().unwrap());
-    if teamid == -1 {
+    let value = command.data.options.first().unwrap().clone().value.unwrap();
+    let Ok(team) = value.as_str().unwrap().parse::<Team>() else {
         if let Err(reason) = command.create_interaction_response(&ctx.http, |res| {
             res
                 .kind(InteractionResponseType::ChannelMessageWithSource)
                 .interaction_response_data(|m| m
                     .ephemeral(true)
-                    .content(format!("ERROR: Cannot blame invalid team '{}'", team))
+                    .content(format!("ERROR: Cannot blame invalid team '{}'", value))
                 )
         })
         .await {
-            println!("![picks] Cannot respond to slash command : {:?}", reason);
+            error!("Cannot respond to slash command : {:?}", reason);
         }
         // We are done here, cannot handle invalid team name
-        // Would like to create a list of possible values, but Discord caps it a 25 options
-        return;
-    }
+        // Autocomplete only suggests teams, a free-text value can still come through
+        return Ok(());
+    };
 
     let discordid = command.user.id.as_u64()
         .to_string().parse::<i64>()
         .unwrap();
-    let poolerid = db.fetch_poolerid(&discordid).await.unwrap();
+    let poolerid = db.fetch_poolerid(&discordid).await?;
 
-    let matches = get_schedule(&season, &teamid).await;
-    let (seasondata, week_count) = db.fetch_season(&poolid, &season).await.unwrap();
+    let blame_url = config.blame_url.as_deref()
+        .ok_or_else(|| anyhow!("'BLAME_URL' is not configured"))?;
+    let matches = get_schedule(blame_url, &season, &team).await?;
+    let (seasondata, week_count) = db.fetch_season(&poolid, &season).await?;
     (0..week_count).for_each(|i| {
-        println!("{:02} -- {:?}", i+1, matches[i]);
+        debug!("{:02} -- {:?}", i+1, matches[i]);
     });
-    let _blame_score = calc_blame(&seasondata[0].0, &Vec::new(), &seasondata[0].2, &poolerid, team.as_str().unwrap());
+    let _blame_score = calc_blame(&seasondata[0].0, &Vec::new(), &seasondata[0].2, &poolerid, &team);
 
     if let Err(reason) = command.create_interaction_response(&ctx.http, |res| {
         res
@@ -71,6 +88,8 @@ pub async fn run(ctx: Context, command: &ApplicationCommandInteraction, db: &DB)
             )
     })
     .await {
-        println!("![picks] Cannot respond to slash command : {:?}", reason);
+        error!("Cannot respond to slash command : {:?}", reason);
     }
+
+    Ok(())
 }
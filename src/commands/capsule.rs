@@ -1,25 +1,95 @@
-use std::env;
+use std::collections::{BTreeMap, HashMap};
 
+use chrono::Utc;
+use anyhow::{anyhow, Result};
+use serenity::async_trait;
 use serenity::builder::CreateApplicationCommand;
 use serenity::model::application::interaction::InteractionResponseType;
 use serenity::model::application::interaction::application_command::ApplicationCommandInteraction;
-use serenity::model::prelude::command::CommandType;
+use serenity::model::prelude::command::{CommandOptionType, CommandType};
 use serenity::prelude::*;
+use tracing::error;
 
-use library::database::DB;
-use library::football::{get_team_emoji, get_afc_emoji, get_nfc_emoji};
+use library::calendar::current_season;
+use library::config::Config;
+use library::database::{CapsuleChange, CapsulePicks, DB, TokenPurpose};
+use library::football::{Conference, Team, get_missing_icon};
 
-pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+const DIVISIONS: [&str; 4] = ["Nord", "Sud", "Est", "Ouest"];
+
+pub struct Capsule;
+
+#[async_trait]
+impl super::SlashCommand for Capsule {
+    fn name(&self) -> &'static str {
+        "capsule"
+    }
+
+    fn register<'a>(&self, command: &'a mut CreateApplicationCommand) -> &'a mut CreateApplicationCommand {
+        register(command)
+    }
+
+    async fn run(&self, ctx: Context, command: &ApplicationCommandInteraction, db: &DB, config: &Config) -> Result<()> {
+        run(ctx, command, db, config).await
+    }
+}
+
+fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
     command
-        .name("capsule")
         .description("Faire ses prédictions pour la capsule de l'année")
         .kind(CommandType::ChatInput)
+        .create_option(|opt| {
+            opt
+                .name("pooler")
+                .kind(CommandOptionType::String)
+                .description("Voir la capsule d'un autre pooler (après le verrouillage)")
+                .required(false)
+                .set_autocomplete(true)
+        })
+        .create_option(|opt| {
+            opt
+                .name("consensus")
+                .kind(CommandOptionType::Boolean)
+                .description("Voir combien de poolers ont choisi chaque équipe (après le verrouillage)")
+                .required(false)
+        })
 }
 
-pub async fn run(ctx: Context, command: &ApplicationCommandInteraction, db: &DB) {
-    let season = env::var("CONF_SEASON")
-        .expect("[capsule] Cannot find 'CONF_SEASON' in env").parse::<u16>()
-        .expect("[capsule] Could not parse 'CONF_SEASON' to u16");
+async fn run(ctx: Context, command: &ApplicationCommandInteraction, db: &DB, config: &Config) -> Result<()> {
+    let poolid = config.pool_id;
+    let season = current_season(db, &config.data_url).await?;
+
+    let pooler = command.data.options.iter()
+        .find(|opt| opt.name == "pooler")
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|v| v.as_str());
+    let consensus = command.data.options.iter()
+        .find(|opt| opt.name == "consensus")
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+
+    let lock = match db.fetch_capsule_lock(&season).await {
+        Ok(lock) => lock,
+        Err(e) => {
+            error!("Could not fetch capsule lock for season {}: {:?}", season, e);
+            None
+        },
+    };
+    let locked = lock.is_some_and(|l| l <= Utc::now());
+
+    if pooler.is_some() || consensus {
+        if !locked {
+            let message = match lock {
+                Some(l) => format!("Les capsules {} seront dévoilées après le verrouillage du {}.", season, l.format("%Y-%m-%d %H:%M UTC")),
+                None => format!("Aucune date de verrouillage n'est fixée pour la capsule {}.", season),
+            };
+            respond(&ctx, command, message, true).await;
+            return Ok(());
+        }
+
+        return reveal(&ctx, command, db, &season, &poolid, pooler, consensus).await;
+    }
 
     let discordid = command.user.id.as_u64()
         .to_string().parse::<i64>()
@@ -27,117 +97,210 @@ pub async fn run(ctx: Context, command: &ApplicationCommandInteraction, db: &DB)
     let poolerid = match db.fetch_poolerid(&discordid).await {
         Ok(pid) => pid,
         Err(_) => {
-            if let Err(reason) = command.create_interaction_response(&ctx.http, |res| {
-                res
-                    .kind(InteractionResponseType::ChannelMessageWithSource)
-                    .interaction_response_data(|m| m
-                        .ephemeral(true)
-                        .content("Tu n'es pas inscrit au pool.")
-                    )
-            })
-            .await {
-                println!("![picks] Cannot respond to slash command : {:?}", reason);
-            }
-            return;
+            respond(&ctx, command, "Tu n'es pas inscrit au pool.".to_string(), true).await;
+            return Ok(());
         },
     };
 
-    match db.fetch_pooler_capsule(&discordid, season).await {
-        Ok(None) => {
-            let message = match db.issue_pick_token(season, 0, poolerid).await {
-                Ok(token) => {
-                    let picks_url = env::var("PICKS_URL")
-                        .expect("![capsule] Could not find 'PICKS_URL' env var");
-                    let url = format!("{}/capsule/{}", picks_url, token);
-
-                    format!("Prêt pour les prédictions de la capsule {} à faire ici: {}", season, url)
-                },
-                Err(_) => "Une erreur s'est produite avec la commande `/capsule` .".to_string(),
-            };
+    let message = match db.fetch_pooler_capsule(&discordid, season).await {
+        Ok(None) if locked => format!("La capsule {} est verrouillée, il n'est plus possible d'y participer.", season),
+        Ok(None) => match db.issue_pick_token(super::actor(command), TokenPurpose::Capsule, season, 0, poolerid).await {
+            Ok(token) => {
+                let picks_url = &config.picks_url;
+                let url = format!("{}/capsule/{}", picks_url, token);
 
-            if let Err(reason) = command.create_interaction_response(&ctx.http, |res| {
-                res
-                    .kind(InteractionResponseType::ChannelMessageWithSource)
-                    .interaction_response_data(|m| m
-                        .ephemeral(true)
-                        .content(message)
-                    )
-            })
-            .await {
-                println!("![capsule] Cannot respond to slash command : {:?}", reason);
-            }
+                format!("Prêt pour les prédictions de la capsule {} à faire ici: {}", season, url)
+            },
+            Err(e) => return Err(e),
         },
-        Ok(Some(capsule)) if capsule.repicks > 0 => {
-            let message = match db.issue_pick_token(season, 0, poolerid).await {
-                Ok(token) => {
-                    let picks_url = env::var("PICKS_URL")
-                        .expect("![capsule] Could not find 'PICKS_URL' env var");
-                    let url = format!("{}/capsule-repicks/{}", picks_url, token);
-
-                    format!("{} changements disponible pour la capsule {season}: {url}", capsule.repicks)
-                },
-                Err(_) => "Une erreur s'est produite avec la commande `/capsule` .".to_string(),
-            };
+        Ok(Some(capsule)) if capsule.repicks > 0 && !locked => match db.issue_pick_token(super::actor(command), TokenPurpose::CapsuleRepicks, season, 0, poolerid).await {
+            Ok(token) => {
+                let picks_url = &config.picks_url;
+                let url = format!("{}/capsule-repicks/{}", picks_url, token);
 
-            if let Err(reason) = command.create_interaction_response(&ctx.http, |res| {
-                res
-                    .kind(InteractionResponseType::ChannelMessageWithSource)
-                    .interaction_response_data(|m| m
-                        .ephemeral(true)
-                        .content(message)
-                    )
-            })
-            .await {
-                println!("![capsule] Cannot respond to slash command : {:?}", reason);
-            }
+                format!("{} changements disponible pour la capsule {season}: {url}{}", capsule.repicks,
+                    history(db, &poolerid, &season).await)
+            },
+            Err(e) => return Err(e),
         },
         Ok(Some(capsule)) => {
-            let mut content = format!("**Capsule {}**\n\n", season);
-
-            content.push_str(&format!("<:AFC:{}> **Gagnants -** :", get_afc_emoji()));
-            for team in &capsule.afc_wins {
-                content.push_str(&format!(" <:{}:{}>", team, get_team_emoji(team)));
-            }
-            content.push_str(&format!("  /  **Wildcards -** :"));
-            for team in &capsule.afc_wildcards {
-                content.push_str(&format!(" <:{}:{}>", team, get_team_emoji(team)));
-            }
-
-            content.push_str(&format!("\n<:NFC:{}> **Gagnants -** :", get_nfc_emoji()));
-            for team in &capsule.nfc_wins {
-                content.push_str(&format!(" <:{}:{}>", team, get_team_emoji(team)));
-            }
-            content.push_str(&format!("  /  **Wildcards -** :"));
-            for team in &capsule.nfc_wildcards {
-                content.push_str(&format!(" <:{}:{}>", team, get_team_emoji(team)));
-            }
-
-            if let Err(reason) = command.create_interaction_response(&ctx.http, |res| {
-                res
-                    .kind(InteractionResponseType::ChannelMessageWithSource)
-                    .interaction_response_data(|m| m
-                        .ephemeral(true)
-                        .content(content)
-                    )
-            })
-            .await {
-                println!("![capsule] Cannot respond to slash command : {:?}", reason);
-            }
+            format!("**Capsule {}**\n\n{}{}", season, capsule_content(&capsule), history(db, &poolerid, &season).await)
         },
-        Err(reason) => {
-            println!("![capsule] Could not fetch capsule for season {}: {:?}", season, reason);
-
-            if let Err(reason) = command.create_interaction_response(&ctx.http, |res| {
-                res
-                    .kind(InteractionResponseType::ChannelMessageWithSource)
-                    .interaction_response_data(|m| m
-                        .ephemeral(true)
-                        .content("Une erreur s'est produite avec la commande `/capsule`.")
-                    )
-            })
-            .await {
-                println!("![capsule] Cannot respond to slash command : {:?}", reason);
-            }
+        Err(e) => return Err(anyhow!("Could not fetch capsule for season {} : {}", season, e)),
+    };
+
+    respond(&ctx, command, message, true).await;
+
+    Ok(())
+}
+
+async fn respond(ctx: &Context, command: &ApplicationCommandInteraction, content: String, ephemeral: bool) {
+    if let Err(reason) = command.create_interaction_response(&ctx.http, |res| {
+        res
+            .kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|m| m
+                .ephemeral(ephemeral)
+                .content(content)
+            )
+    })
+    .await {
+        error!("Cannot respond to slash command : {:?}", reason);
+    }
+}
+
+async fn reveal(
+    ctx: &Context,
+    command: &ApplicationCommandInteraction,
+    db: &DB,
+    season: &u16,
+    poolid: &i64,
+    pooler: Option<&str>,
+    consensus: bool) -> Result<()> {
+
+    let capsules = match db.fetch_capsule(season, poolid).await {
+        Ok(capsules) => capsules,
+        Err(e) => return Err(anyhow!("Could not fetch capsules for poolid: {}, season: {} : {}", poolid, season, e)),
+    };
+
+    if consensus {
+        // Both conferences don't fit in a single Discord message
+        let [afc, nfc] = consensus_content(&capsules);
+        respond(ctx, command, format!("**Consensus de la capsule {}** ({} poolers)\n\n{}", season, capsules.len(), afc), false).await;
+
+        if let Err(reason) = command.create_followup_message(&ctx.http, |m| m.content(nfc)).await {
+            error!("Cannot send followup message : {:?}", reason);
+        }
+        return Ok(());
+    }
+
+    let name = pooler.unwrap_or_default();
+    let message = match capsules.values().find(|c| c.name.eq_ignore_ascii_case(name)) {
+        Some(capsule) => format!("**Capsule {} de {}**\n\n{}{}", season, capsule.name,
+            capsule_content(capsule), history(db, &capsule.poolerid, season).await),
+        None => format!("Aucune capsule {} trouvée pour {}.", season, name),
+    };
+
+    respond(ctx, command, message, false).await;
+    Ok(())
+}
+
+fn capsule_content(capsule: &CapsulePicks) -> String {
+    let icon = |team: &Option<Team>| team.map_or_else(get_missing_icon, |t| t.icon());
+
+    let mut content = format!("{} **Gagnants -** :", Conference::AFC.icon());
+    for team in &capsule.afc_wins {
+        content.push_str(&format!(" {}", icon(team)));
+    }
+    content.push_str("  /  **Wildcards -** :");
+    for team in &capsule.afc_wildcards {
+        content.push_str(&format!(" {}", icon(team)));
+    }
+
+    content.push_str(&format!("\n{} **Gagnants -** :", Conference::NFC.icon()));
+    for team in &capsule.nfc_wins {
+        content.push_str(&format!(" {}", icon(team)));
+    }
+    content.push_str("  /  **Wildcards -** :");
+    for team in &capsule.nfc_wildcards {
+        content.push_str(&format!(" {}", icon(team)));
+    }
+
+    if capsule.champion.is_some() {
+        content.push_str(&format!("\n**Têtes de série #1 -** : {} {}  /  **Champion -** : {}",
+            icon(&capsule.afc_top_seed), icon(&capsule.nfc_top_seed), icon(&capsule.champion)));
+    }
+
+    content
+}
+
+// `pick_count` already holds how many poolers share a pick, so each team only needs to be seen once
+fn tally<'a>(picks: impl Iterator<Item = (&'a Option<Team>, i32)>) -> String {
+    let counts: BTreeMap<&str, (Team, i32)> = picks
+        .filter_map(|(team, count)| team.map(|t| (t.code(), (t, count))))
+        .collect();
+
+    let mut counts: Vec<_> = counts.into_values().collect();
+    counts.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+
+    counts.iter().fold(String::new(), |m, (team, count)| {
+        format!("{} {}×{}", m, team.icon(), count)
+    })
+}
+
+// A conference's picked teams alongside how many poolers share each pick
+type Slots<'a, const N: usize> = (&'a [Option<Team>; N], &'a [i32; N]);
+
+fn consensus_content(capsules: &HashMap<i64, CapsulePicks>) -> [String; 2] {
+    let conference = |header: String,
+                      wins: fn(&CapsulePicks) -> Slots<'_, 4>,
+                      wilds: fn(&CapsulePicks) -> Slots<'_, 3>,
+                      seed: fn(&CapsulePicks) -> (&Option<Team>, i32)| {
+
+        let mut content = header;
+        for (i, div) in DIVISIONS.iter().enumerate() {
+            let picks = capsules.values().map(|c| {
+                let (teams, counts) = wins(c);
+                (&teams[i], counts[i])
+            });
+            content.push_str(&format!("\n`{:<9}`{}", div, tally(picks)));
+        }
+
+        let picks = capsules.values().flat_map(|c| {
+            let (teams, counts) = wilds(c);
+            teams.iter().zip(counts.iter().copied())
+        });
+        content.push_str(&format!("\n`{:<9}`{}", "Wildcards", tally(picks)));
+        content.push_str(&format!("\n`{:<9}`{}", "#1", tally(capsules.values().map(seed))));
+        content
+    };
+
+    let afc = conference(format!("{} **AFC**", Conference::AFC.icon()),
+        |c| (&c.afc_wins, &c.afc_wins_counts),
+        |c| (&c.afc_wildcards, &c.afc_wild_counts),
+        |c| (&c.afc_top_seed, c.afc_top_seed_count));
+    let mut nfc = conference(format!("{} **NFC**", Conference::NFC.icon()),
+        |c| (&c.nfc_wins, &c.nfc_wins_counts),
+        |c| (&c.nfc_wildcards, &c.nfc_wild_counts),
+        |c| (&c.nfc_top_seed, c.nfc_top_seed_count));
+
+    nfc.push_str(&format!("\n\n:trophy: **Champion** :{}",
+        tally(capsules.values().map(|c| (&c.champion, c.champion_count)))));
+
+    [afc, nfc]
+}
+
+fn slot_label(change: &CapsuleChange) -> String {
+    let conf = match change.conference {
+        1 => "AFC",
+        _ => "NFC",
+    };
+    let div = DIVISIONS.get(change.division as usize).unwrap_or(&"?");
+
+    match change.ctype {
+        0 => format!("Gagnant {} {}", conf, div),
+        1 => format!("Wildcard {} #{}", conf, change.slot + 1),
+        2 => format!("Tête de série #1 {}", conf),
+        _ => "Champion".to_string(),
+    }
+}
+
+async fn history(db: &DB, poolerid: &i64, season: &u16) -> String {
+    let changes = match db.fetch_capsule_history(poolerid, season).await {
+        Ok(changes) => changes,
+        Err(e) => {
+            error!("Could not fetch capsule history for pooler {} : {}", poolerid, e);
+            return String::new();
         },
+    };
+
+    if changes.is_empty() {
+        return String::new();
     }
+
+    // `changed_at` is sqlite's CURRENT_TIMESTAMP, the date part is enough here
+    changes.iter().fold("\n\n**Historique des changements**".to_string(), |m, c| {
+        format!("{}\n`{}` {}: {} → {}", m,
+            c.changed_at.get(..10).unwrap_or(&c.changed_at), slot_label(c),
+            c.old_team.icon(), c.new_team.icon())
+    })
 }
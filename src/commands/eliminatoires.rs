@@ -1,28 +1,50 @@
-use std::env;
-
-use library::football;
+use anyhow::{anyhow, Result};
+use serenity::async_trait;
 use serenity::builder::CreateApplicationCommand;
 use serenity::model::application::interaction::InteractionResponseType;
 use serenity::model::application::interaction::application_command::ApplicationCommandInteraction;
-use serenity::model::prelude::command::CommandType;
+use serenity::model::prelude::command::{CommandOptionType, CommandType};
 use serenity::prelude::*;
+use tracing::{error, info};
 
+use library::calendar::current_season;
+use library::config::Config;
 use library::database::DB;
+use library::football;
+
+pub struct Eliminatoires;
+
+#[async_trait]
+impl super::SlashCommand for Eliminatoires {
+    fn name(&self) -> &'static str {
+        "eliminatoires"
+    }
+
+    fn register<'a>(&self, command: &'a mut CreateApplicationCommand) -> &'a mut CreateApplicationCommand {
+        register(command)
+    }
+
+    async fn run(&self, ctx: Context, command: &ApplicationCommandInteraction, db: &DB, config: &Config) -> Result<()> {
+        run(ctx, command, db, config).await
+    }
+}
 
-pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
     command
-        .name("eliminatoires")
         .description("Corriger les capsules de tous les poolers pour la saison en cours")
         .kind(CommandType::ChatInput)
+        .create_option(|opt| {
+            opt
+                .name("recorriger")
+                .kind(CommandOptionType::Boolean)
+                .description("(Admin) Effacer les scores sauvegardés et recorriger toutes les capsules")
+                .required(false)
+        })
 }
 
-pub async fn run(ctx: Context, command: &ApplicationCommandInteraction, db: &DB) {
-    let poolid = env::var("POOL_ID")
-        .expect("![eliminatoires] Could not find env var 'POOL_ID'").parse::<i64>()
-        .expect("![eliminatoires] Could not parse pool_id to int");
-    let season = env::var("CONF_SEASON")
-        .expect("[eliminatoires] Cannot find 'CONF_SEASON' in env").parse::<u16>()
-        .expect("[eliminatoires] Could not parse 'CONF_SEASON' to u16");
+async fn run(ctx: Context, command: &ApplicationCommandInteraction, db: &DB, config: &Config) -> Result<()> {
+    let poolid = config.pool_id;
+    let season = current_season(db, &config.data_url).await?;
 
     if let Err(reason) = command.create_interaction_response(&ctx.http, |res| {
         res
@@ -31,32 +53,62 @@ pub async fn run(ctx: Context, command: &ApplicationCommandInteraction, db: &DB)
                 .content(format!("### Capsule {} — Correction\n", season).as_str())
             )
     }).await {
-        println!("![eliminatoires] Cannot respond to slash command : {:?}", reason);
+        error!("Cannot respond to slash command : {:?}", reason);
     }
 
-    let capsules = match db.fetch_capsule(&season, &poolid).await {
-        Ok(c) => c,
-        Err(e) => {
-            println!("![eliminatoires] Could not fetch capsules for poolid: {}, season: {}\nerror: {}", poolid, season, e);
+    let regrade = command.data.options.iter()
+        .find(|opt| opt.name == "recorriger")
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+
+    if regrade {
+        let is_admin = command.member.as_ref()
+            .and_then(|m| m.permissions)
+            .is_some_and(|p| p.administrator());
+
+        if !is_admin {
             if let Err(reason) = command.edit_original_interaction_response(&ctx.http, |res| {
-                res.content("Une erreur s'est produite lors de la récupération des capsules.")
+                res.content("Seul un administrateur peut recorriger les capsules.")
             }).await {
-                println!("![eliminatoires] Cannot edit interaction response : {:?}", reason);
+                error!("Cannot edit interaction response : {:?}", reason);
             }
-            return;
+            return Ok(());
+        }
+
+        match db.clear_capsule_scores(super::actor(command), &season, &poolid).await {
+            Ok(count) => info!("Cleared {} capsule scores for season {}", count, season),
+            Err(e) => return Err(anyhow!("Could not clear capsule scores for season {} : {}", season, e)),
         }
+    }
+
+    let capsules = match db.fetch_capsule(&season, &poolid).await {
+        Ok(c) => c,
+        Err(e) => return Err(anyhow!("Could not fetch capsules for poolid: {}, season: {} : {}", poolid, season, e)),
     };
 
-    let picture = football::get_playoff_picture(season).await;
-    let results = football::calc_playoff_picture(&picture, &capsules);
+    let repick_weight = db.fetch_repick_weight(&poolid).await.unwrap_or(100);
+    let picture = football::get_playoff_picture(&config.standings_url, &config.data_url, season).await?;
+    let results = football::calc_playoff_picture(&picture, &capsules, &repick_weight);
+    for r in results.iter().filter(|r| r.cache) {
+        db.cache_capsule_score(&r.poolerid, &season, &r.score).await?;
+    }
 
     if results.is_empty() {
         if let Err(message) = command.channel_id.send_message(&ctx.http, |res| {
             res.content("Aucune capsule trouvée.")
         }).await {
-            println!("![eliminatoires] Cannot respond to interaction : {:?}", message);
+            error!("Cannot respond to interaction : {:?}", message);
+        }
+        return Ok(());
+    }
+
+    if results.iter().any(|r| r.provisional) {
+        if let Err(message) = command.channel_id.send_message(&ctx.http, |res| {
+            res.content("*Projection provisoire selon le classement actuel* — :white_check_mark: qualifié, :hourglass: dans la course, :x: éliminé")
+        }).await {
+            error!("Cannot respond to interaction : {:?}", message);
         }
-        return;
     }
 
     for (i, r) in results.iter().enumerate() {
@@ -65,7 +117,9 @@ pub async fn run(ctx: Context, command: &ApplicationCommandInteraction, db: &DB)
         if let Err(message) = command.channel_id.send_message(&ctx.http, |res| {
             res.content(format!("`#{:<2} {}{} {:>3}pts` {}\n", i+1, r.name, pad, r.score, r.icons).as_str())
         }).await {
-            println!("![eliminatoires] Cannot respond to interaction : {:?}", message);
+            error!("Cannot respond to interaction : {:?}", message);
         }
     }
+
+    Ok(())
 }
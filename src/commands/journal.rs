@@ -0,0 +1,131 @@
+use anyhow::{anyhow, Result};
+use serenity::async_trait;
+use serenity::builder::CreateApplicationCommand;
+use serenity::model::Permissions;
+use serenity::model::application::interaction::InteractionResponseType;
+use serenity::model::application::interaction::application_command::ApplicationCommandInteraction;
+use serenity::model::prelude::command::{CommandOptionType, CommandType};
+use serenity::prelude::*;
+use tracing::error;
+
+use library::config::Config;
+use library::database::{AuditAction, AuditEntry, DB};
+
+// Discord refuses longer messages
+const MAX_MESSAGE_LEN: usize = 2000;
+const DEFAULT_ENTRIES: i64 = 20;
+const MAX_ENTRIES: i64 = 50;
+
+pub struct Journal;
+
+#[async_trait]
+impl super::SlashCommand for Journal {
+    fn name(&self) -> &'static str {
+        "journal"
+    }
+
+    fn register<'a>(&self, command: &'a mut CreateApplicationCommand) -> &'a mut CreateApplicationCommand {
+        register(command)
+    }
+
+    fn permissions(&self) -> Option<Permissions> {
+        Some(Permissions::ADMINISTRATOR)
+    }
+
+    async fn run(&self, ctx: Context, command: &ApplicationCommandInteraction, db: &DB, _config: &Config) -> Result<()> {
+        run(ctx, command, db).await
+    }
+}
+
+fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command
+        .description("Consulter le journal des modifications faites par le bot")
+        .kind(CommandType::ChatInput)
+        .create_option(|opt| {
+            opt
+                .name("action")
+                .kind(CommandOptionType::String)
+                .description("Seulement ce type de modification");
+
+            for action in AuditAction::all() {
+                opt.add_string_choice(action.as_str(), action.as_str());
+            }
+            opt
+        })
+        .create_option(|opt| {
+            opt
+                .name("membre")
+                .kind(CommandOptionType::User)
+                .description("Seulement les modifications faites par ce membre")
+        })
+        .create_option(|opt| {
+            opt
+                .name("nombre")
+                .kind(CommandOptionType::Integer)
+                .description("Nombre d'entrées à afficher (20 par défaut)")
+                .min_int_value(1)
+                .max_int_value(MAX_ENTRIES)
+        })
+}
+
+async fn run(ctx: Context, command: &ApplicationCommandInteraction, db: &DB) -> Result<()> {
+    let option = |name: &str| command.data.options.iter()
+        .find(|o| o.name == name)
+        .and_then(|o| o.value.as_ref());
+
+    let action = option("action").and_then(|v| v.as_str());
+    // User options come in as the id, as a string
+    let actor = option("membre")
+        .and_then(|v| v.as_str())
+        .and_then(|v| v.parse::<i64>().ok());
+    let limit = option("nombre")
+        .and_then(|v| v.as_i64())
+        .unwrap_or(DEFAULT_ENTRIES)
+        .clamp(1, MAX_ENTRIES);
+
+    let message = match db.fetch_audit_log(action, actor, limit).await {
+        Ok(entries) if entries.is_empty() => "Aucune entrée dans le journal.".to_string(),
+        Ok(entries) => journal_message(&entries),
+        Err(e) => return Err(anyhow!("Could not fetch the audit log : {}", e)),
+    };
+
+    if let Err(reason) = command.create_interaction_response(&ctx.http, |res| {
+        res
+            .kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|m| m
+                .ephemeral(true)
+                .content(message)
+            )
+    })
+    .await {
+        error!("Cannot respond to slash command : {:?}", reason);
+    }
+
+    Ok(())
+}
+
+fn journal_message(entries: &[AuditEntry]) -> String {
+    let mut message = format!("## Journal — {} dernière(s) entrée(s)", entries.len());
+
+    for e in entries {
+        let actor = match e.actor {
+            Some(id) => format!("<@{}>", id),
+            None => "bot".to_string(),
+        };
+        let change = match (&e.old_value, &e.new_value) {
+            (Some(old), Some(new)) => format!("{} → {}", old, new),
+            (None, Some(new)) => new.clone(),
+            (Some(old), None) => format!("~~{}~~", old),
+            (None, None) => String::new(),
+        };
+
+        let line = format!("\n`{}` {} **{}** {} {}", e.created_at, actor, e.action, e.target, change);
+        // Newest first, the oldest ones are the ones left out
+        if message.len() + line.len() > MAX_MESSAGE_LEN {
+            break;
+        }
+        message.push_str(&line);
+    }
+
+    message
+}
@@ -1,112 +1,93 @@
-use std::env;
-
-use serde_json::Value;
-
+use anyhow::Result;
+use serenity::async_trait;
 use serenity::builder::CreateApplicationCommand;
-use serenity::model::prelude::command::{ CommandType, CommandOptionType };
+use serenity::model::prelude::command::CommandType;
 use serenity::model::prelude::interaction::InteractionResponseType;
 use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
 use serenity::prelude::*;
+use tracing::error;
 
+use library::config::Config;
 use library::database::DB;
-use library::football::{ get_week, get_team_emoji };
+use library::calendar::current_week;
+use library::football::get_week;
 
 const VS_EMOJI: &str = "<:VS:1144451849571794997>";
 
-pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+pub struct Matches;
+
+#[async_trait]
+impl super::SlashCommand for Matches {
+    fn name(&self) -> &'static str {
+        "semaine"
+    }
+
+    fn register<'a>(&self, command: &'a mut CreateApplicationCommand) -> &'a mut CreateApplicationCommand {
+        register(command)
+    }
+
+    async fn run(&self, ctx: Context, command: &ApplicationCommandInteraction, db: &DB, config: &Config) -> Result<()> {
+        run(ctx, command, db, config).await
+    }
+}
+
+fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
     command
-        .name("semaine")
         .description("Montre tous les matches d'une semaine")
         .kind(CommandType::ChatInput)
-        .create_option(|opt| {
-            opt
-                .name("semaine")
-                .kind(CommandOptionType::String)
-                .description("La semaine choisie")
-                .required(true)
-                .add_string_choice("semaine 1", 1)
-                .add_string_choice("semaine 2", 2)
-                .add_string_choice("semaine 3", 3)
-                .add_string_choice("semaine 4", 4)
-                .add_string_choice("semaine 5", 5)
-                .add_string_choice("semaine 6", 6)
-                .add_string_choice("semaine 7", 7)
-                .add_string_choice("semaine 8", 8)
-                .add_string_choice("semaine 9", 9)
-                .add_string_choice("semaine 10", 10)
-                .add_string_choice("semaine 11", 11)
-                .add_string_choice("semaine 12", 12)
-                .add_string_choice("semaine 13", 13)
-                .add_string_choice("semaine 14", 14)
-                .add_string_choice("semaine 15", 15)
-                .add_string_choice("semaine 16", 16)
-                .add_string_choice("semaine 17", 17)
-                .add_string_choice("semaine 18", 18)
-                .add_string_choice("WildCards", 19)
-                .add_string_choice("Divisional", 20)
-                .add_string_choice("Championship", 21)
-                .add_string_choice("Super Bowl", 22)
-        })
+        .create_option(|opt| super::week_option(opt, false))
 }
 
-pub async fn run(ctx: Context, command: &ApplicationCommandInteraction, db: &DB) {
-    if let Value::String(str) = command.data.options.get(0)
-        .expect("![Week] Could not fetch week arg")
-        .value.as_ref()
-        .expect("![Week] Could not get value of the week arg")
-    {
-        if let Err(reason) = command.create_interaction_response(&ctx.http, |res| {
-            res
-                .kind(InteractionResponseType::DeferredChannelMessageWithSource)
-                .interaction_response_data(|m|
-                    m.content("Calcul ...")
-                )
-        })
-        .await {
-            println!("![week] Cannot respond to slash command : {:?}", reason);
-        }
+async fn run(ctx: Context, command: &ApplicationCommandInteraction, db: &DB, config: &Config) -> Result<()> {
+    if let Err(reason) = command.create_interaction_response(&ctx.http, |res| {
+        res
+            .kind(InteractionResponseType::DeferredChannelMessageWithSource)
+            .interaction_response_data(|m|
+                m.content("Calcul ...")
+            )
+    })
+    .await {
+        error!("Cannot respond to slash command : {:?}", reason);
+    }
 
-        let season = env::var("CONF_SEASON")
-            .expect("![Week] Could not find 'CONF_SEASON' env var")
-            .parse::<u16>()
-            .expect("![Week] Could not parse 'CONF_SEASON' to int");
-        let week = str.parse::<i64>()
-            .expect("![Week] Could not parse week arg to u64");
-        let matches = get_week(&season, &week).await;
+    let (season, current) = current_week(db, &config.data_url).await?;
+    let week = super::week_arg(&command.data.options).unwrap_or(current);
+    let matches = get_week(&config.data_url, &season, &week).await?;
 
-        let feature_id = if let Ok(feature) = db.fetch_feature(season, week).await {
-            feature.matchid
-        } else {
-            String::new()
-        };
+    let feature_id = if let Ok(feature) = db.fetch_feature(season, week.number()).await {
+        feature.matchid
+    } else {
+        String::new()
+    };
 
-        let output = matches.into_iter().fold(String::new(), |mut out, m| {
-            let aemoji = get_team_emoji(m.away_team.as_str());
-            let hemoji = get_team_emoji(m.home_team.as_str());
+    let output = matches.into_iter().fold(format!("### {}, {}\n", week, season), |mut out, m| {
+        let aicon = m.away_team.icon();
+        let hicon = m.home_team.icon();
 
-            let (ascore, hscore, aline, hline) = if let (Some(a), Some(h)) = (m.away_score, m.home_score) {
-                (a.to_string(), h.to_string(), a > h, h > a)
-            }
-            else {
-                ("--".to_string(), "--".to_string(), false, false)
-            };
+        let (ascore, hscore, aline, hline) = if let (Some(a), Some(h)) = (m.away_score, m.home_score) {
+            (a.to_string(), h.to_string(), a > h, h > a)
+        }
+        else {
+            ("--".to_string(), "--".to_string(), false, false)
+        };
 
-            out.push_str(format!("<:{}:{}> {} {} {} <:{}:{}> {}\n",
-                m.away_team, aemoji,
-                if aline { format!("__`{:02}`__", ascore) } else { format!("`{:02}`", ascore) },
-                VS_EMOJI,
-                if hline { format!("__`{:02}`__", hscore) } else { format!("`{:02}`", hscore) },
-                m.home_team, hemoji,
-                if feature_id == m.id_event { "<--" } else { "" }
-            ).as_str());
-            out
-        });
+        out.push_str(format!("{} {} {} {} {} {}\n",
+            aicon,
+            if aline { format!("__`{:02}`__", ascore) } else { format!("`{:02}`", ascore) },
+            VS_EMOJI,
+            if hline { format!("__`{:02}`__", hscore) } else { format!("`{:02}`", hscore) },
+            hicon,
+            if feature_id == m.id_event { "<--" } else { "" }
+        ).as_str());
+        out
+    });
 
-        if let Err(reason) = command.edit_original_interaction_response(&ctx.http, |res| {
-            res.content(output)
-        })
-        .await {
-            println!("![week] Cannot respond to slash command : {:?}", reason);
-        }
+    if let Err(reason) = command.edit_original_interaction_response(&ctx.http, |res| {
+        res.content(output)
+    })
+    .await {
+        error!("Cannot respond to slash command : {:?}", reason);
     }
+
+    Ok(())
 }
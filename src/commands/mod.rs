@@ -9,3 +9,113 @@ pub mod blame;
 pub mod features;
 pub mod capsule;
 pub mod eliminatoires;
+pub mod rank;
+pub mod awards;
+pub mod survivor;
+pub mod journal;
+pub mod autocomplete;
+
+use anyhow::Result;
+use serenity::async_trait;
+use serenity::builder::{CreateApplicationCommand, CreateApplicationCommandOption};
+use serenity::model::Permissions;
+use serenity::model::application::interaction::application_command::{ApplicationCommandInteraction, CommandDataOption};
+use serenity::model::prelude::command::CommandOptionType;
+use serenity::prelude::*;
+use tracing::warn;
+
+use library::config::Config;
+use library::database::{Actor, DB};
+use library::football::Week;
+
+#[async_trait]
+pub trait SlashCommand: Send + Sync {
+    // Also the key used by `ENABLE_COMMANDS` / `DISABLE_COMMANDS`
+    fn name(&self) -> &'static str;
+
+    // Everything but the name and the permissions, see `create`
+    fn register<'a>(&self, command: &'a mut CreateApplicationCommand) -> &'a mut CreateApplicationCommand;
+
+    // Members without them don't see the command at all, server admins can still override it
+    fn permissions(&self) -> Option<Permissions> {
+        None
+    }
+
+    fn enabled_by_default(&self) -> bool {
+        true
+    }
+
+    // An error is logged and answered by the dispatcher, whatever was already sent gets replaced
+    async fn run(&self, ctx: Context, command: &ApplicationCommandInteraction, db: &DB, config: &Config) -> Result<()>;
+}
+
+pub fn create<'a>(cmd: &dyn SlashCommand, builder: &'a mut CreateApplicationCommand) -> &'a mut CreateApplicationCommand {
+    builder.name(cmd.name());
+    if let Some(permissions) = cmd.permissions() {
+        builder.default_member_permissions(permissions);
+    }
+    cmd.register(builder)
+}
+
+// Registration and dispatch both go through this list, a command left out of it doesn't exist for Discord
+pub fn registry(config: &Config) -> Vec<Box<dyn SlashCommand>> {
+    let all: Vec<Box<dyn SlashCommand>> = vec![
+        Box::new(matches::Matches),
+        Box::new(picks::Picks),
+        Box::new(ping::Ping),
+        Box::new(results::Results),
+        Box::new(season::Season),
+        Box::new(stats::Stats),
+        Box::new(team::FavoriteTeam),
+        Box::new(blame::Blame),
+        Box::new(features::Features),
+        Box::new(capsule::Capsule),
+        Box::new(eliminatoires::Eliminatoires),
+        Box::new(rank::Rank),
+        Box::new(awards::Awards),
+        Box::new(survivor::Survivor),
+        Box::new(journal::Journal),
+    ];
+
+    for name in config.enable_commands.iter().chain(&config.disable_commands) {
+        if !all.iter().any(|c| c.name() == name) {
+            warn!("Unknown command '{}' in ENABLE_COMMANDS / DISABLE_COMMANDS", name);
+        }
+    }
+
+    all.into_iter()
+        .filter(|c| {
+            let name = c.name().to_owned();
+            config.enable_commands.contains(&name)
+                || (c.enabled_by_default() && !config.disable_commands.contains(&name))
+        })
+        .collect()
+}
+
+// Every command taking a week registers the same option, the choices come from `Week`.
+// When it isn't required, the command falls back on the current week.
+pub fn week_option(opt: &mut CreateApplicationCommandOption, required: bool) -> &mut CreateApplicationCommandOption {
+    opt
+        .name("semaine")
+        .kind(CommandOptionType::String)
+        .description(if required { "La semaine choisie" } else { "La semaine choisie (par défaut, la semaine courante)" })
+        .required(required);
+
+    for week in Week::all() {
+        opt.add_string_choice(week.label(), week.number());
+    }
+    opt
+}
+
+// Discord ids fit in an i64 until 2084, the DB stores them that way
+pub fn actor(command: &ApplicationCommandInteraction) -> Actor {
+    Actor::User(*command.user.id.as_u64() as i64)
+}
+
+pub fn week_arg(options: &[CommandDataOption]) -> Option<Week> {
+    options.iter()
+        .find(|o| o.name == "semaine")
+        .and_then(|o| o.value.as_ref())
+        .and_then(|v| v.as_str())
+        .and_then(|v| v.parse::<Week>().ok())
+}
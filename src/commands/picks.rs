@@ -1,58 +1,47 @@
-use std::env;
-
+use chrono::Utc;
+use anyhow::Result;
+use serenity::async_trait;
 use serenity::builder::CreateApplicationCommand;
 use serenity::model::application::interaction::InteractionResponseType;
 use serenity::model::application::interaction::application_command::ApplicationCommandInteraction;
-use serenity::model::prelude::command::{CommandOptionType, CommandType};
+use serenity::model::prelude::command::CommandType;
 use serenity::prelude::*;
+use tracing::error;
+
+use library::calendar::current_week;
+use library::config::Config;
+use library::database::{DB, TokenPurpose};
+use library::football::{PoolMode, Team, get_week, get_missing_icon};
+use library::validation::validate_week_picks;
+
+pub struct Picks;
+
+#[async_trait]
+impl super::SlashCommand for Picks {
+    fn name(&self) -> &'static str {
+        "choix"
+    }
 
-use library::database::DB;
-use library::football::{get_week, get_team_emoji};
+    fn register<'a>(&self, command: &'a mut CreateApplicationCommand) -> &'a mut CreateApplicationCommand {
+        register(command)
+    }
 
-pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    async fn run(&self, ctx: Context, command: &ApplicationCommandInteraction, db: &DB, config: &Config) -> Result<()> {
+        run(ctx, command, db, config).await
+    }
+}
+
+fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
     command
-        .name("choix")
         .description("Faire ses choix pour une semaine de la saison courante")
         .kind(CommandType::ChatInput)
-        .create_option(|opt| {
-            opt
-                .name("semaine")
-                .kind(CommandOptionType::String)
-                .description("La semaine choisie")
-                .required(true)
-                .add_string_choice("semaine 1", 1)
-                .add_string_choice("semaine 2", 2)
-                .add_string_choice("semaine 3", 3)
-                .add_string_choice("semaine 4", 4)
-                .add_string_choice("semaine 5", 5)
-                .add_string_choice("semaine 6", 6)
-                .add_string_choice("semaine 7", 7)
-                .add_string_choice("semaine 8", 8)
-                .add_string_choice("semaine 9", 9)
-                .add_string_choice("semaine 10", 10)
-                .add_string_choice("semaine 11", 11)
-                .add_string_choice("semaine 12", 12)
-                .add_string_choice("semaine 13", 13)
-                .add_string_choice("semaine 14", 14)
-                .add_string_choice("semaine 15", 15)
-                .add_string_choice("semaine 16", 16)
-                .add_string_choice("semaine 17", 17)
-                .add_string_choice("semaine 18", 18)
-                .add_string_choice("WildCards", 19)
-                .add_string_choice("Divisional", 20)
-                .add_string_choice("Championship", 21)
-                .add_string_choice("Super Bowl", 22)
-        })
+        .create_option(|opt| super::week_option(opt, false))
 }
 
-pub async fn run(ctx: Context, command: &ApplicationCommandInteraction, db: &DB) {
-    let season = env::var("CONF_SEASON")
-        .expect("[picks] Cannot find 'CONF_SEASON' in env").parse::<u16>()
-        .expect("[picks] Could not parse 'CONF_SEASON' to u16");
-    let week = command.data.options.first()
-        .expect("[picks] No week arg given with the command").value.as_ref()
-        .unwrap().as_str().unwrap().parse::<i64>()
-        .expect("[picks] Could not parse week arg to u64");
+async fn run(ctx: Context, command: &ApplicationCommandInteraction, db: &DB, config: &Config) -> Result<()> {
+    let poolid = config.pool_id;
+    let (season, current) = current_week(db, &config.data_url).await?;
+    let week = super::week_arg(&command.data.options).unwrap_or(current);
 
     let discordid = command.user.id.as_u64()
         .to_string().parse::<i64>()
@@ -69,36 +58,45 @@ pub async fn run(ctx: Context, command: &ApplicationCommandInteraction, db: &DB)
                     )
             })
             .await {
-                println!("![picks] Cannot respond to slash command : {:?}", reason);
+                error!("Cannot respond to slash command : {:?}", reason);
             }
-            return;
+            return Ok(());
         },
     };
 
-    let message = match db.fetch_pick(&season, &week, &poolerid).await {
+    let message = match db.fetch_pick(&season, &week.number(), &poolerid).await {
         Ok(p) => {
-            let picks = p.picks.unwrap();
-            let feature = db.fetch_feature(season, week).await.ok();
+            let feature = db.fetch_feature(season, week.number()).await.ok();
+            let mode = db.fetch_pool_mode(&poolid).await.unwrap_or(PoolMode::Classic);
+            let matches = get_week(&config.data_url, &season, &week).await?;
+
+            let ranks = match (mode, &p.confidences) {
+                (PoolMode::Confidence, Some(ranks)) => Some(ranks),
+                _ => None,
+            };
+            // A stray entry is shown rather than trusted
+            let errors = validate_week_picks(&matches, &p, &mode).err().unwrap_or_default();
+            let picks = p.picks.clone().unwrap_or_default();
 
-            let (icons, feat_str) = get_week(&season, &week).await
-                .into_iter()
+            let (icons, feat_str) = matches.iter()
                 .fold((String::new(), String::new()), |(mut icons, mut feat_str), m| {
-                    let team = picks.get(&m.id_event).unwrap();
-                    let emoji = get_team_emoji(team);
+                    let icon = picks.get(&m.id_event)
+                        .map_or_else(get_missing_icon, Team::icon);
 
-                    icons.push_str(format!("<:{}:{}> ", team, emoji).as_str());
+                    match ranks.and_then(|ranks| ranks.get(&m.id_event)) {
+                        Some(rank) => icons.push_str(format!("{}`{}` ", icon, rank).as_str()),
+                        None => icons.push_str(format!("{} ", icon).as_str()),
+                    }
 
                     if let Some(ref feat) = feature {
                         if feat.matchid == m.id_event {
-                            let away_emoji = get_team_emoji(&m.away_team);
-                            let home_emoji = get_team_emoji(&m.home_team);
                             let trend = match p.featpick {
                                 Some(1) => ":chart_with_upwards_trend:",
                                 _ => ":chart_with_downwards_trend:",
                             };
                             feat_str = format!(
-                                "<:{}:{}> @ <:{}:{}> {}",
-                                m.away_team, away_emoji, m.home_team, home_emoji, trend
+                                "{} @ {} {}",
+                                m.away_team.icon(), m.home_team.icon(), trend
                             );
                         }
                     }
@@ -106,19 +104,36 @@ pub async fn run(ctx: Context, command: &ApplicationCommandInteraction, db: &DB)
                     (icons, feat_str)
                 });
 
-            if feat_str.is_empty() {
-                format!("## Choix pour la semaine {}, {}\n{}", week, season, icons)
+            let warning = errors.iter()
+                .fold(String::new(), |w, e| format!("{}\n:warning: {}", w, e));
+
+            let summary = if feat_str.is_empty() {
+                format!("## Choix — {}, {}\n{}{}", week, season, icons, warning)
             } else {
-                format!("## Choix pour la semaine {}, {}\n{}\n**Feature:** {}", week, season, icons, feat_str)
+                format!("## Choix — {}, {}\n{}\n**Feature:** {}{}", week, season, icons, feat_str, warning)
+            };
+
+            // Picks stay editable until their game kicks off
+            let now = Utc::now();
+            if !matches.iter().any(|m| m.date > now) {
+                summary
+            } else {
+                match db.issue_pick_token(super::actor(command), TokenPurpose::PicksEdit, season, week.number(), poolerid).await {
+                    Ok(token) => format!("{}\nModifier les choix des matchs pas encore commencés: {}/edit/{}",
+                        summary, config.picks_url, token),
+                    Err(e) => {
+                        error!("Could not issue an edit token for pooler {} : {}", poolerid, e);
+                        summary
+                    },
+                }
             }
         },
-        Err(_) => match db.issue_pick_token(season, week, poolerid).await {
+        Err(_) => match db.issue_pick_token(super::actor(command), TokenPurpose::Picks, season, week.number(), poolerid).await {
             Ok(token) => {
-                let picks_url = env::var("PICKS_URL").expect("![Picks] Could not find 'PICKS_URL' env var");
-                let week_name = command.data.options.first().unwrap().name.as_str();
-                format!("Prêt pour les choix de la {} à faire ici: {}/{}", week_name, picks_url, token)
+                let picks_url = &config.picks_url;
+                format!("Prêt pour les choix ({}) à faire ici: {}/{}", week, picks_url, token)
             },
-            Err(_) => "Une erreur s'est produite avec la commande `/choix` .".to_string(),
+            Err(e) => return Err(e),
         },
     };
 
@@ -131,6 +146,8 @@ pub async fn run(ctx: Context, command: &ApplicationCommandInteraction, db: &DB)
             )
     })
     .await {
-        println!("![picks] Cannot respond to slash command : {:?}", reason);
+        error!("Cannot respond to slash command : {:?}", reason);
     }
+
+    Ok(())
 }
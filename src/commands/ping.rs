@@ -1,23 +1,44 @@
-//use serenity::builder::CreateApplicationCommand;
-//use serenity::model::application::interaction::InteractionResponseType;
-//use serenity::model::application::interaction::application_command::ApplicationCommandInteraction;
-//use serenity::prelude::*;
-//
-//pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
-//    command
-//        .name("ping")
-//        .description("A ping command")
-//}
-//
-//pub async fn run(ctx: Context, command: &ApplicationCommandInteraction) {
-//    if let Err(reason) = command.create_interaction_response(&ctx.http, |res| {
-//        res
-//            .kind(InteractionResponseType::ChannelMessageWithSource)
-//            .interaction_response_data(|m| m
-//                .content("Ping response!")
-//            )
-//    })
-//    .await {
-//        println!("![ping] Cannot respond to slash command : {:?}", reason);
-//    }
-//}
+use anyhow::Result;
+use serenity::async_trait;
+use serenity::builder::CreateApplicationCommand;
+use serenity::model::application::interaction::InteractionResponseType;
+use serenity::model::application::interaction::application_command::ApplicationCommandInteraction;
+use serenity::prelude::*;
+use tracing::error;
+
+use library::config::Config;
+use library::database::DB;
+
+pub struct Ping;
+
+#[async_trait]
+impl super::SlashCommand for Ping {
+    fn name(&self) -> &'static str {
+        "ping"
+    }
+
+    fn register<'a>(&self, command: &'a mut CreateApplicationCommand) -> &'a mut CreateApplicationCommand {
+        command
+            .description("Vérifie que le bot répond")
+    }
+
+    // Only useful while debugging, turned on with `ENABLE_COMMANDS=ping`
+    fn enabled_by_default(&self) -> bool {
+        false
+    }
+
+    async fn run(&self, ctx: Context, command: &ApplicationCommandInteraction, _db: &DB, _config: &Config) -> Result<()> {
+        if let Err(reason) = command.create_interaction_response(&ctx.http, |res| {
+            res
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|m| m
+                    .ephemeral(true)
+                    .content("Pong!")
+                )
+        })
+        .await {
+            error!("Cannot respond to slash command : {:?}", reason);
+        }
+        Ok(())
+    }
+}
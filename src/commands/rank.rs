@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use serenity::async_trait;
+use serenity::builder::CreateApplicationCommand;
+use serenity::model::application::interaction::InteractionResponseType;
+use serenity::model::application::interaction::application_command::ApplicationCommandInteraction;
+use serenity::model::prelude::command::{CommandOptionType, CommandType};
+use serenity::prelude::*;
+use tracing::error;
+
+use library::calendar::current_season;
+use library::config::Config;
+use library::database::DB;
+use library::football::{PoolMode, calc_season, calc_standings_history, get_movement_icon};
+
+pub struct Rank;
+
+#[async_trait]
+impl super::SlashCommand for Rank {
+    fn name(&self) -> &'static str {
+        "rang"
+    }
+
+    fn register<'a>(&self, command: &'a mut CreateApplicationCommand) -> &'a mut CreateApplicationCommand {
+        register(command)
+    }
+
+    async fn run(&self, ctx: Context, command: &ApplicationCommandInteraction, db: &DB, config: &Config) -> Result<()> {
+        run(ctx, command, db, config).await
+    }
+}
+
+fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command
+        .description("Montre la progression d'un pooler au classement, semaine par semaine")
+        .kind(CommandType::ChatInput)
+        .create_option(|opt| {
+            opt
+                .name("pooler")
+                .kind(CommandOptionType::String)
+                .description("Le pooler à afficher (par défaut, soi-même)")
+                .required(false)
+                .set_autocomplete(true)
+        })
+}
+
+async fn run(ctx: Context, command: &ApplicationCommandInteraction, db: &DB, config: &Config) -> Result<()> {
+    let poolid = config.pool_id;
+    let season = current_season(db, &config.data_url).await?;
+
+    if let Err(reason) = command.create_interaction_response(&ctx.http, |res| {
+        res
+            .kind(InteractionResponseType::DeferredChannelMessageWithSource)
+            .interaction_response_data(|m| m
+                .content("Calcul ...")
+            )
+    })
+    .await {
+        error!("Cannot respond to slash command : {:?}", reason);
+    }
+
+    let (weeks, _) = db.fetch_season(&poolid, &season).await?;
+    let mode = db.fetch_pool_mode(&poolid).await.unwrap_or(PoolMode::Classic);
+    let auto_weight = db.fetch_auto_pick_weight(&poolid).await.unwrap_or(100);
+    let season_results = calc_season(&config.data_url, &season, &weeks, &mode, &auto_weight).await?;
+    for (_, results) in &season_results {
+        for r in results.iter().filter(|r| r.cache) {
+            db.cache_results(&r.pickid.unwrap(), &r.score, &r.featscore).await?;
+        }
+    }
+    let standings = calc_standings_history(&season_results, &HashMap::new());
+
+    let entry = match command.data.options.first().and_then(|opt| opt.value.as_ref()).and_then(|v| v.as_str()) {
+        Some(name) => standings.iter().find(|e| e.name.eq_ignore_ascii_case(name)),
+        None => {
+            let discordid = command.user.id.as_u64()
+                .to_string().parse::<i64>()
+                .unwrap();
+            match db.fetch_poolerid(&discordid).await {
+                Ok(poolerid) => standings.iter().find(|e| e.poolerid == poolerid),
+                Err(_) => None,
+            }
+        },
+    };
+
+    let message = match entry {
+        Some(entry) => {
+            let lines = season_results.iter().enumerate()
+                .fold(String::new(), |m, (i, (week, _))| {
+                    format!("{}\n`{} #{:<2} [{:03}] (+{:02})` {}", m,
+                        week.short_label(), entry.ranks[i], entry.totals[i], entry.scores[i],
+                        get_movement_icon(entry.movement_at(i)))
+                });
+
+            format!("## Classement de {}, saison {}{}", entry.name, season, lines)
+        },
+        None => format!("Aucun classement trouvé pour ce pooler (saison {}).", season),
+    };
+
+    if let Err(reason) = command.edit_original_interaction_response(&ctx.http, |res| {
+        res.content(message)
+    })
+    .await {
+        error!("Cannot respond to slash command : {:?}", reason);
+    }
+
+    Ok(())
+}
@@ -1,94 +1,80 @@
-use std::env;
-
+use anyhow::{anyhow, Result};
+use serenity::async_trait;
 use serenity::builder::CreateApplicationCommand;
 use serenity::model::application::interaction::InteractionResponseType;
 use serenity::model::application::interaction::application_command::ApplicationCommandInteraction;
-use serenity::model::prelude::command::{CommandType, CommandOptionType};
+use serenity::model::prelude::command::CommandType;
 use serenity::prelude::*;
+use tracing::error;
 
+use library::calendar::current_week;
+use library::config::Config;
 use library::database::DB;
-use library::football::{ get_team_emoji, calc_results, get_week, Match };
+use library::football::{ calc_results, get_week, Match, PoolMode };
+
+pub struct Results;
+
+#[async_trait]
+impl super::SlashCommand for Results {
+    fn name(&self) -> &'static str {
+        "resultat"
+    }
+
+    fn register<'a>(&self, command: &'a mut CreateApplicationCommand) -> &'a mut CreateApplicationCommand {
+        register(command)
+    }
 
-pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    async fn run(&self, ctx: Context, command: &ApplicationCommandInteraction, db: &DB, config: &Config) -> Result<()> {
+        run(ctx, command, db, config).await
+    }
+}
+
+fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
     command
-        .name("resultat")
         .description("Montre les résultats de tous les membres du pool pour une semaine")
         .kind(CommandType::ChatInput)
-        .create_option(|opt| {
-            opt
-                .name("semaine")
-                .kind(CommandOptionType::String)
-                .description("La semaine choisie")
-                .required(true)
-                .add_string_choice("semaine 1", 1)
-                .add_string_choice("semaine 2", 2)
-                .add_string_choice("semaine 3", 3)
-                .add_string_choice("semaine 4", 4)
-                .add_string_choice("semaine 5", 5)
-                .add_string_choice("semaine 6", 6)
-                .add_string_choice("semaine 7", 7)
-                .add_string_choice("semaine 8", 8)
-                .add_string_choice("semaine 9", 9)
-                .add_string_choice("semaine 10", 10)
-                .add_string_choice("semaine 11", 11)
-                .add_string_choice("semaine 12", 12)
-                .add_string_choice("semaine 13", 13)
-                .add_string_choice("semaine 14", 14)
-                .add_string_choice("semaine 15", 15)
-                .add_string_choice("semaine 16", 16)
-                .add_string_choice("semaine 17", 17)
-                .add_string_choice("semaine 18", 18)
-                .add_string_choice("WildCards", 19)
-                .add_string_choice("Divisional", 20)
-                .add_string_choice("Championship", 21)
-                .add_string_choice("Super Bowl", 22)
-        })
+        .create_option(|opt| super::week_option(opt, false))
 }
 
-pub async fn run(ctx: Context, command: &ApplicationCommandInteraction, db: &DB) {
-    let poolid = env::var("POOL_ID")
-        .expect("![results] Could not find env var 'POOL_ID'").parse()
-        .expect("![results] Could not parse pool_id to int");
-    let season = env::var("CONF_SEASON")
-        .expect("[results] Cannot find 'CONF_SEASON' in env").parse::<u16>()
-        .expect("[results] Could not parse 'CONF_SEASON' to u16");
-    let week = command.data.options.first()
-        .expect("[results] No argument provided").value.as_ref()
-        .unwrap().as_str().unwrap().parse::<i64>()
-        .expect("[results] Could not parse week arg to u64");
+async fn run(ctx: Context, command: &ApplicationCommandInteraction, db: &DB, config: &Config) -> Result<()> {
+    let poolid = config.pool_id;
+    let (season, current) = current_week(db, &config.data_url).await?;
+    let week = super::week_arg(&command.data.options).unwrap_or(current);
 
-    match db.fetch_picks(&poolid, &season, &week).await {
+    match db.fetch_picks(&poolid, &season, &week.number()).await {
         Ok(picks) => {
-            let matches: Vec<Match> = get_week(&season, &week).await;
+            let matches: Vec<Match> = get_week(&config.data_url, &season, &week).await?;
+            let mode = db.fetch_pool_mode(&poolid).await.unwrap_or(PoolMode::Classic);
+            let auto_weight = db.fetch_auto_pick_weight(&poolid).await.unwrap_or(100);
 
-            let feature = db.fetch_feature(season, week).await.ok();
+            let feature = db.fetch_feature(season, week.number()).await.ok();
             let feat_line = if let Some(feat) = &feature {
                 let feat_match = matches.iter().find(|m| m.id_event == feat.matchid).unwrap();
                 let aways = feat_match.away_score.unwrap_or_default();
                 let homes = feat_match.home_score.unwrap_or_default();
 
-                format!(":bar_chart: <:{}:{}> {} @ {} <:{}:{}>  -  O/U: {:02}.5  (Total: {})",
-                    feat_match.away_team, get_team_emoji(&feat_match.away_team), aways,
-                    homes, feat_match.home_team, get_team_emoji(&feat_match.home_team),
+                format!(":bar_chart: {} {} @ {} {}  -  O/U: {:02}.5  (Total: {})",
+                    feat_match.away_team.icon(), aways,
+                    homes, feat_match.home_team.icon(),
                     feat.target, aways + homes)
             } else {
-                format!("")
+                String::new()
             };
 
             if let Err(reason) = command.create_interaction_response(&ctx.http, |res| {
                 res
                     .kind(InteractionResponseType::ChannelMessageWithSource)
                     .interaction_response_data(|m| m
-                        .content(format!("### Résultats pour la semaine {}, {}\n{}", week, season, feat_line))
+                        .content(format!("### Résultats — {}, {}\n{}", week, season, feat_line))
                     )
             })
             .await {
-                println!("![results] Cannot respond to slash command : {:?}", reason);
+                error!("Cannot respond to slash command : {:?}", reason);
             }
 
-            for r in calc_results(&week, &matches, &picks, &feature).await.iter() {
+            for r in calc_results(&week, &matches, &picks, &feature, &mode, &auto_weight).await.iter() {
                 if r.cache {
-                    db.cache_results(&r.pickid.unwrap(), &r.score, &r.featscore).await.unwrap();
+                    db.cache_results(&r.pickid.unwrap(), &r.score, &r.featscore).await?;
                 }
 
                 let width = 12usize.saturating_sub(r.name.len());
@@ -101,13 +87,13 @@ pub async fn run(ctx: Context, command: &ApplicationCommandInteraction, db: &DB)
                             r.name, " ".repeat(width), r.score, r.featscore, r.icons, r.overunder).as_str())
                     }
                 }).await {
-                    println!("![results] Cannot respond to slash command : {:?}", message);
+                    error!("Cannot respond to slash command : {:?}", message);
                 }
             }
         },
-        Err(e) => {
-            println!("![results] Could not fetch picks for poolid: {}; season: {}, week: {}\nerror: {}",
-                poolid, season, week, e);
-        },
+        Err(e) => return Err(anyhow!("Could not fetch picks for poolid: {}; season: {}, week: {} : {}",
+            poolid, season, week, e)),
     }
+
+    Ok(())
 }
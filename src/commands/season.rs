@@ -1,37 +1,54 @@
-use std::env;
 use std::collections::HashMap;
 
+use anyhow::Result;
+use serenity::async_trait;
 use serenity::builder::CreateApplicationCommand;
 use serenity::model::application::interaction::InteractionResponseType;
 use serenity::model::application::interaction::application_command::ApplicationCommandInteraction;
-use serenity::model::prelude::command::CommandType;
+use serenity::model::prelude::command::{CommandOptionType, CommandType};
 use serenity::prelude::*;
+use tracing::error;
 
+use library::calendar::current_season;
+use library::config::Config;
 use library::database::DB;
-use library::football::{Match, calc_playoff_picture, calc_results, get_playoff_picture, get_week};
+use library::football::{
+    PoolMode, calc_playoff_picture, calc_season, calc_standings_history, get_movement_icon, get_playoff_picture,
+};
 
-pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+pub struct Season;
+
+#[async_trait]
+impl super::SlashCommand for Season {
+    fn name(&self) -> &'static str {
+        "saison"
+    }
+
+    fn register<'a>(&self, command: &'a mut CreateApplicationCommand) -> &'a mut CreateApplicationCommand {
+        register(command)
+    }
+
+    async fn run(&self, ctx: Context, command: &ApplicationCommandInteraction, db: &DB, config: &Config) -> Result<()> {
+        run(ctx, command, db, config).await
+    }
+}
+
+fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
     command
-        .name("saison")
         .description("Montre les résultats de toutes les semaines de la saison courante")
         .kind(CommandType::ChatInput)
+        .create_option(|opt| {
+            opt
+                .name("projection")
+                .kind(CommandOptionType::Boolean)
+                .description("Inclure les points provisoires de la capsule selon le classement actuel")
+                .required(false)
+        })
 }
 
-struct SeasonResult {
-    //poolerid: i64,
-    name: String,
-    scores: Vec<u32>,
-    cap_score: u32,
-    total: u32,
-}
-
-pub async fn run(ctx: Context, command: &ApplicationCommandInteraction, db: &DB) {
-    let poolid = env::var("POOL_ID")
-        .expect("![Handler] Could not find env var 'POOL_ID'").parse::<i64>()
-        .expect("![Handler] Could not parse pool_id to int");
-    let season = env::var("CONF_SEASON")
-        .expect("[results] Cannot find 'CONF_SEASON' in env").parse::<u16>()
-        .expect("[results] Could not parse 'CONF_SEASON' to u16");
+async fn run(ctx: Context, command: &ApplicationCommandInteraction, db: &DB, config: &Config) -> Result<()> {
+    let poolid = config.pool_id;
+    let season = current_season(db, &config.data_url).await?;
 
     if let Err(reason) = command.create_interaction_response(&ctx.http, |res| {
         res
@@ -41,88 +58,103 @@ pub async fn run(ctx: Context, command: &ApplicationCommandInteraction, db: &DB)
             )
     })
     .await {
-        println!("![results] Cannot respond to slash command : {:?}", reason);
+        error!("Cannot respond to slash command : {:?}", reason);
     }
 
-    let (weeks, _week_count) = db.fetch_season(&poolid, &season).await.unwrap();
-    let capsule = match db.fetch_capsule(&season, &poolid).await {
-        Ok(cap) => cap,
-        Err(_) => HashMap::<_, _>::new(),
-    };
-    let picture = get_playoff_picture(season).await;
-    let cap_results = if picture.reg_season_over {
-        calc_playoff_picture(&picture, &capsule)
+    let (weeks, _) = db.fetch_season(&poolid, &season).await?;
+    let capsule = db.fetch_capsule(&season, &poolid).await.unwrap_or_default();
+    let projection = command.data.options.iter()
+        .find(|opt| opt.name == "projection")
+        .and_then(|opt| opt.value.as_ref())
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+
+    // Every capsule already graded: final scores come from the DB, no need for ESPN
+    let graded = !capsule.is_empty() && capsule.values().all(|c| c.cached.is_some());
+    let (season_over, cap_scores): (bool, HashMap<i64, u32>) = if graded {
+        (true, capsule.values().map(|c| (c.poolerid, c.cached.unwrap_or(0))).collect())
     } else {
-        Vec::new()
+        let picture = get_playoff_picture(&config.standings_url, &config.data_url, season).await?;
+        let cap_results = if picture.reg_season_over || projection {
+            let repick_weight = db.fetch_repick_weight(&poolid).await.unwrap_or(100);
+            calc_playoff_picture(&picture, &capsule, &repick_weight)
+        } else {
+            Vec::new()
+        };
+
+        for r in cap_results.iter().filter(|r| r.cache) {
+            db.cache_capsule_score(&r.poolerid, &season, &r.score).await?;
+        }
+        (picture.reg_season_over, cap_results.iter().map(|r| (r.poolerid, r.score)).collect())
     };
-    let mut season_data = Vec::<SeasonResult>::new();
-
-    for (_, feat, picks) in weeks.iter() {
-        for pick in picks {
-            let score = if pick.cached.is_some() {
-                pick.cached.unwrap() + pick.featcached.unwrap()
-            } else {
-                let matches: Vec<Match> = get_week(&season, &pick.week).await;
-                let results = calc_results(&pick.week, &matches, &picks, feat).await;
-                let result = results.iter()
-                    .find(|res| res.poolerid == pick.poolerid)
-                    .unwrap();
-
-                if result.cache {
-                    db.cache_results(&result.pickid.unwrap(), &result.score, &result.featscore).await.unwrap();
-                }
-                result.score + result.featscore
-            };
-
-            if let Some(data) = season_data.iter_mut().find(|d| d.name.eq(&pick.name)) {
-                data.scores.push(score);
-                data.total += score;
-            }
-            else {
-                let pooler_cap_score = match cap_results.iter().find(|cr| cr.poolerid == pick.poolerid) {
-                    Some(res) => res.score,
-                    None => 0,
-                };
-                season_data.push(SeasonResult{ name: pick.name.clone(), scores: vec![score], cap_score: pooler_cap_score, total: score });
-            }
+    let show_capsule = season_over || projection;
+
+    let mode = db.fetch_pool_mode(&poolid).await.unwrap_or(PoolMode::Classic);
+    let auto_weight = db.fetch_auto_pick_weight(&poolid).await.unwrap_or(100);
+    let season_results = calc_season(&config.data_url, &season, &weeks, &mode, &auto_weight).await?;
+    for (_, results) in &season_results {
+        for r in results.iter().filter(|r| r.cache) {
+            db.cache_results(&r.pickid.unwrap(), &r.score, &r.featscore).await?;
         }
     }
 
-    season_data.sort_unstable_by(|l, r| {
-        let r_full = r.total + r.cap_score;
-        let l_full = l.total + l.cap_score;
-        r_full.cmp(&l_full)
-    });
-    let header = (1..=_week_count).fold(String::new(), |m, i| {
-        if i <= 18 {
-            format!("{}|{:02}", m, i)
-        }
-        else {
-            match i {
-                19 => format!("{}|{:02}", m, "WC"),
-                20 => format!("{}|{:02}", m, "DV"),
-                21 => format!("{}|{:02}", m, "CF"),
-                22 => format!("{}|{:02}", m, "SB"),
-                _ => unreachable!(),
-            }
-        }
+    // A projection is only shown on the side, the total and order stay on the picks until the capsules are final
+    let counted = if season_over { cap_scores.clone() } else { HashMap::new() };
+    let standings: Vec<_> = calc_standings_history(&season_results, &counted)
+        .into_iter()
+        .map(|entry| {
+            let cap_score = cap_scores.get(&entry.poolerid).copied().unwrap_or(0);
+            let total = entry.total() + counted.get(&entry.poolerid).copied().unwrap_or(0);
+            (entry, cap_score, total)
+        })
+        .collect();
+    let header = season_results.iter().fold(String::new(), |m, (week, _)| {
+        format!("{}|{}", m, week.short_label())
     });
-    // Only surface the capsule column once it actually counts (season over).
-    let cap_header = if picture.reg_season_over { "|+C" } else { "" };
+    // Only surface the capsule column once it actually counts (season over),
+    // or on demand as a provisional projection from the current standings.
+    let cap_header = match (show_capsule, season_over) {
+        (true, true)  => "|+C",
+        (true, false) => "|~C",
+        (false, _)    => "",
+    };
     let header = format!("Semaines{} {}{}", " ".repeat(15-6), header, cap_header);
-    let message = season_data.iter()
-        .fold(String::new(), |m, entry| {
-            let width = 12 - entry.name.len();
+    let message = standings.iter()
+        .fold(String::new(), |m, (entry, cap_score, total)| {
+            let width = 12usize.saturating_sub(entry.name.len());
             let grid = entry.scores.iter().fold(String::new(), |g, s| { format!("{}|{:02}", g, s) });
-            let cap_col = if picture.reg_season_over { format!("|{:02}", entry.cap_score) } else { String::new() };
+            let cap_col = if show_capsule { format!("|{:02}", cap_score) } else { String::new() };
 
-            format!("{}\n`{}{}[{:03}] {}{}`", m, entry.name, " ".repeat(width), entry.total + entry.cap_score, grid, cap_col)
+            format!("{}\n`{}{}[{:03}] {}{}` {}", m, entry.name, " ".repeat(width), total, grid, cap_col,
+                get_movement_icon(entry.movement()))
         });
 
+    let climb = standings.iter()
+        .map(|(entry, _, _)| entry)
+        .filter(|entry| entry.movement() > 0)
+        .max_by_key(|entry| entry.movement());
+    let fall = standings.iter()
+        .map(|(entry, _, _)| entry)
+        .filter(|entry| entry.movement() < 0)
+        .min_by_key(|entry| entry.movement());
+
+    let mut callouts = String::new();
+    if show_capsule && !season_over {
+        callouts.push_str("\n*~C : points de capsule provisoires, selon le classement actuel*");
+    }
+    if let Some(entry) = climb {
+        callouts.push_str(&format!("\n{} **Meilleure remontée:** {} (+{})", get_movement_icon(1), entry.name, entry.movement()));
+    }
+    if let Some(entry) = fall {
+        callouts.push_str(&format!("\n{} **Pire chute:** {} ({})", get_movement_icon(-1), entry.name, entry.movement()));
+    }
+
     if let Err(reason) = command.edit_original_interaction_response(&ctx.http, |res| {
-        res.content(format!("Saison {}\n`{}`\n{}\n", season, header, message))
+        res.content(format!("Saison {}\n`{}`\n{}\n{}", season, header, message, callouts))
     })
     .await {
-        println!("![results] Cannot respond to slash command : {:?}", reason);
+        error!("Cannot respond to slash command : {:?}", reason);
     }
+
+    Ok(())
 }
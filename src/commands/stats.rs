@@ -1,18 +1,38 @@
-use std::env;
 use std::fmt::Display;
 
+use anyhow::Result;
+use serenity::async_trait;
 use serenity::builder::CreateApplicationCommand;
 use serenity::model::application::interaction::InteractionResponseType;
 use serenity::model::application::interaction::application_command::ApplicationCommandInteraction;
 use serenity::model::prelude::command::CommandType;
 use serenity::prelude::*;
+use tracing::error;
 
+use library::calendar::current_season;
+use library::config::Config;
 use library::database::{ DB, WeekFeature };
-use library::football::{ Match, get_week };
+use library::football::{ Match, Team, get_week };
 
-pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+pub struct Stats;
+
+#[async_trait]
+impl super::SlashCommand for Stats {
+    fn name(&self) -> &'static str {
+        "stats"
+    }
+
+    fn register<'a>(&self, command: &'a mut CreateApplicationCommand) -> &'a mut CreateApplicationCommand {
+        register(command)
+    }
+
+    async fn run(&self, ctx: Context, command: &ApplicationCommandInteraction, db: &DB, config: &Config) -> Result<()> {
+        run(ctx, command, db, config).await
+    }
+}
+
+fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
     command
-        .name("stats")
         .description("Montre les statistiques de toutes les semaines de la saison courante")
         .kind(CommandType::ChatInput)
 }
@@ -48,13 +68,9 @@ impl Display for PoolerStats {
     }
 }
 
-pub async fn run(ctx: Context, command: &ApplicationCommandInteraction, db: &DB) {
-    let poolid = env::var("POOL_ID")
-        .expect("![Handler] Could not find env var 'POOL_ID'").parse::<i64>()
-        .expect("![Handler] Could not parse pool_id to int");
-    let season = env::var("CONF_SEASON")
-        .expect("[results] Cannot find 'CONF_SEASON' in env").parse::<u16>()
-        .expect("[results] Could not parse 'CONF_SEASON' to u16");
+async fn run(ctx: Context, command: &ApplicationCommandInteraction, db: &DB, config: &Config) -> Result<()> {
+    let poolid = config.pool_id;
+    let season = current_season(db, &config.data_url).await?;
 
     if let Err(reason) = command.create_interaction_response(&ctx.http, |res| {
         res
@@ -64,29 +80,30 @@ pub async fn run(ctx: Context, command: &ApplicationCommandInteraction, db: &DB)
             )
     })
     .await {
-        println!("![results] Cannot respond to slash command : {:?}", reason);
+        error!("Cannot respond to slash command : {:?}", reason);
     }
 
     let mut pool = PoolStats{ uni_count: 0, uni_hits: 0, unique_count: 0, unique_hits: 0, ou_count: 0, ou_hits: 0 };
     let mut stats = Vec::<PoolerStats>::new();
 
-    let (weeks, _) = db.fetch_season(&poolid, &season).await.unwrap();
+    let (weeks, _) = db.fetch_season(&poolid, &season).await?;
     for (w, feat_info, poolers) in &weeks[..] {
-        for m in get_week(&season, &w).await {
+        let matches = get_week(&config.data_url, &season, w).await?;
+        for m in matches {
             //TODO: Look into skipping matches that are not played yet
             let picks: Vec<(_, _)> = poolers.iter()
                 .map(|p| {
                     let pick = match &p.picks {
-                        Some(pick) => pick.get(&m.id_event).unwrap().as_str(),
-                        None => "",
+                        Some(pick) => pick.get(&m.id_event).copied(),
+                        None => None,
                     };
                     (p.name.as_str(), pick)
                 })
                 .inspect(|&(name, pick)| {
-                    let win = pick == &m.away_team && m.away_score > m.home_score ||
-                              pick == &m.home_team && m.home_score > m.away_score;
+                    let win = pick == Some(m.away_team) && m.away_score > m.home_score ||
+                              pick == Some(m.home_team) && m.home_score > m.away_score;
 
-                    if let Some(stat) = stats.iter_mut().find(|s| &s.name == name) {
+                    if let Some(stat) = stats.iter_mut().find(|s| s.name == name) {
                         stat.pick_count += 1;
                         if win {
                             stat.hit_count += 1;
@@ -117,7 +134,7 @@ pub async fn run(ctx: Context, command: &ApplicationCommandInteraction, db: &DB)
             }
         }
 
-        if let Some(_) = feat_info {
+        if feat_info.is_some() {
             pool.ou_count += poolers.len() as u32;
         }
     }
@@ -155,13 +172,15 @@ pub async fn run(ctx: Context, command: &ApplicationCommandInteraction, db: &DB)
         res.content(format!("## Statistiques de la saison {}\n{}\n{}\n{}\n{}", season, uni, unique, ou_line, list))
     })
     .await {
-        println!("![results] Cannot respond to slash command : {:?}", reason);
+        error!("Cannot respond to slash command : {:?}", reason);
     }
+
+    Ok(())
 }
 
-fn check_unanimous(m: &Match, picks: &Vec<(&str, &str)>, una_hit: &mut u32, una_count: &mut u32) {
-    let all_away = picks.iter().all(|&(_, p)| p == m.away_team);
-    let all_home = picks.iter().all(|&(_, p)| p == m.home_team);
+fn check_unanimous(m: &Match, picks: &Vec<(&str, Option<Team>)>, una_hit: &mut u32, una_count: &mut u32) {
+    let all_away = picks.iter().all(|&(_, p)| p == Some(m.away_team));
+    let all_home = picks.iter().all(|&(_, p)| p == Some(m.home_team));
 
     if all_away || all_home {
         *una_count += 1;
@@ -174,24 +193,24 @@ fn check_unanimous(m: &Match, picks: &Vec<(&str, &str)>, una_hit: &mut u32, una_
 
 fn check_unique(
     m: &Match,
-    picks: &Vec<(&str, &str)>,
+    picks: &Vec<(&str, Option<Team>)>,
     uni_hit: &mut u32,
     uni_count: &mut u32,
-    stats: &mut Vec<PoolerStats>)
+    stats: &mut [PoolerStats])
 {
-    let away_count = picks.iter().filter(|(_, pick)| pick == &m.away_team).count();
-    let home_count = picks.iter().filter(|(_, pick)| pick == &m.home_team).count();
+    let away_count = picks.iter().filter(|(_, pick)| *pick == Some(m.away_team)).count();
+    let home_count = picks.iter().filter(|(_, pick)| *pick == Some(m.home_team)).count();
 
     if away_count == 1 || home_count == 1 {
         *uni_count += 1;
 
         let name = if away_count < home_count {
-            picks.iter().find(|(_, pick)| pick == &m.away_team).unwrap().0
+            picks.iter().find(|(_, pick)| *pick == Some(m.away_team)).unwrap().0
         } else {
-            picks.iter().find(|(_, pick)| pick == &m.home_team).unwrap().0
+            picks.iter().find(|(_, pick)| *pick == Some(m.home_team)).unwrap().0
         };
 
-        let stat = stats.iter_mut().find(|s| &s.name == name).unwrap();
+        let stat = stats.iter_mut().find(|s| s.name == name).unwrap();
         stat.unique_count += 1;
 
         if away_count == 1 && m.away_score > m.home_score || home_count == 1 && m.home_score > m.away_score {
@@ -201,7 +220,7 @@ fn check_unique(
     }
 }
 
-fn check_ou(m: &Match, f: &WeekFeature, feats: &Vec<(&str, Option<u32>)>, pool_hits: &mut u32, stats: &mut Vec<PoolerStats>) {
+fn check_ou(m: &Match, f: &WeekFeature, feats: &Vec<(&str, Option<u32>)>, pool_hits: &mut u32, stats: &mut [PoolerStats]) {
     if m.id_event != f.matchid { return; }
 
     for (name, feat) in feats {
@@ -0,0 +1,198 @@
+use anyhow::{anyhow, Result};
+use serenity::async_trait;
+use serenity::builder::CreateApplicationCommand;
+use serenity::model::application::interaction::InteractionResponseType;
+use serenity::model::application::interaction::application_command::{ApplicationCommandInteraction, CommandDataOption};
+use serenity::model::prelude::command::{CommandOptionType, CommandType};
+use serenity::prelude::*;
+use tracing::error;
+
+use library::calendar::current_season;
+use library::config::Config;
+use library::database::DB;
+use library::football::{Team, get_missing_icon, get_week};
+use library::survivor::{SurvivorStatus, resolve_survivor, survivor_standings, survivor_status, validate_survivor_pick};
+
+pub struct Survivor;
+
+#[async_trait]
+impl super::SlashCommand for Survivor {
+    fn name(&self) -> &'static str {
+        "survivor"
+    }
+
+    fn register<'a>(&self, command: &'a mut CreateApplicationCommand) -> &'a mut CreateApplicationCommand {
+        register(command)
+    }
+
+    async fn run(&self, ctx: Context, command: &ApplicationCommandInteraction, db: &DB, config: &Config) -> Result<()> {
+        run(ctx, command, db, config).await
+    }
+}
+
+fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command
+        .description("Pool survivor - une équipe gagnante par semaine, jamais la même")
+        .kind(CommandType::ChatInput)
+        .create_option(|opt| {
+            opt
+                .name("choisir")
+                .kind(CommandOptionType::SubCommand)
+                .description("Choisir l'équipe survivor pour une semaine")
+                .create_sub_option(|sub| super::week_option(sub, true))
+                .create_sub_option(|sub| {
+                    sub
+                        .name("équipe")
+                        .kind(CommandOptionType::String)
+                        .description("L'équipe qui va gagner")
+                        .required(true)
+                        .set_autocomplete(true)
+                })
+        })
+        .create_option(|opt| {
+            opt
+                .name("statut")
+                .kind(CommandOptionType::SubCommand)
+                .description("Montre ses choix survivor et son statut")
+        })
+        .create_option(|opt| {
+            opt
+                .name("vivants")
+                .kind(CommandOptionType::SubCommand)
+                .description("Montre les poolers encore en vie")
+        })
+}
+
+async fn run(ctx: Context, command: &ApplicationCommandInteraction, db: &DB, config: &Config) -> Result<()> {
+    let poolid = config.pool_id;
+    let season = current_season(db, &config.data_url).await?;
+
+    let sub = command.data.options.first()
+        .expect("[survivor] No subcommand given with the command");
+
+    if let Err(reason) = command.create_interaction_response(&ctx.http, |res| {
+        res
+            .kind(InteractionResponseType::DeferredChannelMessageWithSource)
+            .interaction_response_data(|m| m
+                .ephemeral(sub.name != "vivants")
+                .content("Calcul ...")
+            )
+    })
+    .await {
+        error!("Cannot respond to slash command : {:?}", reason);
+    }
+
+    if let Err(e) = resolve_survivor(db, &config.data_url, &poolid, &season).await {
+        error!("Could not resolve survivor picks for season {} : {}", season, e);
+    }
+
+    let message = match sub.name.as_str() {
+        "choisir" => pick(command, sub, db, config, &season, &poolid).await?,
+        "statut"  => status(command, db, &season, &poolid).await?,
+        "vivants" => alive(db, &season, &poolid).await?,
+        other     => format!("Sous-commande inconnue: {}", other),
+    };
+
+    if let Err(reason) = command.edit_original_interaction_response(&ctx.http, |res| {
+        res.content(message)
+    })
+    .await {
+        error!("Cannot respond to slash command : {:?}", reason);
+    }
+
+    Ok(())
+}
+
+async fn pick(command: &ApplicationCommandInteraction, sub: &CommandDataOption, db: &DB, config: &Config, season: &u16, poolid: &i64) -> Result<String> {
+    let week = super::week_arg(&sub.options)
+        .expect("[survivor] No week arg given with the command");
+    let team = sub.options.iter()
+        .find(|o| o.name == "équipe")
+        .and_then(|o| o.value.as_ref()).and_then(|v| v.as_str())
+        .expect("[survivor] No team arg given with the command");
+    let team = match team.parse::<Team>() {
+        Ok(team) => team,
+        Err(_) => return Ok(format!("Équipe inconnue: {}", team)),
+    };
+
+    let discordid = command.user.id.as_u64()
+        .to_string().parse::<i64>()
+        .unwrap();
+    let poolerid = match db.fetch_poolerid(&discordid).await {
+        Ok(pid) => pid,
+        Err(_) => return Ok("Tu n'es pas inscrit au pool.".to_string()),
+    };
+
+    let picks = match db.fetch_survivor_picks(season, poolid).await {
+        Ok(picks) => picks,
+        Err(e) => return Err(anyhow!("Could not fetch survivor picks for season {} : {}", season, e)),
+    };
+    let matches = get_week(&config.data_url, season, &week).await?;
+
+    match validate_survivor_pick(&picks, poolerid, week.number(), team, &matches) {
+        Ok(game) => match db.set_survivor_pick(super::actor(command), season, &week.number(), &poolerid, &game.id_event, &team).await {
+            Ok(_) => Ok(format!("Choix survivor — {}: {}", week, team.icon())),
+            Err(e) => Err(anyhow!("Could not save survivor pick for pooler {} : {}", poolerid, e)),
+        },
+        Err(reason) => Ok(reason.to_string()),
+    }
+}
+
+async fn status(command: &ApplicationCommandInteraction, db: &DB, season: &u16, poolid: &i64) -> Result<String> {
+    let discordid = command.user.id.as_u64()
+        .to_string().parse::<i64>()
+        .unwrap();
+    let poolerid = match db.fetch_poolerid(&discordid).await {
+        Ok(pid) => pid,
+        Err(_) => return Ok("Tu n'es pas inscrit au pool.".to_string()),
+    };
+
+    let picks = match db.fetch_survivor_picks(season, poolid).await {
+        Ok(picks) => picks,
+        Err(e) => return Err(anyhow!("Could not fetch survivor picks for season {} : {}", season, e)),
+    };
+
+    let lines = picks.iter()
+        .filter(|p| p.poolerid == poolerid)
+        .fold(String::new(), |m, p| {
+            let outcome = match p.outcome {
+                Some(1) => ":white_check_mark:",
+                Some(_) => ":x:",
+                None => ":hourglass:",
+            };
+            format!("{}\n`Semaine {:02}` {} {}", m, p.week, p.team.map_or_else(get_missing_icon, |t| t.icon()), outcome)
+        });
+
+    let header = match survivor_status(&picks, poolerid) {
+        SurvivorStatus::Alive => format!("## Survivor {} - encore en vie", season),
+        SurvivorStatus::Eliminated(week) => format!("## Survivor {} - éliminé à la semaine {}", season, week),
+    };
+
+    if lines.is_empty() {
+        Ok(format!("{}\nAucun choix survivor pour l'instant.", header))
+    } else {
+        Ok(format!("{}{}", header, lines))
+    }
+}
+
+async fn alive(db: &DB, season: &u16, poolid: &i64) -> Result<String> {
+    let picks = match db.fetch_survivor_picks(season, poolid).await {
+        Ok(picks) => picks,
+        Err(e) => return Err(anyhow!("Could not fetch survivor picks for season {} : {}", season, e)),
+    };
+
+    let standings = survivor_standings(&picks);
+    let alive_count = standings.iter().filter(|e| e.status == SurvivorStatus::Alive).count();
+
+    Ok(standings.iter().fold(format!("## Survivor {} - {} pooler(s) en vie", season, alive_count), |m, e| {
+        let width = 12usize.saturating_sub(e.name.len());
+        let status = match e.status {
+            SurvivorStatus::Alive => ":heart:".to_string(),
+            SurvivorStatus::Eliminated(week) => format!(":skull: (semaine {})", week),
+        };
+        let icons = e.teams.iter().fold(String::new(), |icons, team| {
+            format!("{}{}", icons, team.map_or_else(get_missing_icon, |t| t.icon()))
+        });
+        format!("{}\n`{}{}` {} {}", m, e.name, " ".repeat(width), icons, status)
+    }))
+}
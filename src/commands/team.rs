@@ -1,15 +1,35 @@
+use anyhow::{anyhow, Result};
+use serenity::async_trait;
 use serenity::builder::CreateApplicationCommand;
 use serenity::model::application::interaction::InteractionResponseType;
 use serenity::model::application::interaction::application_command::ApplicationCommandInteraction;
 use serenity::model::prelude::command::{CommandType, CommandOptionType};
 use serenity::prelude::*;
+use tracing::error;
 
+use library::config::Config;
 use library::database::DB;
-use library::football::get_team_emoji;
+use library::football::{Team, get_missing_icon};
 
-pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+pub struct FavoriteTeam;
+
+#[async_trait]
+impl super::SlashCommand for FavoriteTeam {
+    fn name(&self) -> &'static str {
+        "equipe"
+    }
+
+    fn register<'a>(&self, command: &'a mut CreateApplicationCommand) -> &'a mut CreateApplicationCommand {
+        register(command)
+    }
+
+    async fn run(&self, ctx: Context, command: &ApplicationCommandInteraction, db: &DB, _config: &Config) -> Result<()> {
+        run(ctx, command, db).await
+    }
+}
+
+fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
     command
-        .name("equipe")
         .description("Modifier ou afficher l'équipe favorite du pooler courant")
         .kind(CommandType::ChatInput)
         .create_option(|opt| {
@@ -18,10 +38,11 @@ pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicatio
                 .kind(CommandOptionType::String)
                 .description("La nouvelle équipe favorite")
                 .required(false)
+                .set_autocomplete(true)
         })
 }
 
-pub async fn run(ctx: Context, command: &ApplicationCommandInteraction, db: &DB) {
+async fn run(ctx: Context, command: &ApplicationCommandInteraction, db: &DB) -> Result<()> {
     let discordid = command.user.id.as_u64()
         .to_string().parse::<i64>()
         .unwrap();
@@ -29,29 +50,29 @@ pub async fn run(ctx: Context, command: &ApplicationCommandInteraction, db: &DB)
     if let Some(option) = command.data.options.first() {
         let team = option.value.as_ref().unwrap().as_str().unwrap();
 
-        if get_team_emoji(team) != get_team_emoji("") {
-            match db.update_favteam(&discordid, team).await {
-                Ok(_) => { },
-                Err(e) => { println!("![team] Could not update favorite team: {}", e) },
+        if let Ok(team) = team.parse::<Team>() {
+            if let Err(e) = db.update_favteam(&discordid, &team).await {
+                return Err(anyhow!("Could not update favorite team : {}", e));
             }
         }
     }
 
     let (name, favteam) = match db.fetch_favteam(&discordid).await {
         Ok((name, favteam)) => { (name, favteam) }
-        Err(_) => { ("Inconnu".to_string(), "NA".to_string()) }
+        Err(_) => { ("Inconnu".to_string(), None) }
     };
-    let logo = get_team_emoji(&favteam);
 
     if let Err(reason) = command.create_interaction_response(&ctx.http, |res| {
         res
             .kind(InteractionResponseType::ChannelMessageWithSource)
             .interaction_response_data(|m| m
                 .ephemeral(true)
-                .content(format!("Équipe favorite pour {}: <:{}:{}>", name, favteam, logo))
+                .content(format!("Équipe favorite pour {}: {}", name, favteam.map_or_else(get_missing_icon, |t| t.icon())))
             )
     })
     .await {
-        println!("![results] Cannot respond to slash command : {:?}", reason);
+        error!("Cannot respond to slash command : {:?}", reason);
     }
+
+    Ok(())
 }
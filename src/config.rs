@@ -0,0 +1,193 @@
+use std::{env, fs, net::SocketAddr, path::Path, str::FromStr};
+
+use anyhow::{ anyhow, Result };
+use reqwest::Url;
+use toml::Table;
+use tracing_subscriber::EnvFilter;
+
+// Used when `CONFIG_FILE` isn't set, skipped if it doesn't exist
+const DEFAULT_CONFIG_FILE: &str = "config.toml";
+// sqlx logs every statement at info, serenity every gateway event
+const DEFAULT_LOG_LEVEL: &str = "info,sqlx=warn,serenity=warn";
+
+#[derive(Clone)]
+pub struct Config {
+    pub discord_token: String,
+    pub guild_id: u64,
+    pub pool_id: i64,
+    pub database_url: String,
+    pub picks_url: String,
+    pub data_url: String,
+    pub standings_url: String,
+    pub blame_url: Option<String>,
+    pub emoji_prefix: String,
+    pub log_level: String,
+    pub log_format: LogFormat,
+    pub health_addr: Option<SocketAddr>,
+    pub api_addr: Option<SocketAddr>,
+    pub public_addr: Option<SocketAddr>,
+    pub enable_commands: Vec<String>,
+    pub disable_commands: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+// Each key is read from the env first (`POOL_ID`), then from the config file (`pool_id`)
+struct Source {
+    file: Table,
+    errors: Vec<String>,
+}
+
+impl Source {
+    fn value(&self, key: &str) -> Option<String> {
+        if let Ok(value) = env::var(key) {
+            return Some(value);
+        }
+
+        match self.file.get(&key.to_lowercase())? {
+            toml::Value::String(s) => Some(s.clone()),
+            other => Some(other.to_string()),
+        }
+    }
+
+    fn required(&mut self, key: &str) -> String {
+        match self.value(key) {
+            Some(value) if !value.trim().is_empty() => value,
+            _ => {
+                self.errors.push(format!("'{}' is missing", key));
+                String::new()
+            },
+        }
+    }
+
+    fn parsed<T: FromStr + Default>(&mut self, key: &str) -> T {
+        let value = self.required(key);
+        if value.is_empty() {
+            return T::default();
+        }
+
+        value.trim().parse().unwrap_or_else(|_| {
+            self.errors.push(format!("'{}' is not a valid number : {}", key, value));
+            T::default()
+        })
+    }
+
+    fn url(&mut self, key: &str) -> String {
+        let value = self.required(key);
+        self.check_url(key, value)
+    }
+
+    fn optional_url(&mut self, key: &str) -> Option<String> {
+        let value = self.value(key).filter(|v| !v.trim().is_empty())?;
+        Some(self.check_url(key, value))
+    }
+
+    fn optional_addr(&mut self, key: &str) -> Option<SocketAddr> {
+        let value = self.value(key).filter(|v| !v.trim().is_empty())?;
+        match value.trim().parse() {
+            Ok(addr) => Some(addr),
+            Err(_) => {
+                self.errors.push(format!("'{}' is not a valid address (e.g. 127.0.0.1:9100) : {}", key, value));
+                None
+            },
+        }
+    }
+
+    // Comma separated, e.g. `ping,blame`. A toml array works too in the config file.
+    fn list(&self, key: &str) -> Vec<String> {
+        let value = match env::var(key) {
+            Ok(value) => value,
+            Err(_) => match self.file.get(&key.to_lowercase()) {
+                Some(toml::Value::Array(values)) => values.iter()
+                    .map(|v| v.as_str().map(str::to_owned).unwrap_or_else(|| v.to_string()))
+                    .collect::<Vec<_>>()
+                    .join(","),
+                _ => self.value(key).unwrap_or_default(),
+            },
+        };
+
+        value.split(',')
+            .map(|name| name.trim().to_lowercase())
+            .filter(|name| !name.is_empty())
+            .collect()
+    }
+
+    // Same syntax as `RUST_LOG`, e.g. `debug` or `info,library::database=debug`
+    fn log_level(&mut self) -> String {
+        let value = self.value("LOG_LEVEL").unwrap_or_else(|| DEFAULT_LOG_LEVEL.to_owned());
+        if let Err(e) = EnvFilter::try_new(&value) {
+            self.errors.push(format!("'LOG_LEVEL' is not a valid filter : {} ({})", value, e));
+        }
+        value
+    }
+
+    fn log_format(&mut self) -> LogFormat {
+        match self.value("LOG_FORMAT").as_deref().map(str::trim) {
+            None | Some("") | Some("text") => LogFormat::Text,
+            Some("json") => LogFormat::Json,
+            Some(other) => {
+                self.errors.push(format!("'LOG_FORMAT' must be 'text' or 'json' : {}", other));
+                LogFormat::Text
+            },
+        }
+    }
+
+    fn check_url(&mut self, key: &str, value: String) -> String {
+        if !value.is_empty() && Url::parse(&value).is_err() {
+            self.errors.push(format!("'{}' is not a valid URL : {}", key, value));
+        }
+        value
+    }
+}
+
+impl Config {
+    // Every missing or invalid key is reported at once, not only the first one
+    pub fn load() -> Result<Config> {
+        let mut source = Source { file: read_file()?, errors: Vec::new() };
+
+        let config = Config {
+            discord_token: source.required("DISCORD_TOKEN"),
+            guild_id: source.parsed("GUILD_ID"),
+            pool_id: source.parsed("POOL_ID"),
+            database_url: source.required("DATABASE_URL"),
+            picks_url: source.url("PICKS_URL"),
+            data_url: source.url("DATA_URL"),
+            standings_url: source.url("STANDINGS_URL"),
+            blame_url: source.optional_url("BLAME_URL"),
+            emoji_prefix: source.value("EMOJI_PREFIX").unwrap_or_default(),
+            log_level: source.log_level(),
+            log_format: source.log_format(),
+            health_addr: source.optional_addr("HEALTH_ADDR"),
+            api_addr: source.optional_addr("API_ADDR"),
+            public_addr: source.optional_addr("PUBLIC_ADDR"),
+            enable_commands: source.list("ENABLE_COMMANDS"),
+            disable_commands: source.list("DISABLE_COMMANDS"),
+        };
+
+        if source.errors.is_empty() {
+            Ok(config)
+        } else {
+            Err(anyhow!("Invalid configuration :\n\t- {}", source.errors.join("\n\t- ")))
+        }
+    }
+}
+
+fn read_file() -> Result<Table> {
+    let (path, explicit) = match env::var("CONFIG_FILE") {
+        Ok(path) => (path, true),
+        Err(_) => (DEFAULT_CONFIG_FILE.to_owned(), false),
+    };
+
+    if !explicit && !Path::new(&path).exists() {
+        return Ok(Table::new());
+    }
+
+    let content = fs::read_to_string(&path)
+        .map_err(|e| anyhow!("Could not read config file '{}' : {}", path, e))?;
+    content.parse::<Table>()
+        .map_err(|e| anyhow!("Could not parse config file '{}' : {}", path, e))
+}
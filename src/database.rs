@@ -1,11 +1,18 @@
-use std::collections::{HashMap, BTreeMap};
-use std::env;
+use std::collections::{HashMap, HashSet, BTreeMap};
 use std::fmt::{ Display, Debug };
+use std::str::FromStr;
 
-use anyhow::Result;
-use sqlx::{ Pool, QueryBuilder, Row, Sqlite };
+use anyhow::{ anyhow, Result };
+use chrono::{ DateTime, SecondsFormat, TimeDelta, Utc };
+use rand::distributions::{Alphanumeric, DistString};
+use rand::rngs::OsRng;
+use sqlx::{ Pool, QueryBuilder, Row, Sqlite, Transaction };
 use sqlx::sqlite::{ SqlitePool, SqliteRow };
+use tracing::{debug, error, instrument, warn};
 
+use crate::football::{DefaultPicks, PoolMode, Team, Week, WeekDates};
+
+#[derive(Clone)]
 pub struct DB {
     pool: Pool<Sqlite>,
 }
@@ -15,14 +22,19 @@ pub struct WeekPicks {
     pub poolerid: i64,
     pub name: String,
     pub week: i64,
-    pub picks: Option<HashMap<String, String>>,
+    pub picks: Option<HashMap<String, Team>>,
     pub counts: Option<HashMap<String, i32>>,
+    pub confidences: Option<HashMap<String, u32>>,
+    // Matches filled in by the pool's default picks policy
+    pub autos: HashSet<String>,
     pub featpick: Option<u32>,
     pub cached: Option<u32>,
     pub featcached: Option<u32>,
+    // The favorite team when the picks went in, unknown on older picks
+    pub favteam: Option<Team>,
 }
 
-type SeasonPicks = Vec<(i64, Option<WeekFeature>, Vec<WeekPicks>)>;
+pub type SeasonPicks = Vec<(Week, Option<WeekFeature>, Vec<WeekPicks>)>;
 
 #[derive(Debug, Default)]
 pub struct CapsulePicks {
@@ -30,16 +42,35 @@ pub struct CapsulePicks {
     pub poolerid: i64,
     pub name: String,
     pub repicks: i32,
+    pub cached: Option<u32>,
 
-    pub nfc_wins: [String; 4],
+    // Indexed by `Division` for the winners and by slot for the wildcards
+    pub nfc_wins: [Option<Team>; 4],
     pub nfc_wins_counts: [i32; 4],
-    pub nfc_wildcards: [String; 3],
+    pub nfc_wildcards: [Option<Team>; 3],
     pub nfc_wild_counts: [i32; 3],
 
-    pub afc_wins: [String; 4],
+    pub afc_wins: [Option<Team>; 4],
     pub afc_wins_counts: [i32; 4],
-    pub afc_wildcards: [String; 3],
+    pub afc_wildcards: [Option<Team>; 3],
     pub afc_wild_counts: [i32; 3],
+
+    // Slots changed through a repick since the capsule was first filed
+    pub nfc_wins_repicked: [bool; 4],
+    pub nfc_wild_repicked: [bool; 3],
+    pub afc_wins_repicked: [bool; 4],
+    pub afc_wild_repicked: [bool; 3],
+    pub nfc_top_seed_repicked: bool,
+    pub afc_top_seed_repicked: bool,
+    pub champion_repicked: bool,
+
+    // Seeding predictions, left empty on capsules filed before they existed
+    pub nfc_top_seed: Option<Team>,
+    pub nfc_top_seed_count: i32,
+    pub afc_top_seed: Option<Team>,
+    pub afc_top_seed_count: i32,
+    pub champion: Option<Team>,
+    pub champion_count: i32,
 }
 
 impl Display for WeekPicks {
@@ -86,35 +117,465 @@ impl Debug for WeekFeature {
     }
 }
 
+#[derive(Debug)]
+pub struct SurvivorPick {
+    pub id: i64,
+    pub season: u16,
+    pub week: i64,
+    pub poolerid: i64,
+    pub name: String,
+    pub matchid: String,
+    // None for a missed week, stored as "NA"
+    pub team: Option<Team>,
+    pub outcome: Option<u32>,
+}
+
+#[derive(Debug)]
+pub struct CapsuleChange {
+    pub ctype: i32,
+    pub conference: i32,
+    pub division: i32,
+    pub slot: i32,
+    pub old_team: Team,
+    pub new_team: Team,
+    pub changed_at: String,
+}
+
+// What a pick link can be used for, the web app checks it on every page
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenPurpose {
+    Picks,
+    PicksEdit,
+    Capsule,
+    CapsuleRepicks,
+}
+
+impl TokenPurpose {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenPurpose::Picks => "picks",
+            TokenPurpose::PicksEdit => "picks-edit",
+            TokenPurpose::Capsule => "capsule",
+            TokenPurpose::CapsuleRepicks => "capsule-repicks",
+        }
+    }
+
+    // Asking the bot again hands out a fresh link, these only need to cover a normal delay
+    pub fn lifetime(&self) -> TimeDelta {
+        match self {
+            TokenPurpose::Picks | TokenPurpose::PicksEdit => TimeDelta::days(3),
+            TokenPurpose::Capsule | TokenPurpose::CapsuleRepicks => TimeDelta::days(7),
+        }
+    }
+}
+
+impl FromStr for TokenPurpose {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<TokenPurpose> {
+        [TokenPurpose::Picks, TokenPurpose::PicksEdit, TokenPurpose::Capsule, TokenPurpose::CapsuleRepicks]
+            .into_iter()
+            .find(|p| p.as_str() == s)
+            .ok_or_else(|| anyhow!("Unknown token purpose '{}'", s))
+    }
+}
+
+// ~190 bits, the token is the only thing standing between a link and someone's picks
+const TOKEN_LENGTH: usize = 32;
+
+#[derive(Debug)]
+pub struct PickToken {
+    pub poolerid: i64,
+    pub season: u16,
+    pub week: i64,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub struct Pooler {
+    pub id: i64,
+    pub name: String,
+    pub favteam: Option<Team>,
+    pub poolid: i64,
+    pub discordid: Option<i64>,
+    pub avatar: Option<String>,
+    // Capsule slots the pooler can still change
+    pub repicks: i32,
+}
+
+// One row of `capsules`, as filed by the pick page
+#[derive(Debug, Clone)]
+pub struct CapsuleSlot {
+    pub kind: i32,
+    pub conference: i32,
+    pub division: i32,
+    pub slot: i32,
+    pub team: Team,
+}
+
+// Who a write is attributed to in `audit_log`, stored as the Discord id or NULL
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Actor {
+    User(i64),
+    Bot,
+}
+
+impl Actor {
+    fn discordid(&self) -> Option<i64> {
+        match self {
+            Actor::User(id) => Some(*id),
+            Actor::Bot => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    FavTeam,
+    Feature,
+    ScoreCache,
+    PicksEdit,
+    PicksSubmitted,
+    AutoPicks,
+    CapsuleSubmitted,
+    CapsuleRepicked,
+    TokenIssued,
+    TokenConsumed,
+    TokensPurged,
+    CalendarCache,
+    CapsuleScore,
+    CapsuleScoresCleared,
+    SurvivorPick,
+    SurvivorResolved,
+}
+
+impl AuditAction {
+    pub fn all() -> impl Iterator<Item = AuditAction> {
+        [
+            AuditAction::FavTeam,
+            AuditAction::Feature,
+            AuditAction::ScoreCache,
+            AuditAction::PicksEdit,
+            AuditAction::PicksSubmitted,
+            AuditAction::AutoPicks,
+            AuditAction::CapsuleSubmitted,
+            AuditAction::CapsuleRepicked,
+            AuditAction::TokenIssued,
+            AuditAction::TokenConsumed,
+            AuditAction::TokensPurged,
+            AuditAction::CalendarCache,
+            AuditAction::CapsuleScore,
+            AuditAction::CapsuleScoresCleared,
+            AuditAction::SurvivorPick,
+            AuditAction::SurvivorResolved,
+        ].into_iter()
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::FavTeam => "favteam",
+            AuditAction::Feature => "feature",
+            AuditAction::ScoreCache => "score_cache",
+            AuditAction::PicksEdit => "picks_edit",
+            AuditAction::PicksSubmitted => "picks_submitted",
+            AuditAction::AutoPicks => "auto_picks",
+            AuditAction::CapsuleSubmitted => "capsule_submitted",
+            AuditAction::CapsuleRepicked => "capsule_repicked",
+            AuditAction::TokenIssued => "token_issued",
+            AuditAction::TokenConsumed => "token_consumed",
+            AuditAction::TokensPurged => "tokens_purged",
+            AuditAction::CalendarCache => "calendar_cache",
+            AuditAction::CapsuleScore => "capsule_score",
+            AuditAction::CapsuleScoresCleared => "capsule_scores_cleared",
+            AuditAction::SurvivorPick => "survivor_pick",
+            AuditAction::SurvivorResolved => "survivor_resolved",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub actor: Option<i64>,
+    pub action: String,
+    pub target: String,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+    pub created_at: String,
+}
+
 impl DB {
-    pub async fn new() -> DB {
-        let db_url = env::var("DATABASE_URL")
-            .expect("![MAIN] Cannot find 'DATABASE_URL' in env");
+    pub async fn new(db_url: &str) -> DB {
+        DB { pool: SqlitePool::connect(db_url).await.unwrap() }
+    }
+
+    pub async fn close(&self) {
+        self.pool.close().await;
+    }
+
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
+    pub async fn ping(&self) -> Result<()> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
+    pub async fn fetch_pool_mode(&self, poolid: &i64) -> Result<PoolMode> {
+        let row = sqlx::query("
+                SELECT mode FROM pools
+                WHERE id = ?
+                ")
+            .bind(poolid)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(PoolMode::from_db(row.get("mode")))
+    }
+
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
+    pub async fn fetch_repick_weight(&self, poolid: &i64) -> Result<u32> {
+        let row = sqlx::query("
+                SELECT repick_weight FROM pools
+                WHERE id = ?
+                ")
+            .bind(poolid)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(row.get("repick_weight"))
+    }
+
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
+    pub async fn fetch_default_picks(&self, poolid: &i64) -> Result<DefaultPicks> {
+        let row = sqlx::query("
+                SELECT default_picks FROM pools
+                WHERE id = ?
+                ")
+            .bind(poolid)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(DefaultPicks::from_db(row.get("default_picks")))
+    }
+
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
+    pub async fn fetch_auto_pick_weight(&self, poolid: &i64) -> Result<u32> {
+        let row = sqlx::query("
+                SELECT auto_pick_weight FROM pools
+                WHERE id = ?
+                ")
+            .bind(poolid)
+            .fetch_one(&self.pool)
+            .await?;
 
-        DB { pool: SqlitePool::connect(db_url.as_str()).await.unwrap() }
+        Ok(row.get("auto_pick_weight"))
     }
 
-    pub async fn find_week(&self, poolid: &i64, season: &u16) -> Result<i64> {
-        let week = sqlx::query("
-                SELECT max(pk.week) as maxweek FROM picks as pk
-                LEFT JOIN poolers as pl ON pk.poolerid = pl.id
-                WHERE pl.poolid = ? AND season = ?
-                GROUP BY pk.poolerid
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
+    pub async fn fetch_pooler_favteams(&self, poolid: &i64) -> Result<HashMap<i64, Option<Team>>> {
+        let rows = sqlx::query("
+                SELECT id, favteam FROM poolers
+                WHERE poolid = ?
                 ")
             .bind(poolid)
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.iter()
+            .map(|row| Ok((row.get("id"), opt_team_from_row(row, "favteam")?)))
+            .collect()
+    }
+
+    // Returns false when the pooler submitted in the meantime, their picks are left alone
+    #[instrument(level = "debug", skip(self, picks, ranks), err(level = "debug"))]
+    pub async fn insert_auto_picks(
+        &self,
+        season: &u16,
+        week: &i64,
+        poolerid: &i64,
+        picks: &HashMap<String, Team>,
+        ranks: Option<&HashMap<String, u32>>) -> Result<bool> {
+
+        let mut tx = self.pool.begin().await?;
+        let res = sqlx::query("
+                INSERT INTO picks (season, week, poolerid, favteam)
+                SELECT ?, ?, id, NULLIF(favteam, '') FROM poolers WHERE id = ?
+                ON CONFLICT(season, week, poolerid) DO NOTHING
+                ")
             .bind(season)
-            .fetch_all(&self.pool).await?
-        .iter().fold( 0, |week, row| std::cmp::max(week, row.get("maxweek")) );
+            .bind(week)
+            .bind(poolerid)
+            .execute(&mut tx)
+            .await?;
+        if res.rows_affected() == 0 {
+            return Ok(false);
+        }
+        let pickid = res.last_insert_rowid();
+
+        for (matchid, team) in picks {
+            sqlx::query("
+                    INSERT INTO match_picks (pickid, matchid, team, confidence, auto)
+                    VALUES (?, ?, ?, ?, 1)
+                    ")
+                .bind(pickid)
+                .bind(matchid)
+                .bind(team.code())
+                .bind(ranks.and_then(|r| r.get(matchid)))
+                .execute(&mut tx)
+                .await?;
+        }
+
+        audit(&mut tx, Actor::Bot, AuditAction::AutoPicks, &format!("pick:{}", pickid),
+            None, Some(format!("{} picks for pooler:{} ({}/{})", picks.len(), poolerid, season, week))).await?;
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    // Returns false when the token was already used or the pooler submitted
+    // for this week some other way, nothing is written then. `autos` are the
+    // default picks for games that kicked off before the submission
+    #[allow(clippy::too_many_arguments)]
+    #[instrument(level = "debug", skip(self, token, picks, autos, ranks), err(level = "debug"))]
+    pub async fn submit_picks(
+        &self,
+        actor: Actor,
+        token: &str,
+        pick: &PickToken,
+        picks: &HashMap<String, Team>,
+        autos: &HashMap<String, Team>,
+        ranks: Option<&HashMap<String, u32>>,
+        featpick: Option<u32>) -> Result<bool> {
+
+        let mut tx = self.pool.begin().await?;
+        if !take_token(&mut tx, token, TokenPurpose::Picks).await? {
+            return Ok(false);
+        }
+
+        // The favorite team is kept as it is now, /equipe can change it later
+        let res = sqlx::query("
+                INSERT INTO picks (season, week, poolerid, featurepick, favteam)
+                SELECT ?, ?, id, ?, NULLIF(favteam, '') FROM poolers WHERE id = ?
+                ON CONFLICT(season, week, poolerid) DO NOTHING
+                ")
+            .bind(pick.season)
+            .bind(pick.week)
+            .bind(featpick)
+            .bind(pick.poolerid)
+            .execute(&mut tx)
+            .await?;
+        if res.rows_affected() == 0 {
+            return Ok(false);
+        }
+        let pickid = res.last_insert_rowid();
+
+        let rows = picks.iter().map(|p| (p, false)).chain(autos.iter().map(|p| (p, true)));
+        for ((matchid, team), auto) in rows {
+            sqlx::query("
+                    INSERT INTO match_picks (pickid, matchid, team, confidence, auto)
+                    VALUES (?, ?, ?, ?, ?)
+                    ")
+                .bind(pickid)
+                .bind(matchid)
+                .bind(team.code())
+                .bind(ranks.and_then(|r| r.get(matchid)))
+                .bind(auto)
+                .execute(&mut tx)
+                .await?;
+        }
+
+        audit(&mut tx, actor, AuditAction::PicksSubmitted, &format!("pick:{}", pickid),
+            None, Some(format!("{} picks ({} default) for pooler:{} ({}/{})",
+                picks.len() + autos.len(), autos.len(), pick.poolerid, pick.season, pick.week))).await?;
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    // Only rows that differ from `current` are written, they lose their default
+    // pick mark. The cached scores are cleared since they came from the old picks
+    #[instrument(level = "debug", skip(self, token, current, picks, ranks), err(level = "debug"))]
+    pub async fn edit_picks(
+        &self,
+        actor: Actor,
+        token: &str,
+        current: &WeekPicks,
+        picks: &HashMap<String, Team>,
+        ranks: Option<&HashMap<String, u32>>,
+        featpick: Option<u32>) -> Result<bool> {
+
+        let Some(pickid) = current.pickid else {
+            return Ok(false);
+        };
+
+        let mut tx = self.pool.begin().await?;
+        if !take_token(&mut tx, token, TokenPurpose::PicksEdit).await? {
+            return Ok(false);
+        }
+
+        let describe = |matchid: &str, team: Option<&Team>, rank: Option<&u32>| match rank {
+            Some(rank) => format!("{} {} {}", matchid, team.map_or("", Team::code), rank),
+            None => format!("{} {}", matchid, team.map_or("", Team::code)),
+        };
+
+        let mut matchids: Vec<_> = picks.keys().collect();
+        matchids.sort();
+
+        let (mut before, mut after) = (Vec::new(), Vec::new());
+        for matchid in matchids {
+            let old_team = current.picks.as_ref().and_then(|p| p.get(matchid));
+            let old_rank = current.confidences.as_ref().and_then(|r| r.get(matchid));
+            let (team, rank) = (picks.get(matchid), ranks.and_then(|r| r.get(matchid)));
+            if old_team == team && old_rank == rank {
+                continue;
+            }
 
-        Ok(week)
+            sqlx::query("
+                    UPDATE match_picks
+                    SET team = ?, confidence = ?, auto = 0
+                    WHERE pickid = ? AND matchid = ?
+                    ")
+                .bind(team.map(Team::code))
+                .bind(rank)
+                .bind(pickid)
+                .bind(matchid)
+                .execute(&mut tx)
+                .await?;
+
+            before.push(describe(matchid, old_team, old_rank));
+            after.push(describe(matchid, team, rank));
+        }
+
+        sqlx::query("
+                UPDATE picks
+                SET featurepick = ?, scorecache = NULL, featcache = NULL
+                WHERE id = ?
+                ")
+            .bind(featpick)
+            .bind(pickid)
+            .execute(&mut tx)
+            .await?;
+
+        if featpick != current.featpick {
+            before.push(format!("feature {:?}", current.featpick));
+            after.push(format!("feature {:?}", featpick));
+        }
+        if !after.is_empty() {
+            audit(&mut tx, actor, AuditAction::PicksEdit, &format!("pick:{}", pickid),
+                Some(before.join(", ")), Some(after.join(", "))).await?;
+        }
+        tx.commit().await?;
+
+        Ok(true)
     }
 
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
     pub async fn fetch_picks(&self, poolid: &i64, season: &u16, week: &i64) -> Result<Vec<WeekPicks>> {
         let rows = sqlx::query("
                 SELECT pl.id AS 'poolerid', pl.name,
-                   pk.id AS 'pickid', pk.featurepick, pk.scorecache, pk.featcache,
-                   mp.matchid, mp.team,
-                   COUNT(*) OVER (PARTITION BY mp.matchid, mp.team) AS 'pick_count'
+                   pk.id AS 'pickid', pk.featurepick, pk.scorecache, pk.featcache, pk.favteam,
+                   mp.matchid, mp.team, mp.confidence, mp.auto,
+                   SUM(CASE WHEN mp.auto = 0 THEN 1 ELSE 0 END) OVER (PARTITION BY mp.matchid, mp.team) AS 'pick_count'
                 FROM poolers AS pl
                 LEFT JOIN picks AS pk ON pk.poolerid = pl.id AND pk.season = ? AND pk.week = ?
                 LEFT JOIN match_picks AS mp ON mp.pickid = pk.id
@@ -129,6 +590,7 @@ impl DB {
         let mut by_pooler: BTreeMap<i64, WeekPicks> = BTreeMap::new();
         for row in &rows {
             let poolerid: i64 = row.get("poolerid");
+            let favteam = opt_team_from_row(row, "favteam")?;
             let entry = by_pooler.entry(poolerid).or_insert_with(|| {
                 let pickid: Option<i64> = row.get("pickid");
                 WeekPicks {
@@ -138,15 +600,24 @@ impl DB {
                     week: *week,
                     picks: pickid.map(|_| HashMap::new()),
                     counts: pickid.map(|_| HashMap::new()),
+                    confidences: None,
+                    autos: HashSet::new(),
                     featpick: row.get("featurepick"),
                     cached: row.get("scorecache"),
                     featcached: row.get("featcache"),
+                    favteam,
                 }
             });
 
             if let Some(matchid) = row.get::<Option<String>, _>("matchid") {
                 if let Some(map) = entry.picks.as_mut() {
-                    map.insert(matchid.clone(), row.get("team"));
+                    map.insert(matchid.clone(), team_from_row(row, "team")?);
+                }
+                if let Some(rank) = row.get::<Option<u32>, _>("confidence") {
+                    entry.confidences.get_or_insert_with(HashMap::new).insert(matchid.clone(), rank);
+                }
+                if row.get::<bool, _>("auto") {
+                    entry.autos.insert(matchid.clone());
                 }
                 if let Some(count) = entry.counts.as_mut() {
                     count.insert(matchid, row.get("pick_count"));
@@ -157,12 +628,13 @@ impl DB {
         Ok(by_pooler.into_values().collect())
     }
 
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
     pub async fn fetch_pick(&self, season: &u16, week: &i64, poolerid: &i64) -> Result<WeekPicks> {
         let rows = sqlx::query("
                 SELECT pk.id AS 'pickid', pl.id AS 'poolerid', pl.name,
-                   pk.featurepick, pk.scorecache, pk.featcache,
-                   mp.matchid, mp.team,
-                   COUNT(*) OVER (PARTITION BY mp.matchid, mp.team) AS 'pick_count'
+                   pk.featurepick, pk.scorecache, pk.featcache, pk.favteam,
+                   mp.matchid, mp.team, mp.confidence, mp.auto,
+                   SUM(CASE WHEN mp.auto = 0 THEN 1 ELSE 0 END) OVER (PARTITION BY mp.matchid, mp.team) AS 'pick_count'
                 FROM picks AS pk
                 JOIN poolers AS pl ON pl.id = pk.poolerid
                 LEFT JOIN match_picks AS mp ON mp.pickid = pk.id
@@ -179,16 +651,25 @@ impl DB {
             None => return Err(anyhow::anyhow!("no picks for pooler {poolerid} (season {season}, week {week})")),
         };
 
-        let mut map = HashMap::<String, String>::new();
+        let mut map = HashMap::<String, Team>::new();
         let mut map_counts = HashMap::<String, i32>::new();
+        let mut map_ranks = HashMap::<String, u32>::new();
+        let mut autos = HashSet::<String>::new();
         for row in &rows {
             if let Some(matchid) = row.get::<Option<String>, _>("matchid") {
-                map.insert(matchid.clone(), row.get("team"));
+                map.insert(matchid.clone(), team_from_row(row, "team")?);
+                if let Some(rank) = row.get::<Option<u32>, _>("confidence") {
+                    map_ranks.insert(matchid.clone(), rank);
+                }
+                if row.get::<bool, _>("auto") {
+                    autos.insert(matchid.clone());
+                }
                 map_counts.insert(matchid, row.get("pick_count"));
             }
         }
         let picks = if map.is_empty() { None } else { Some(map) };
         let counts = if map_counts.is_empty() { None } else { Some(map_counts) };
+        let confidences = if map_ranks.is_empty() { None } else { Some(map_ranks) };
 
         Ok(WeekPicks {
             pickid: first.get("pickid"),
@@ -197,12 +678,16 @@ impl DB {
             week: *week,
             picks,
             counts,
+            confidences,
+            autos,
             featpick: first.get("featurepick"),
             cached: first.get("scorecache"),
-            featcached: first.get("featcache")
+            featcached: first.get("featcache"),
+            favteam: opt_team_from_row(first, "favteam")?,
         })
     }
 
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
     pub async fn fetch_season(&self, poolid: &i64, season: &u16) -> Result<(SeasonPicks, usize)> {
         let mut feats: HashMap<_, _> = sqlx::query("
             SELECT season, week, type, target, match FROM features
@@ -225,9 +710,9 @@ impl DB {
 
         let season_rows = sqlx::query("
                     SELECT pk.id as 'pickid', pl.id as 'poolerid', pl.name,
-                        pk.week, pk.scorecache, pk.featurepick, pk.featcache,
-                        mp.matchid, mp.team,
-                        COUNT(*) OVER (PARTITION BY mp.matchid, mp.team) AS 'pick_count'
+                        pk.week, pk.scorecache, pk.featurepick, pk.featcache, pk.favteam,
+                        mp.matchid, mp.team, mp.confidence, mp.auto,
+                        SUM(CASE WHEN mp.auto = 0 THEN 1 ELSE 0 END) OVER (PARTITION BY mp.matchid, mp.team) AS 'pick_count'
                     FROM picks AS pk
                     JOIN poolers AS pl ON pl.id = pk.poolerid AND pl.poolid = ?
                     LEFT JOIN match_picks AS mp ON mp.pickid = pk.id
@@ -242,6 +727,7 @@ impl DB {
         for row in &season_rows {
             let week: i64 = row.get("week");
             let poolerid: i64 = row.get("poolerid");
+            let favteam = opt_team_from_row(row, "favteam")?;
             let entry = by_key.entry((week, poolerid)).or_insert_with(|| {
                 let pickid: Option<i64> = row.get("pickid");
                 WeekPicks {
@@ -251,15 +737,24 @@ impl DB {
                     week,
                     picks: pickid.map(|_| HashMap::new()),
                     counts: pickid.map(|_| HashMap::new()),
+                    confidences: None,
+                    autos: HashSet::new(),
                     featpick: row.get("featurepick"),
                     cached: row.get("scorecache"),
                     featcached: row.get("featcache"),
+                    favteam,
                 }
             });
 
             if let Some(matchid) = row.get::<Option<String>, _>("matchid") {
                 if let Some(map) = entry.picks.as_mut() {
-                    map.insert(matchid.clone(), row.get("team"));
+                    map.insert(matchid.clone(), team_from_row(row, "team")?);
+                }
+                if let Some(rank) = row.get::<Option<u32>, _>("confidence") {
+                    entry.confidences.get_or_insert_with(HashMap::new).insert(matchid.clone(), rank);
+                }
+                if row.get::<bool, _>("auto") {
+                    entry.autos.insert(matchid.clone());
                 }
                 if let Some(count) = entry.counts.as_mut() {
                     count.insert(matchid, row.get("pick_count"));
@@ -268,15 +763,20 @@ impl DB {
         }
 
         let season = by_key.into_values().fold(SeasonPicks::new(), |mut acc, e| {
-            if let Some(week) = acc.iter_mut().find(|a| a.0 == e.week) {
-                week.2.push(e);
+            let week = match Week::try_from(e.week) {
+                Ok(week) => week,
+                Err(err) => {
+                    warn!("Skipping picks of pooler {} : {}", e.poolerid, err);
+                    return acc;
+                },
+            };
+
+            if let Some(entry) = acc.iter_mut().find(|a| a.0 == week) {
+                entry.2.push(e);
             }
             else {
-                if let Some(feat) = feats.remove(&e.week) {
-                    acc.push( (e.week, Some(feat), vec![e]) );
-                } else {
-                    acc.push( (e.week, None, vec![e]) );
-                }
+                let feat = feats.remove(&e.week);
+                acc.push( (week, feat, vec![e]) );
             }
             acc
         });
@@ -285,47 +785,175 @@ impl DB {
         Ok((season, week_count))
     }
 
-    pub async fn issue_pick_token(&self, season: u16, week: i64, poolerid: i64) -> Result<i64> {
-        let row = sqlx::query("
-                INSERT INTO pick_tokens (poolerid, token, season, week)
-                VALUES (?, random() & 0x7fffffffffffffff, ?, ?)
-                ON CONFLICT(poolerid) DO UPDATE SET
-                    token  = excluded.token,
-                    season = excluded.season,
-                    week   = excluded.week
-                RETURNING token;
+    // A pooler can hold one token per purpose and week, asking again replaces it
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
+    pub async fn issue_pick_token(&self, actor: Actor, purpose: TokenPurpose, season: u16, week: i64, poolerid: i64) -> Result<String> {
+        let token = Alphanumeric.sample_string(&mut OsRng, TOKEN_LENGTH);
+        let expires_at = (Utc::now() + purpose.lifetime()).to_rfc3339_opts(SecondsFormat::Secs, true);
+
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("
+                INSERT INTO pick_tokens (token, poolerid, purpose, season, week, expires_at)
+                VALUES (?, ?, ?, ?, ?, ?)
+                ON CONFLICT(poolerid, purpose, season, week) DO UPDATE SET
+                    token      = excluded.token,
+                    expires_at = excluded.expires_at;
                 ")
+            .bind(&token)
             .bind(poolerid)
+            .bind(purpose.as_str())
             .bind(season)
             .bind(week)
-            .fetch_one(&self.pool)
+            .bind(&expires_at)
+            .execute(&mut tx)
+            .await?;
+
+        // The token itself never goes in the log
+        audit(&mut tx, actor, AuditAction::TokenIssued, &format!("pooler:{}", poolerid),
+            None, Some(format!("{} {}/{} until {}", purpose.as_str(), season, week, expires_at))).await?;
+        tx.commit().await?;
+
+        Ok(token)
+    }
+
+    // `None` for an unknown or expired token, or one issued for something else
+    #[instrument(level = "debug", skip(self, token), err(level = "debug"))]
+    pub async fn validate_pick_token(&self, token: &str, purpose: TokenPurpose) -> Result<Option<PickToken>> {
+        let row = sqlx::query("
+                SELECT poolerid, season, week, expires_at FROM pick_tokens
+                WHERE token = ? AND purpose = ?
+                    AND datetime(expires_at) > datetime('now')
+                ")
+            .bind(token)
+            .bind(purpose.as_str())
+            .fetch_optional(&self.pool)
             .await?;
 
-        Ok(row.get("token"))
+        match row {
+            Some(row) => {
+                let expires_at: String = row.get("expires_at");
+                Ok(Some(PickToken {
+                    poolerid: row.get("poolerid"),
+                    season: row.get("season"),
+                    week: row.get("week"),
+                    expires_at: DateTime::parse_from_rfc3339(&expires_at)?.with_timezone(&Utc),
+                }))
+            },
+            None => Ok(None),
+        }
     }
 
-    pub async fn cache_results(&self, pickid: &i64, score: &u32, featscore: &u32) -> Result<bool> {
-        match sqlx::query("
-                UPDATE picks
-                SET scorecache = ?, featcache = ?
-                WHERE id = ?
+    #[instrument(level = "debug", skip(self, token), err(level = "debug"))]
+    pub async fn consume_pick_token(&self, token: &str) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+        let row = sqlx::query("
+                DELETE FROM pick_tokens WHERE token = ?
+                RETURNING poolerid, purpose, season, week
                 ")
-            .bind(score)
-            .bind(featscore)
-            .bind(pickid)
-            .execute(&self.pool)
-            .await {
-                Ok(r) => {
-                    println!("[DB] Successful score cache updated: rows affected {}", r.rows_affected());
-                    Ok(true)
-                },
-                Err(e) => {
-                    println!("[DB] Could not update score cache: {}", e);
-                    Ok(false)
-                }
+            .bind(token)
+            .fetch_optional(&mut tx)
+            .await?;
+
+        let Some(row) = row else {
+            return Ok(false);
+        };
+        let (poolerid, purpose, season, week): (i64, String, i64, i64) =
+            (row.get("poolerid"), row.get("purpose"), row.get("season"), row.get("week"));
+        audit(&mut tx, Actor::Bot, AuditAction::TokenConsumed, &format!("pooler:{}", poolerid),
+            Some(format!("{} {}/{}", purpose, season, week)), None).await?;
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
+    pub async fn purge_pick_tokens(&self) -> Result<u64> {
+        let mut tx = self.pool.begin().await?;
+        let res = sqlx::query("
+                DELETE FROM pick_tokens
+                WHERE datetime(expires_at) <= datetime('now')
+                ")
+            .execute(&mut tx)
+            .await?;
+
+        let count = res.rows_affected();
+        if count > 0 {
+            audit(&mut tx, Actor::Bot, AuditAction::TokensPurged, "pick_tokens",
+                Some(count.to_string()), None).await?;
+        }
+        tx.commit().await?;
+
+        Ok(count)
+    }
+
+    // Recomputed on every /resultat, only an actual change is written and logged
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
+    pub async fn cache_results(&self, pickid: &i64, score: &u32, featscore: &u32) -> Result<bool> {
+        let outcome: Result<bool> = async {
+            let mut tx = self.pool.begin().await?;
+            let row = sqlx::query("SELECT scorecache, featcache FROM picks WHERE id = ?")
+                .bind(pickid)
+                .fetch_one(&mut tx)
+                .await?;
+            let old: (Option<u32>, Option<u32>) = (row.get("scorecache"), row.get("featcache"));
+            if old == (Some(*score), Some(*featscore)) {
+                return Ok(false);
+            }
+
+            sqlx::query("
+                    UPDATE picks
+                    SET scorecache = ?, featcache = ?
+                    WHERE id = ?
+                    ")
+                .bind(score)
+                .bind(featscore)
+                .bind(pickid)
+                .execute(&mut tx)
+                .await?;
+
+            let old = old.0.map(|s| format!("{}/{}", s, old.1.unwrap_or_default()));
+            audit(&mut tx, Actor::Bot, AuditAction::ScoreCache, &format!("pick:{}", pickid),
+                old, Some(format!("{}/{}", score, featscore))).await?;
+            tx.commit().await?;
+            Ok(true)
+        }.await;
+
+        match outcome {
+            Ok(changed) => {
+                debug!("Successful score cache updated: changed {}", changed);
+                Ok(true)
+            },
+            Err(e) => {
+                error!("Could not update score cache: {}", e);
+                Ok(false)
+            }
         }
     }
 
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
+    pub async fn fetch_pooler(&self, poolerid: &i64) -> Result<Pooler> {
+        let row = sqlx::query("
+                SELECT pl.id, pl.name, pl.favteam, pl.poolid, pl.repicks, u.discordid, u.avatar
+                FROM poolers AS pl
+                LEFT JOIN users AS u ON u.id = pl.userid
+                WHERE pl.id = ?
+                ")
+            .bind(poolerid)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(Pooler {
+            id: row.get("id"),
+            name: row.get("name"),
+            favteam: opt_team_from_row(&row, "favteam")?,
+            poolid: row.get("poolid"),
+            discordid: row.get("discordid"),
+            avatar: row.get("avatar"),
+            repicks: row.get("repicks"),
+        })
+    }
+
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
     pub async fn fetch_poolerid(&self, discordid: &i64,) -> Result<i64> {
         let row = sqlx::query("
                 SELECT p.id FROM poolers AS p
@@ -340,7 +968,22 @@ impl DB {
         Ok(row.get(0))
     }
 
-    pub async fn fetch_favteam(&self, discordid: &i64) -> Result<(String, String)> {
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
+    pub async fn fetch_pooler_names(&self, poolid: &i64) -> Result<Vec<String>> {
+        let rows = sqlx::query("
+                SELECT name FROM poolers
+                WHERE poolid = ?
+                ORDER BY name
+                ")
+            .bind(poolid)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.iter().map(|row| row.get("name")).collect())
+    }
+
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
+    pub async fn fetch_favteam(&self, discordid: &i64) -> Result<(String, Option<Team>)> {
         let row = sqlx::query("
                 SELECT p.name, p.favteam FROM users AS u
                 JOIN poolers AS p
@@ -351,33 +994,53 @@ impl DB {
             .fetch_one(&self.pool)
             .await?;
 
-        Ok((row.get(0), row.get(1)))
+        Ok((row.get("name"), opt_team_from_row(&row, "favteam")?))
     }
 
-    pub async fn update_favteam(&self, discordid: &i64, team: &str) -> Result<bool> {
-        match sqlx::query("
-                UPDATE poolers
-                SET favteam = ?
-                WHERE userid IN (
-                    SELECT id from users
-                    WHERE discordid = ?
-                )
-                ")
-            .bind(team)
-            .bind(discordid)
-            .execute(&self.pool)
-            .await {
-                Ok(r) => {
-                    println!("[DB] Successful favorite team updated: rows affected {}", r.rows_affected());
-                    Ok(true)
-                },
-                Err(e) => {
-                    println!("[DB] Could not update score cache: {}", e);
-                    Ok(false)
-                }
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
+    pub async fn update_favteam(&self, discordid: &i64, team: &Team) -> Result<bool> {
+        let outcome: Result<u64> = async {
+            let mut tx = self.pool.begin().await?;
+            let rows = sqlx::query("
+                    SELECT p.id, p.favteam FROM poolers AS p
+                    JOIN users AS u
+                    ON u.id = p.userid
+                    WHERE u.discordid = ?
+                    ")
+                .bind(discordid)
+                .fetch_all(&mut tx)
+                .await?;
+
+            for row in &rows {
+                let poolerid: i64 = row.get("id");
+                let old: Option<String> = row.get("favteam");
+
+                sqlx::query("UPDATE poolers SET favteam = ? WHERE id = ?")
+                    .bind(team.code())
+                    .bind(poolerid)
+                    .execute(&mut tx)
+                    .await?;
+                audit(&mut tx, Actor::User(*discordid), AuditAction::FavTeam, &format!("pooler:{}", poolerid),
+                    old, Some(team.code().to_owned())).await?;
+            }
+
+            tx.commit().await?;
+            Ok(rows.len() as u64)
+        }.await;
+
+        match outcome {
+            Ok(count) => {
+                debug!("Successful favorite team updated: rows affected {}", count);
+                Ok(true)
+            },
+            Err(e) => {
+                error!("Could not update favorite team: {}", e);
+                Ok(false)
+            }
         }
     }
 
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
     pub async fn fetch_feature(&self, season: u16, week: i64) -> Result<WeekFeature> {
         let row = sqlx::query("
             SELECT ft.season, ft.week, ft.type, ft.target, ft.match FROM features AS ft
@@ -396,52 +1059,57 @@ impl DB {
         })
     }
 
-    pub async fn set_feature(&self, season: u16, week: i64, target: i64, matchid: &String) -> Result<bool> {
-        match self.fetch_feature(season, week).await {
-            Ok(_) => {
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
+    pub async fn set_feature(&self, actor: Actor, season: u16, week: i64, target: i64, matchid: &String) -> Result<bool> {
+        let previous = self.fetch_feature(season, week).await.ok();
+
+        let outcome: Result<()> = async {
+            let mut tx = self.pool.begin().await?;
+            match previous {
                 // feature for season/week found, UPDATE existing
-                let outcome = sqlx::query("
+                Some(_) => sqlx::query("
                         UPDATE features
                         SET type = ?, target = ?, match = ?
                         WHERE season = ? AND week = ?;
-                ")
-                .bind(0)
-                .bind(target)
-                .bind(matchid)
-                .bind(season)
-                .bind(week)
-                .execute(&self.pool)
-                .await;
-
-                //TODO: Probably need better error handling here
-                match outcome {
-                    Ok(_) => Ok(true),
-                    Err(_) => Ok(false),
-                }
-            }
-            Err(_) => {
+                    ")
+                    .bind(0)
+                    .bind(target)
+                    .bind(matchid)
+                    .bind(season)
+                    .bind(week)
+                    .execute(&mut tx)
+                    .await?,
                 // Could not find feature for season/week, INSERT new
-                let outcome = sqlx::query("
+                None => sqlx::query("
                         INSERT INTO features (season, week, type, target, match)
                         VALUES (?, ?, ?, ?, ?);
-                ")
-                .bind(season)
-                .bind(week)
-                .bind(0)
-                .bind(target)
-                .bind(matchid)
-                .execute(&self.pool)
-                .await;
+                    ")
+                    .bind(season)
+                    .bind(week)
+                    .bind(0)
+                    .bind(target)
+                    .bind(matchid)
+                    .execute(&mut tx)
+                    .await?,
+            };
 
-                //TODO: Probably need better error handling here
-                match outcome {
-                    Ok(_) => Ok(true),
-                    Err(_) => Ok(false),
-                }
+            audit(&mut tx, actor, AuditAction::Feature, &format!("feature:{}/{}", season, week),
+                previous.as_ref().map(|f| format!("{} target {}", f.matchid, f.target)),
+                Some(format!("{} target {}", matchid, target))).await?;
+            tx.commit().await?;
+            Ok(())
+        }.await;
+
+        match outcome {
+            Ok(_) => Ok(true),
+            Err(e) => {
+                error!("Could not set feature: {}", e);
+                Ok(false)
             }
         }
     }
 
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
     pub async fn fetch_pooler_capsule(&self, discordid: &i64, season: u16) -> Result<Option<CapsulePicks>> {
         let prow = sqlx::query("
                 SELECT p.id, p.name, p.repicks FROM users AS u
@@ -457,27 +1125,30 @@ impl DB {
         let repicks: i32 = prow.get("repicks");
 
         let rows: Vec<SqliteRow> = sqlx::query("
-                SELECT type, conference, division, slot, team,
-                       COUNT(*) OVER (PARTITION BY season, type, conference, division, team) AS pick_count
-                FROM capsules
-                WHERE poolerid = ? AND season = ?
+                SELECT c.type, c.conference, c.division, c.slot, c.team,
+                       EXISTS (SELECT 1 FROM capsule_history AS h WHERE h.capsuleid = c.id) AS repicked,
+                       COUNT(*) OVER (PARTITION BY c.season, c.type, c.conference, c.division, c.team) AS pick_count
+                FROM capsules AS c
+                WHERE c.poolerid = ? AND c.season = ?
                 ")
             .bind(poolerid)
             .bind(season)
             .fetch_all(&self.pool)
             .await?;
 
+        // 14 rows for the original capsule, 17 once seeding predictions are in
         match rows.len() {
             0 => Ok(None),
-            14 => {
+            14 | 17 => {
                 let mut capsule = CapsulePicks { season, poolerid, name, repicks, ..Default::default() };
-                for row in &rows { populate_capsule(&mut capsule, row); }
+                for row in &rows { populate_capsule(&mut capsule, row)?; }
                 Ok(Some(capsule))
             },
-            n => panic!("[DB] pooler {poolerid} has {n} capsule rows for season {season} (expected 0 or 14)"),
+            n => Err(anyhow!("Pooler {} has {} capsule rows for season {} (expected 0, 14 or 17)", poolerid, n, season)),
         }
     }
 
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
     pub async fn fetch_capsule(&self, season: &u16, poolid: &i64) -> Result<HashMap<i64, CapsulePicks>> {
         let poolerids: Vec<i64>= sqlx::query("
                 SELECT id FROM poolers
@@ -491,10 +1162,12 @@ impl DB {
 
         let mut qb = QueryBuilder::new("
                 SELECT c.season, c.poolerid, p.name, p.repicks, c.type, c.conference,
-                       c.division, c.slot, c.team,
+                       c.division, c.slot, c.team, cs.score AS scorecache,
+                       EXISTS (SELECT 1 FROM capsule_history AS h WHERE h.capsuleid = c.id) AS repicked,
                        COUNT(*) OVER (PARTITION BY c.season, c.type, c.conference, c.division, c.team) AS pick_count
                 FROM capsules AS c
                 JOIN poolers AS p ON p.id = c.poolerid
+                LEFT JOIN capsule_scores AS cs ON cs.poolerid = c.poolerid AND cs.season = c.season
                 WHERE c.season = 
                 ");
         qb.push_bind(*season);
@@ -512,42 +1185,622 @@ impl DB {
             let poolerid: i64 = row.get("poolerid");
             let name: String = row.get("name");
             let repicks: i32 = row.get("repicks");
+            let cached: Option<u32> = row.get("scorecache");
 
-            let capsule = capsules.entry(poolerid).or_insert_with(|| { CapsulePicks { season: *season, poolerid, name, repicks, ..Default::default() } });
-            populate_capsule(capsule, row);
+            let capsule = capsules.entry(poolerid).or_insert_with(|| { CapsulePicks { season: *season, poolerid, name, repicks, cached, ..Default::default() } });
+            populate_capsule(capsule, row)?;
         }
 
         Ok(capsules)
     }
+
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
+    pub async fn fetch_capsule_lock(&self, season: &u16) -> Result<Option<DateTime<Utc>>> {
+        let row = sqlx::query("
+                SELECT locked_at FROM capsule_locks
+                WHERE season = ?
+                ")
+            .bind(season)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        match row {
+            Some(row) => {
+                let locked_at: String = row.get("locked_at");
+                Ok(Some(DateTime::parse_from_rfc3339(&locked_at)?.with_timezone(&Utc)))
+            },
+            None => Ok(None),
+        }
+    }
+
+    // Same as `submit_picks`, false when the token was used or a capsule is already filed
+    #[instrument(level = "debug", skip(self, token, slots), err(level = "debug"))]
+    pub async fn submit_capsule(
+        &self,
+        actor: Actor,
+        token: &str,
+        pick: &PickToken,
+        slots: &[CapsuleSlot]) -> Result<bool> {
+
+        let mut tx = self.pool.begin().await?;
+        if !take_token(&mut tx, token, TokenPurpose::Capsule).await? {
+            return Ok(false);
+        }
+
+        let filed = sqlx::query("
+                SELECT 1 FROM capsules
+                WHERE season = ? AND poolerid = ?
+                ")
+            .bind(pick.season)
+            .bind(pick.poolerid)
+            .fetch_optional(&mut tx)
+            .await?;
+        if filed.is_some() {
+            return Ok(false);
+        }
+
+        for s in slots {
+            sqlx::query("
+                    INSERT INTO capsules (season, poolerid, type, conference, division, slot, team)
+                    VALUES (?, ?, ?, ?, ?, ?, ?)
+                    ")
+                .bind(pick.season)
+                .bind(pick.poolerid)
+                .bind(s.kind)
+                .bind(s.conference)
+                .bind(s.division)
+                .bind(s.slot)
+                .bind(s.team.code())
+                .execute(&mut tx)
+                .await?;
+        }
+
+        audit(&mut tx, actor, AuditAction::CapsuleSubmitted, &format!("pooler:{}", pick.poolerid),
+            None, Some(format!("{} picks for season {}", slots.len(), pick.season))).await?;
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    // The capsule's rows as filed, see `submit_capsule`
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
+    pub async fn fetch_capsule_slots(&self, poolerid: &i64, season: &u16) -> Result<Vec<CapsuleSlot>> {
+        let rows = sqlx::query("
+                SELECT type, conference, division, slot, team
+                FROM capsules
+                WHERE poolerid = ? AND season = ?
+                ORDER BY type, conference, division, slot
+                ")
+            .bind(poolerid)
+            .bind(season)
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.iter().map(|row| Ok(CapsuleSlot {
+            kind: row.get("type"),
+            conference: row.get("conference"),
+            division: row.get("division"),
+            slot: row.get("slot"),
+            team: team_from_row(row, "team")?,
+        })).collect()
+    }
+
+    // `changes` are the slots whose team changed, each one costs a repick and
+    // is logged in `capsule_history`. Returns false when the token was already
+    // used or the pooler doesn't have enough repicks left, nothing is written then
+    #[instrument(level = "debug", skip(self, token, changes), err(level = "debug"))]
+    pub async fn repick_capsule(
+        &self,
+        actor: Actor,
+        token: &str,
+        pick: &PickToken,
+        changes: &[CapsuleSlot]) -> Result<bool> {
+
+        let mut tx = self.pool.begin().await?;
+        if !take_token(&mut tx, token, TokenPurpose::CapsuleRepicks).await? {
+            return Ok(false);
+        }
+
+        let spent = sqlx::query("
+                UPDATE poolers
+                SET repicks = repicks - ?1
+                WHERE id = ?2 AND repicks >= ?1
+                ")
+            .bind(changes.len() as i64)
+            .bind(pick.poolerid)
+            .execute(&mut tx)
+            .await?;
+        if spent.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        let (mut before, mut after) = (Vec::new(), Vec::new());
+        for s in changes {
+            // The champion (type 3) is filed under its team's conference, which can change with it
+            let row = sqlx::query("
+                    SELECT id, team FROM capsules
+                    WHERE season = ? AND poolerid = ? AND type = ? AND division = ? AND slot = ?
+                        AND (type = 3 OR conference = ?)
+                    ")
+                .bind(pick.season)
+                .bind(pick.poolerid)
+                .bind(s.kind)
+                .bind(s.division)
+                .bind(s.slot)
+                .bind(s.conference)
+                .fetch_one(&mut tx)
+                .await?;
+            let (capsuleid, old_team): (i64, String) = (row.get("id"), row.get("team"));
+
+            sqlx::query("UPDATE capsules SET team = ?, conference = ? WHERE id = ?")
+                .bind(s.team.code())
+                .bind(s.conference)
+                .bind(capsuleid)
+                .execute(&mut tx)
+                .await?;
+
+            sqlx::query("
+                    INSERT INTO capsule_history (capsuleid, season, poolerid, type, conference, division, slot, old_team, new_team)
+                    VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+                    ")
+                .bind(capsuleid)
+                .bind(pick.season)
+                .bind(pick.poolerid)
+                .bind(s.kind)
+                .bind(s.conference)
+                .bind(s.division)
+                .bind(s.slot)
+                .bind(&old_team)
+                .bind(s.team.code())
+                .execute(&mut tx)
+                .await?;
+
+            before.push(old_team);
+            after.push(s.team.code());
+        }
+
+        audit(&mut tx, actor, AuditAction::CapsuleRepicked, &format!("pooler:{}", pick.poolerid),
+            Some(before.join(", ")), Some(after.join(", "))).await?;
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    // Seasons the pool has picks or capsules for
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
+    pub async fn fetch_pool_seasons(&self, poolid: &i64) -> Result<Vec<u16>> {
+        let rows = sqlx::query("
+                SELECT pk.season FROM picks AS pk
+                JOIN poolers AS pl ON pl.id = pk.poolerid
+                WHERE pl.poolid = ?1
+                UNION
+                SELECT c.season FROM capsules AS c
+                JOIN poolers AS pl ON pl.id = c.poolerid
+                WHERE pl.poolid = ?1
+                ORDER BY 1
+                ")
+            .bind(poolid)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
+    pub async fn fetch_calendar(&self, season: &u16) -> Result<Vec<WeekDates>> {
+        let rows = sqlx::query("
+                SELECT week, start_date, end_date FROM calendar
+                WHERE season = ?
+                ORDER BY week
+                ")
+            .bind(season)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut calendar = Vec::with_capacity(rows.len());
+        for row in rows {
+            let start: String = row.get("start_date");
+            let end: String = row.get("end_date");
+            calendar.push(WeekDates {
+                week: Week::try_from(row.get::<i64, _>("week"))?,
+                start: DateTime::parse_from_rfc3339(&start)?.with_timezone(&Utc),
+                end: DateTime::parse_from_rfc3339(&end)?.with_timezone(&Utc),
+            });
+        }
+
+        Ok(calendar)
+    }
+
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
+    pub async fn cache_calendar(&self, season: &u16, calendar: &[WeekDates]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        let previous: i64 = sqlx::query("SELECT COUNT(*) FROM calendar WHERE season = ?")
+            .bind(season)
+            .fetch_one(&mut tx)
+            .await?
+            .get(0);
+
+        for w in calendar {
+            sqlx::query("
+                    INSERT OR REPLACE INTO calendar (season, week, start_date, end_date)
+                    VALUES (?, ?, ?, ?)
+                    ")
+                .bind(season)
+                .bind(w.week.number())
+                .bind(w.start.to_rfc3339())
+                .bind(w.end.to_rfc3339())
+                .execute(&mut tx)
+                .await?;
+        }
+
+        audit(&mut tx, Actor::Bot, AuditAction::CalendarCache, &format!("calendar:{}", season),
+            Some(format!("{} weeks", previous)), Some(format!("{} weeks", calendar.len()))).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
+    pub async fn fetch_capsule_history(&self, poolerid: &i64, season: &u16) -> Result<Vec<CapsuleChange>> {
+        let rows = sqlx::query("
+                SELECT type, conference, division, slot, old_team, new_team, changed_at
+                FROM capsule_history
+                WHERE poolerid = ? AND season = ?
+                ORDER BY changed_at, id
+                ")
+            .bind(poolerid)
+            .bind(season)
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.iter().map(|row| Ok(CapsuleChange {
+            ctype: row.get("type"),
+            conference: row.get("conference"),
+            division: row.get("division"),
+            slot: row.get("slot"),
+            old_team: team_from_row(row, "old_team")?,
+            new_team: team_from_row(row, "new_team")?,
+            changed_at: row.get("changed_at"),
+        }))
+        .collect()
+    }
+
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
+    pub async fn cache_capsule_score(&self, poolerid: &i64, season: &u16, score: &u32) -> Result<bool> {
+        let outcome: Result<bool> = async {
+            let mut tx = self.pool.begin().await?;
+            let old: Option<u32> = sqlx::query("SELECT score FROM capsule_scores WHERE poolerid = ? AND season = ?")
+                .bind(poolerid)
+                .bind(season)
+                .fetch_optional(&mut tx)
+                .await?
+                .map(|row| row.get("score"));
+            if old == Some(*score) {
+                return Ok(false);
+            }
+
+            sqlx::query("
+                    INSERT INTO capsule_scores (poolerid, season, score)
+                    VALUES (?, ?, ?)
+                    ON CONFLICT(poolerid, season) DO UPDATE SET
+                        score = excluded.score
+                    ")
+                .bind(poolerid)
+                .bind(season)
+                .bind(score)
+                .execute(&mut tx)
+                .await?;
+
+            audit(&mut tx, Actor::Bot, AuditAction::CapsuleScore, &format!("capsule:{}/pooler:{}", season, poolerid),
+                old.map(|s| s.to_string()), Some(score.to_string())).await?;
+            tx.commit().await?;
+            Ok(true)
+        }.await;
+
+        match outcome {
+            Ok(changed) => {
+                debug!("Successful capsule score cache updated: changed {}", changed);
+                Ok(true)
+            },
+            Err(e) => {
+                error!("Could not update capsule score cache: {}", e);
+                Ok(false)
+            }
+        }
+    }
+
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
+    pub async fn clear_capsule_scores(&self, actor: Actor, season: &u16, poolid: &i64) -> Result<u64> {
+        let mut tx = self.pool.begin().await?;
+        let r = sqlx::query("
+                DELETE FROM capsule_scores
+                WHERE season = ? AND poolerid IN (
+                    SELECT id FROM poolers
+                    WHERE poolid = ?
+                )
+                ")
+            .bind(season)
+            .bind(poolid)
+            .execute(&mut tx)
+            .await?;
+
+        audit(&mut tx, actor, AuditAction::CapsuleScoresCleared, &format!("capsule:{}/pool:{}", season, poolid),
+            Some(format!("{} scores", r.rows_affected())), None).await?;
+        tx.commit().await?;
+
+        Ok(r.rows_affected())
+    }
+
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
+    pub async fn fetch_survivor_picks(&self, season: &u16, poolid: &i64) -> Result<Vec<SurvivorPick>> {
+        let rows = sqlx::query("
+                SELECT sp.id, sp.season, sp.week, sp.poolerid, pl.name, sp.matchid, sp.team, sp.outcome
+                FROM survivor_picks AS sp
+                JOIN poolers AS pl ON pl.id = sp.poolerid AND pl.poolid = ?
+                WHERE sp.season = ?
+                ORDER BY sp.week, pl.id
+                ")
+            .bind(poolid)
+            .bind(season)
+            .fetch_all(&self.pool).await?;
+
+        rows.iter().map(survivor_from_row).collect()
+    }
+
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
+    pub async fn fetch_pending_survivor(&self, season: &u16, poolid: &i64) -> Result<Vec<SurvivorPick>> {
+        let rows = sqlx::query("
+                SELECT sp.id, sp.season, sp.week, sp.poolerid, pl.name, sp.matchid, sp.team, sp.outcome
+                FROM survivor_picks AS sp
+                JOIN poolers AS pl ON pl.id = sp.poolerid AND pl.poolid = ?
+                WHERE sp.season = ? AND sp.outcome IS NULL
+                ORDER BY sp.week
+                ")
+            .bind(poolid)
+            .bind(season)
+            .fetch_all(&self.pool).await?;
+
+        rows.iter().map(survivor_from_row).collect()
+    }
+
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
+    pub async fn set_survivor_pick(&self, actor: Actor, season: &u16, week: &i64, poolerid: &i64, matchid: &str, team: &Team) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        let old: Option<String> = sqlx::query("
+                SELECT team FROM survivor_picks
+                WHERE season = ? AND week = ? AND poolerid = ?
+                ")
+            .bind(season)
+            .bind(week)
+            .bind(poolerid)
+            .fetch_optional(&mut tx)
+            .await?
+            .map(|row| row.get("team"));
+
+        sqlx::query("
+                INSERT INTO survivor_picks (season, week, poolerid, matchid, team)
+                VALUES (?, ?, ?, ?, ?)
+                ON CONFLICT(season, week, poolerid) DO UPDATE SET
+                    matchid = excluded.matchid,
+                    team    = excluded.team
+                ")
+            .bind(season)
+            .bind(week)
+            .bind(poolerid)
+            .bind(matchid)
+            .bind(team.code())
+            .execute(&mut tx)
+            .await?;
+
+        audit(&mut tx, actor, AuditAction::SurvivorPick, &format!("survivor:{}/{}/pooler:{}", season, week, poolerid),
+            old, Some(team.code().to_owned())).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
+    pub async fn resolve_survivor_pick(&self, id: &i64, outcome: &u32) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+        let r = sqlx::query("
+                UPDATE survivor_picks
+                SET outcome = ?
+                WHERE id = ?
+                ")
+            .bind(outcome)
+            .bind(id)
+            .execute(&mut tx)
+            .await?;
+
+        audit(&mut tx, Actor::Bot, AuditAction::SurvivorResolved, &format!("survivor_pick:{}", id),
+            None, Some(outcome.to_string())).await?;
+        tx.commit().await?;
+
+        debug!("Successful survivor pick resolved: rows affected {}", r.rows_affected());
+        Ok(r.rows_affected() > 0)
+    }
+
+    // A missed week is stored as a lost pick on "NA", nothing is inserted if a pick showed up meanwhile
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
+    pub async fn eliminate_survivor_missed(&self, season: &u16, week: &i64, poolerid: &i64) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+        let r = sqlx::query("
+                INSERT INTO survivor_picks (season, week, poolerid, matchid, team, outcome)
+                VALUES (?, ?, ?, '', 'NA', 0)
+                ON CONFLICT DO NOTHING
+                ")
+            .bind(season)
+            .bind(week)
+            .bind(poolerid)
+            .execute(&mut tx)
+            .await?;
+
+        if r.rows_affected() > 0 {
+            audit(&mut tx, Actor::Bot, AuditAction::SurvivorResolved, &format!("survivor:{}/{}/pooler:{}", season, week, poolerid),
+                None, Some("0".to_owned())).await?;
+        }
+        tx.commit().await?;
+
+        Ok(r.rows_affected() > 0)
+    }
+
+    // Newest first, `action` and `actor` narrow it down when given
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
+    pub async fn fetch_audit_log(&self, action: Option<&str>, actor: Option<i64>, limit: i64) -> Result<Vec<AuditEntry>> {
+        let rows = sqlx::query("
+                SELECT id, actor, action, target, old_value, new_value, created_at
+                FROM audit_log
+                WHERE (?1 IS NULL OR action = ?1)
+                    AND (?2 IS NULL OR actor = ?2)
+                ORDER BY id DESC
+                LIMIT ?3
+                ")
+            .bind(action)
+            .bind(actor)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.iter().map(|row| AuditEntry {
+            id: row.get("id"),
+            actor: row.get("actor"),
+            action: row.get("action"),
+            target: row.get("target"),
+            old_value: row.get("old_value"),
+            new_value: row.get("new_value"),
+            created_at: row.get("created_at"),
+        })
+        .collect())
+    }
+}
+
+// Deletes a still valid token inside a submission, so a link can't be replayed
+async fn take_token(tx: &mut Transaction<'_, Sqlite>, token: &str, purpose: TokenPurpose) -> Result<bool> {
+    let row = sqlx::query("
+            DELETE FROM pick_tokens
+            WHERE token = ? AND purpose = ?
+                AND datetime(expires_at) > datetime('now')
+            RETURNING poolerid, season, week
+            ")
+        .bind(token)
+        .bind(purpose.as_str())
+        .fetch_optional(&mut *tx)
+        .await?;
+
+    let Some(row) = row else {
+        return Ok(false);
+    };
+    let (poolerid, season, week): (i64, i64, i64) = (row.get("poolerid"), row.get("season"), row.get("week"));
+    audit(tx, Actor::Bot, AuditAction::TokenConsumed, &format!("pooler:{}", poolerid),
+        Some(format!("{} {}/{}", purpose.as_str(), season, week)), None).await?;
+
+    Ok(true)
 }
 
-fn populate_capsule(capsule: &mut CapsulePicks, row: &SqliteRow) {
+// Written in the same transaction as the change it describes
+async fn audit(
+    tx: &mut Transaction<'_, Sqlite>,
+    actor: Actor,
+    action: AuditAction,
+    target: &str,
+    old_value: Option<String>,
+    new_value: Option<String>) -> Result<()> {
+
+    sqlx::query("
+            INSERT INTO audit_log (actor, action, target, old_value, new_value)
+            VALUES (?, ?, ?, ?, ?)
+            ")
+        .bind(actor.discordid())
+        .bind(action.as_str())
+        .bind(target)
+        .bind(old_value)
+        .bind(new_value)
+        .execute(tx)
+        .await?;
+
+    Ok(())
+}
+
+fn populate_capsule(capsule: &mut CapsulePicks, row: &SqliteRow) -> Result<()> {
     let conf: i32 = row.get("conference");
     let t: i32 = row.get("type");
-    let team: String = row.get("team");
+    let team = Some(team_from_row(row, "team")?);
     let count: i32 = row.get("pick_count");
+    let repicked: bool = row.get("repicked");
 
     match (conf, t) {
         (0, 0) => {
             let i = row.get::<i32, _>("division") as usize;
             capsule.nfc_wins[i] = team;
             capsule.nfc_wins_counts[i] = count;
+            capsule.nfc_wins_repicked[i] = repicked;
         },
         (0, 1) => {
             let i = row.get::<i32, _>("slot") as usize;
             capsule.nfc_wildcards[i] = team;
             capsule.nfc_wild_counts[i] = count;
+            capsule.nfc_wild_repicked[i] = repicked;
         },
         (1, 0) => {
             let i = row.get::<i32, _>("division") as usize;
             capsule.afc_wins[i] = team;
             capsule.afc_wins_counts[i] = count;
+            capsule.afc_wins_repicked[i] = repicked;
         },
         (1, 1) => {
             let i = row.get::<i32, _>("slot") as usize;
             capsule.afc_wildcards[i] = team;
             capsule.afc_wild_counts[i] = count;
+            capsule.afc_wild_repicked[i] = repicked;
+        },
+        (0, 2) => {
+            capsule.nfc_top_seed = team;
+            capsule.nfc_top_seed_count = count;
+            capsule.nfc_top_seed_repicked = repicked;
+        },
+        (1, 2) => {
+            capsule.afc_top_seed = team;
+            capsule.afc_top_seed_count = count;
+            capsule.afc_top_seed_repicked = repicked;
+        },
+        // The champion row is filed under its team's conference
+        (_, 3) => {
+            capsule.champion = team;
+            capsule.champion_count = count;
+            capsule.champion_repicked = repicked;
         },
         _ => unreachable!("[DB] Could not parse capsule row poolerid = {}; conference = {conf}; type = {t}", capsule.poolerid),
     };
+    Ok(())
+}
+
+fn survivor_from_row(row: &SqliteRow) -> Result<SurvivorPick> {
+    let team = match row.get::<String, _>("team").as_str() {
+        "NA" => None,
+        code => Some(code.parse()?),
+    };
+
+    Ok(SurvivorPick {
+        id: row.get("id"),
+        season: row.get("season"),
+        week: row.get("week"),
+        poolerid: row.get("poolerid"),
+        name: row.get("name"),
+        matchid: row.get("matchid"),
+        team,
+        outcome: row.get("outcome"),
+    })
+}
+
+// Teams are stored by code, one that doesn't parse is an error rather than a blank icon
+fn team_from_row(row: &SqliteRow, column: &str) -> Result<Team> {
+    row.get::<String, _>(column).parse()
+}
+
+// NULL or empty when there is no team
+fn opt_team_from_row(row: &SqliteRow, column: &str) -> Result<Option<Team>> {
+    match row.get::<Option<String>, _>(column) {
+        Some(code) if !code.is_empty() => Ok(Some(code.parse()?)),
+        _ => Ok(None),
+    }
 }
@@ -1,119 +1,224 @@
-use std::{env, cmp::Ordering, collections::HashMap, sync::OnceLock};
+use std::{cmp::Ordering, collections::{HashMap, HashSet}, str::FromStr, sync::OnceLock};
 use core::fmt::{Display, Debug};
 
+use anyhow::{ anyhow, Result };
 use chrono::{ DateTime, TimeDelta, Utc };
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 use serenity::{model::id::EmojiId, utils::Emoji};
+use tracing::{instrument, warn};
 
-use crate::database::{WeekFeature, WeekPicks, CapsulePicks};
-
-pub fn list_emoji_names() -> &'static [&'static str] {
-    return &[
-        "ARI",
-        "ATL",
-        "BAL",
-        "BUF",
-        "CAR",
-        "CHI",
-        "CIN",
-        "CLE",
-        "DAL",
-        "DEN",
-        "DET",
-        "GB",
-        "HOU",
-        "IND",
-        "JAX",
-        "KC",
-        "LAR",
-        "LAC",
-        "LV",
-        "MIA",
-        "MIN",
-        "NE",
-        "NO",
-        "NYG",
-        "NYJ",
-        "PHI",
-        "PIT",
-        "SEA",
-        "SF",
-        "TB",
-        "TEN",
-        "WSH",
-
-        "AFC",
-        "NFC",
-    ];
-}
-
-pub fn get_short_name(name: &str) -> String {
-    match name {
-        "Arizona Cardinals"    => "ARI",
-        "Atlanta Falcons"      => "ATL",
-        "Baltimore Ravens"     => "BAL",
-        "Buffalo Bills"        => "BUF",
-        "Carolina Panthers"    => "CAR",
-        "Chicago Bears"        => "CHI",
-        "Cincinnati Bengals"   => "CIN",
-        "Cleveland Browns"     => "CLE",
-        "Dallas Cowboys"       => "DAL",
-        "Denver Broncos"       => "DEN",
-        "Detroit Lions"        => "DET",
-        "Green Bay Packers"    => "GB",
-        "Houston Texans"       => "HOU",
-        "Indianapolis Colts"   => "IND",
-        "Jacksonville Jaguars" => "JAX",
-        "Kansas City Chiefs"   => "KC",
-        "Los Angeles Rams"     => "LAR",
-        "Los Angeles Chargers" => "LAC",
-        "Las Vegas Raiders"    => "LV",
-        "Oakland Raiders"      => "LV",
-        "Miami Dolphins"       => "MIA",
-        "Minnesota Vikings"    => "MIN",
-        "New England Patriots" => "NE",
-        "New Orleans Saints"   => "NO",
-        "New York Giants"      => "NYG",
-        "New York Jets"        => "NYJ",
-        "Philadelphia Eagles"  => "PHI",
-        "Pittsburgh Steelers"  => "PIT",
-        "Seattle Seahawks"     => "SEA",
-        "San Francisco 49ers"  => "SF",
-        "Tampa Bay Buccaneers" => "TB",
-        "Tennessee Titans"     => "TEN",
-        "Washington"           => "WSH",
-        "Washington Commanders"=> "WSH",
-        "Washington Redskins"  => "WSH",
-        _                      => "N/A",
-    }.to_owned()
-}
-
-pub fn get_team_emoji(team: &str) -> EmojiId {
-    return EMOJIS.get()
-        .and_then(|emojis| emojis.get(team))
-        .copied()
-        .unwrap_or(EmojiId(1142674584508825681));
+use crate::metrics;
+use crate::database::{WeekFeature, WeekPicks, CapsulePicks, SeasonPicks};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Conference {
+    NFC = 0,
+    AFC = 1,
 }
 
-pub fn get_afc_emoji() -> EmojiId {
-    return EMOJIS.get()
-        .and_then(|emojis| emojis.get("AFC"))
-        .copied()
-        .unwrap_or(EmojiId(1142674584508825681));
+impl Conference {
+    pub fn all() -> &'static [Conference] {
+        &[Conference::AFC, Conference::NFC]
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Conference::AFC => "AFC",
+            Conference::NFC => "NFC",
+        }
+    }
+
+    pub fn emoji(&self) -> EmojiId {
+        get_emoji(self.code())
+    }
+
+    pub fn icon(&self) -> String {
+        format!("<:{}:{}>", self.code(), self.emoji())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Team {
+    Cardinals, Falcons, Ravens, Bills, Panthers, Bears, Bengals, Browns,
+    Cowboys, Broncos, Lions, Packers, Texans, Colts, Jaguars, Chiefs,
+    Rams, Chargers, Raiders, Dolphins, Vikings, Patriots, Saints, Giants,
+    Jets, Eagles, Steelers, Seahawks, FortyNiners, Buccaneers, Titans, Commanders,
+}
+
+struct TeamInfo {
+    team: Team,
+    code: &'static str,
+    name: &'static str,
+    espn_id: i64,
+    conference: Conference,
+    division: Division,
+    // Older codes and names still found in ESPN feeds and past seasons
+    aliases: &'static [&'static str],
+}
+
+macro_rules! team_info {
+    ($team:ident, $code:literal, $name:literal, $id:literal, $conf:ident, $div:ident $(, $alias:literal)*) => {
+        TeamInfo {
+            team: Team::$team, code: $code, name: $name, espn_id: $id,
+            conference: Conference::$conf, division: Division::$div,
+            aliases: &[$($alias),*],
+        }
+    };
+}
+
+// Same order as the `Team` variants, `Team::info` indexes into it
+static TEAMS: [TeamInfo; 32] = [
+    team_info!(Cardinals,   "ARI", "Arizona Cardinals",     22, NFC, West),
+    team_info!(Falcons,     "ATL", "Atlanta Falcons",        1, NFC, South),
+    team_info!(Ravens,      "BAL", "Baltimore Ravens",      33, AFC, North),
+    team_info!(Bills,       "BUF", "Buffalo Bills",          2, AFC, East),
+    team_info!(Panthers,    "CAR", "Carolina Panthers",     29, NFC, South),
+    team_info!(Bears,       "CHI", "Chicago Bears",          3, NFC, North),
+    team_info!(Bengals,     "CIN", "Cincinnati Bengals",     4, AFC, North),
+    team_info!(Browns,      "CLE", "Cleveland Browns",       5, AFC, North),
+    team_info!(Cowboys,     "DAL", "Dallas Cowboys",         6, NFC, East),
+    team_info!(Broncos,     "DEN", "Denver Broncos",         7, AFC, West),
+    team_info!(Lions,       "DET", "Detroit Lions",          8, NFC, North),
+    team_info!(Packers,     "GB",  "Green Bay Packers",      9, NFC, North),
+    team_info!(Texans,      "HOU", "Houston Texans",        34, AFC, South),
+    team_info!(Colts,       "IND", "Indianapolis Colts",    11, AFC, South),
+    team_info!(Jaguars,     "JAX", "Jacksonville Jaguars",  30, AFC, South),
+    team_info!(Chiefs,      "KC",  "Kansas City Chiefs",    12, AFC, West),
+    team_info!(Rams,        "LAR", "Los Angeles Rams",      14, NFC, West, "LA"),
+    team_info!(Chargers,    "LAC", "Los Angeles Chargers",  24, AFC, West),
+    team_info!(Raiders,     "LV",  "Las Vegas Raiders",     13, AFC, West, "OAK", "Oakland Raiders"),
+    team_info!(Dolphins,    "MIA", "Miami Dolphins",        15, AFC, East),
+    team_info!(Vikings,     "MIN", "Minnesota Vikings",     16, NFC, North),
+    team_info!(Patriots,    "NE",  "New England Patriots",  17, AFC, East),
+    team_info!(Saints,      "NO",  "New Orleans Saints",    18, NFC, South),
+    team_info!(Giants,      "NYG", "New York Giants",       19, NFC, East),
+    team_info!(Jets,        "NYJ", "New York Jets",         20, AFC, East),
+    team_info!(Eagles,      "PHI", "Philadelphia Eagles",   21, NFC, East),
+    team_info!(Steelers,    "PIT", "Pittsburgh Steelers",   23, AFC, North),
+    team_info!(Seahawks,    "SEA", "Seattle Seahawks",      26, NFC, West),
+    team_info!(FortyNiners, "SF",  "San Francisco 49ers",   25, NFC, West),
+    team_info!(Buccaneers,  "TB",  "Tampa Bay Buccaneers",  27, NFC, South),
+    team_info!(Titans,      "TEN", "Tennessee Titans",      10, AFC, South),
+    team_info!(Commanders,  "WSH", "Washington Commanders", 28, NFC, East, "WAS", "Washington", "Washington Redskins"),
+];
+
+impl Team {
+    pub fn all() -> impl Iterator<Item = Team> {
+        TEAMS.iter().map(|info| info.team)
+    }
+
+    fn info(&self) -> &'static TeamInfo {
+        &TEAMS[*self as usize]
+    }
+
+    pub fn code(&self) -> &'static str {
+        self.info().code
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.info().name
+    }
+
+    pub fn espn_id(&self) -> i64 {
+        self.info().espn_id
+    }
+
+    pub fn conference(&self) -> Conference {
+        self.info().conference
+    }
+
+    pub fn division(&self) -> Division {
+        self.info().division
+    }
+
+    pub fn aliases(&self) -> &'static [&'static str] {
+        self.info().aliases
+    }
+
+    pub fn emoji(&self) -> EmojiId {
+        get_emoji(self.code())
+    }
+
+    pub fn icon(&self) -> String {
+        format!("<:{}:{}>", self.code(), self.emoji())
+    }
+}
+
+impl FromStr for Team {
+    type Err = anyhow::Error;
+
+    // Accepts the code, the full name or any alias, ignoring case
+    fn from_str(s: &str) -> Result<Team> {
+        let s = s.trim();
+        TEAMS.iter()
+            .find(|info| info.code.eq_ignore_ascii_case(s)
+                || info.name.eq_ignore_ascii_case(s)
+                || info.aliases.iter().any(|a| a.eq_ignore_ascii_case(s)))
+            .map(|info| info.team)
+            .ok_or_else(|| anyhow!("Unknown team '{}'", s))
+    }
+}
+
+impl Display for Team {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.code())
+    }
+}
+
+// By code, as the DB and the web app know them
+impl Serialize for Team {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.code())
+    }
+}
+
+pub fn list_emoji_names() -> Vec<&'static str> {
+    Team::all().map(|t| t.code())
+        .chain(Conference::all().iter().map(|c| c.code()))
+        .collect()
+}
+
+pub fn list_asset_emojis() -> &'static [(&'static str, &'static str)] {
+    &[
+        ("UP",   "./assets/arrow-up.png"),
+        ("DOWN", "./assets/arrow-down.png"),
+    ]
 }
-pub fn get_nfc_emoji() -> EmojiId {
-    return EMOJIS.get()
-        .and_then(|emojis| emojis.get("NFC"))
+
+const UNKNOWN_EMOJI: EmojiId = EmojiId(1142674584508825681);
+
+fn get_emoji(name: &str) -> EmojiId {
+    EMOJIS.get()
+        .and_then(|emojis| emojis.get(name))
         .copied()
-        .unwrap_or(EmojiId(1142674584508825681));
+        .unwrap_or(UNKNOWN_EMOJI)
+}
+
+// Stands in for a pick that was never made
+pub fn get_missing_icon() -> String {
+    format!("<:NA:{}>", UNKNOWN_EMOJI)
 }
 
 static EMOJIS: OnceLock<HashMap<String, EmojiId>> = OnceLock::new();
 
+pub fn get_movement_icon(movement: i64) -> String {
+    let (name, fallback) = match movement.cmp(&0) {
+        Ordering::Greater => ("UP", ":arrow_up_small:"),
+        Ordering::Less    => ("DOWN", ":arrow_down_small:"),
+        Ordering::Equal   => return String::new(),
+    };
+
+    match EMOJIS.get().and_then(|emojis| emojis.get(name)) {
+        Some(id) => format!("<:{}:{}>", name, id),
+        None => fallback.to_owned(),
+    }
+}
+
 pub fn sync_emojis(emojis: &[Emoji]) {
     let names = list_emoji_names();
+    let assets = list_asset_emojis();
     let map = emojis.iter()
-        .filter(|&e| names.contains(&e.name.as_str()))
+        .filter(|&e| names.contains(&e.name.as_str()) || assets.iter().any(|(n, _)| *n == e.name))
         .fold(HashMap::<_, _>::new(), |mut map, e| {
             map.insert(e.name.clone(), e.id);
             map
@@ -122,54 +227,25 @@ pub fn sync_emojis(emojis: &[Emoji]) {
     let _ = EMOJIS.set(map);
 }
 
-pub fn get_team_id(team: &str) -> i64 {
-    match team {
-        "ARI" => 22,
-        "ATL" => 1,
-        "BAL" => 33,
-        "BUF" => 2,
-        "CAR" => 29,
-        "CHI" => 3,
-        "CIN" => 4,
-        "CLE" => 5,
-        "DAL" => 6,
-        "DEN" => 7,
-        "DET" => 8,
-        "GB"  => 9,
-        "HOU" => 34,
-        "IND" => 11,
-        "JAX" => 30,
-        "KC"  => 12,
-        "LA"  => 14,
-        "LAR" => 14,
-        "LAC" => 24,
-        "LV"  => 13,
-        "MIA" => 15,
-        "MIN" => 16,
-        "NE"  => 17,
-        "NO"  => 18,
-        "NYG" => 19,
-        "NYJ" => 20,
-        "PHI" => 21,
-        "PIT" => 23,
-        "SEA" => 26,
-        "SF"  => 25,
-        "TB"  => 27,
-        "TEN" => 10,
-        "WAS" => 28,
-        "WSH" => 28,
-        _     => -1,
-    }
-}
-
 #[derive(Clone)]
 pub struct Match {
     pub id_event: String,
-    pub away_team: String,
-    pub home_team: String,
+    pub away_team: Team,
+    pub home_team: Team,
     pub away_score: Option<u64>,
     pub home_score: Option<u64>,
     pub date: DateTime<Utc>,
+    pub away_record: Option<TeamRecord>,
+    pub home_record: Option<TeamRecord>,
+}
+
+// As ESPN summarizes them on the scoreboard, e.g. "5-2" and "3-1 Road"
+#[derive(Debug, Clone)]
+pub struct TeamRecord {
+    pub overall: String,
+    // Road record for the away team, home record for the home team
+    pub venue: String,
+    pub venue_name: String,
 }
 
 impl Display for Match {
@@ -192,43 +268,246 @@ impl Debug for Match {
     }
 }
 
-pub async fn get_week(season: &u16, week: &i64) -> Vec<Match> {
-    let data_url = env::var("DATA_URL")
-        .expect("![Football] Could not find 'DATA_URL' env var");
+// Weeks are stored as 1..=22 in the DB, the playoff rounds following week 18
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Week {
+    Regular(u8),
+    WildCard,
+    Divisional,
+    Conference,
+    SuperBowl,
+}
+
+const REGULAR_WEEKS: u8 = 18;
+
+impl Week {
+    pub fn all() -> impl Iterator<Item = Week> {
+        (1..=REGULAR_WEEKS).map(Week::Regular)
+            .chain([Week::WildCard, Week::Divisional, Week::Conference, Week::SuperBowl])
+    }
+
+    pub fn number(&self) -> i64 {
+        match self {
+            Week::Regular(w)  => *w as i64,
+            Week::WildCard    => 19,
+            Week::Divisional  => 20,
+            Week::Conference  => 21,
+            Week::SuperBowl   => 22,
+        }
+    }
+
+    // The regular season went from 17 to 18 weeks in 2021
+    pub fn regular_weeks(season: &u16) -> u8 {
+        if *season < 2021 { 17 } else { REGULAR_WEEKS }
+    }
+
+    // A week number coming from a client, checked against that season's length
+    pub fn in_season(season: &u16, number: i64) -> Result<Week> {
+        match Week::try_from(number)? {
+            Week::Regular(w) if w > Week::regular_weeks(season) => Err(anyhow!("No week {} in season {}", w, season)),
+            week => Ok(week),
+        }
+    }
+
+    pub fn is_playoffs(&self) -> bool {
+        !matches!(self, Week::Regular(_))
+    }
+
+    // ESPN's `seasontype` (2 = regular season, 3 = playoffs) and `week` query parameters,
+    // postseason week 4 being the Pro Bowl break
+    pub fn espn_params(&self) -> (u8, u8) {
+        match self {
+            Week::Regular(w)  => (2, *w),
+            Week::WildCard    => (3, 1),
+            Week::Divisional  => (3, 2),
+            Week::Conference  => (3, 3),
+            Week::SuperBowl   => (3, 5),
+        }
+    }
+
+    pub fn from_espn(seasontype: u8, week: u8) -> Option<Week> {
+        match (seasontype, week) {
+            (2, w) if (1..=REGULAR_WEEKS).contains(&w) => Some(Week::Regular(w)),
+            (3, 1) => Some(Week::WildCard),
+            (3, 2) => Some(Week::Divisional),
+            (3, 3) => Some(Week::Conference),
+            (3, 5) => Some(Week::SuperBowl),
+            _ => None,
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            Week::Regular(w)  => format!("Semaine {}", w),
+            Week::WildCard    => "Ronde des wild cards".to_owned(),
+            Week::Divisional  => "Ronde de division".to_owned(),
+            Week::Conference  => "Finales de conférence".to_owned(),
+            Week::SuperBowl   => "Super Bowl".to_owned(),
+        }
+    }
+
+    // Two characters wide, for the tables
+    pub fn short_label(&self) -> String {
+        match self {
+            Week::Regular(w)  => format!("{:02}", w),
+            Week::WildCard    => "WC".to_owned(),
+            Week::Divisional  => "DV".to_owned(),
+            Week::Conference  => "CF".to_owned(),
+            Week::SuperBowl   => "SB".to_owned(),
+        }
+    }
+}
+
+impl TryFrom<i64> for Week {
+    type Error = anyhow::Error;
+
+    fn try_from(number: i64) -> Result<Week> {
+        match number {
+            19 => Ok(Week::WildCard),
+            20 => Ok(Week::Divisional),
+            21 => Ok(Week::Conference),
+            22 => Ok(Week::SuperBowl),
+            w if (1..=REGULAR_WEEKS as i64).contains(&w) => Ok(Week::Regular(w as u8)),
+            w => Err(anyhow!("Invalid week number {}", w)),
+        }
+    }
+}
+
+// Slash command options carry the week number as a string
+impl FromStr for Week {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Week> {
+        Week::try_from(s.trim().parse::<i64>()?)
+    }
+}
 
-    let (w, sw) = if *week == 19 { (160, 1) }
-    else if *week == 20 { (125, 2) }
-    else if *week == 21 { (150, 3) }
-    else if *week == 22 { (200, 5) }
-    else { (*week, *week) };
-    let stype = if w < 100 { 2 } else { 3 };
+impl Display for Week {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.label())
+    }
+}
 
+// Every ESPN call goes through here so requests and failures show up in the metrics
+async fn espn_get<T: DeserializeOwned>(endpoint: &'static str, url: String) -> Result<T> {
+    let res = async {
+        let text = reqwest::get(url).await?.text().await?;
+        Ok::<T, anyhow::Error>(serde_json::from_str(&text)?)
+    }.await;
+
+    metrics::record_espn(endpoint, res.is_ok());
+    res
+}
+
+#[instrument(level = "debug", skip(data_url))]
+pub async fn get_week(data_url: &str, season: &u16, week: &Week) -> Result<Vec<Match>> {
+    let (stype, sw) = week.espn_params();
     let scoreurl = format!("{}?dates={}&seasontype={}&week={}", data_url, season, stype, sw);
-    let scoreres = reqwest::get(scoreurl).await
-        .expect("![Football] Could not get reply")
-        .text().await
-        .expect("![Football] Could not retrieve text from response");
-    let schedule: ESPNSchedule = serde_json::from_str(&scoreres)
-        .expect("![Football] Could not parse response");
+    let schedule: ESPNSchedule = espn_get("scoreboard", scoreurl).await
+        .map_err(|e| anyhow!("Could not get the scoreboard of {} {} : {}", season, week, e))?;
 
     schedule.events.into_iter().map(move |e| {
         let away_team = &e.comp[0].teams[1];
         let home_team = &e.comp[0].teams[0];
         let match_date = e.comp[0].date.replace("Z", ":00Z");
-        Match {
-            id_event: e.id,
-            away_team: away_team.team.abbreviation.to_owned(),
-            home_team: home_team.team.abbreviation.to_owned(),
+        Ok(Match {
+            away_team: away_team.team.parse()?,
+            home_team: home_team.team.parse()?,
             away_score: away_team.score.parse::<u64>().ok(),
             home_score: home_team.score.parse::<u64>().ok(),
             date: DateTime::parse_from_rfc3339(match_date.as_str())
                 .map(|dt| dt.with_timezone(&Utc))
-                .expect("![Football] Could not parse event's date")
-        }
+                .map_err(|err| anyhow!("Could not parse the date of event {} : {}", e.id, err))?,
+            away_record: away_team.record("road"),
+            home_record: home_team.record("home"),
+            id_event: e.id,
+        })
     })
     .collect()
 }
 
+// Date range of a week in ESPN's calendar, the end being the tuesday morning after the games
+#[derive(Debug, Clone)]
+pub struct WeekDates {
+    pub week: Week,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+// Empty while ESPN hasn't published the season
+#[instrument(level = "debug", skip(data_url), err)]
+pub async fn get_calendar(data_url: &str, season: &u16) -> Result<Vec<WeekDates>> {
+    // Any week of the season returns the whole calendar along with its games
+    let url = format!("{}?dates={}&seasontype=2&week=1", data_url, season);
+    let scoreboard: ESPNScoreboard = espn_get("calendar", url).await?;
+
+    let league = scoreboard.leagues.into_iter().next()
+        .ok_or_else(|| anyhow!("No league in the scoreboard for season {}", season))?;
+    // ESPN falls back on the latest season when the requested one isn't published yet
+    if league.season.year != *season {
+        return Ok(Vec::new());
+    }
+
+    let parse_date = |date: &str| {
+        DateTime::parse_from_rfc3339(date.replace("Z", ":00Z").as_str())
+            .map(|dt| dt.with_timezone(&Utc))
+    };
+
+    let mut calendar = Vec::new();
+    for stype in &league.calendar {
+        let Ok(seasontype) = stype.value.parse::<u8>() else { continue };
+
+        for entry in &stype.entries {
+            let week = entry.value.parse::<u8>().ok()
+                .and_then(|w| Week::from_espn(seasontype, w));
+
+            if let Some(week) = week {
+                calendar.push(WeekDates {
+                    week,
+                    start: parse_date(&entry.start_date)?,
+                    end: parse_date(&entry.end_date)?,
+                });
+            }
+        }
+    }
+    calendar.sort_by_key(|w| w.week);
+
+    Ok(calendar)
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ESPNScoreboard {
+    leagues: Vec<ESPNLeague>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ESPNLeague {
+    season: ESPNSeason,
+    #[serde(default)]
+    calendar: Vec<ESPNCalendarType>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ESPNSeason {
+    year: u16,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ESPNCalendarType {
+    value: String,
+    #[serde(default)]
+    entries: Vec<ESPNCalendarEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ESPNCalendarEntry {
+    value: String,
+    #[serde(rename="startDate")]
+    start_date: String,
+    #[serde(rename="endDate")]
+    end_date: String,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 struct ESPNSchedule {
     events: Vec<ESPNEvent>,
@@ -261,6 +540,31 @@ struct ESPNCompetitor {
     team: ESPNTeam,
     #[serde(default)]
     score: String,
+    #[serde(default)]
+    records: Vec<ESPNRecord>,
+}
+
+impl ESPNCompetitor {
+    // `venue` is ESPN's record type, "home" or "road"
+    fn record(&self, venue: &str) -> Option<TeamRecord> {
+        let overall = self.records.iter().find(|r| r.kind == "total")?;
+        let split = self.records.iter().find(|r| r.kind == venue)?;
+        Some(TeamRecord {
+            overall: overall.summary.clone(),
+            venue: split.summary.clone(),
+            venue_name: split.name.clone(),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ESPNRecord {
+    #[serde(default)]
+    name: String,
+    #[serde(rename="type", default)]
+    kind: String,
+    #[serde(default)]
+    summary: String,
 }
 
 /*
@@ -272,18 +576,13 @@ struct ESPNScore {
 }
 */
 
-pub async fn get_schedule(season: &u16, teamid: &i64) -> Vec<Option<Match>> {
-    let partial_url = env::var("BLAME_URL")
-        .expect("![Football] Could not find 'BLAME_URL' env var");
-
-    let url = format!("{}/{}/schedule?season={}", partial_url, teamid, season);
-    let res = reqwest::get(url).await
-        .expect("![Football] Could not get reply")
-        .text().await
-        .expect("![Football] Could not retrieve text from response");
-    let schedule: ESPNSchedule = serde_json::from_str(&res).expect("![Football] Could not parse response");
+#[instrument(level = "debug", skip(blame_url))]
+pub async fn get_schedule(blame_url: &str, season: &u16, team: &Team) -> Result<Vec<Option<Match>>> {
+    let url = format!("{}/{}/schedule?season={}", blame_url, team.espn_id(), season);
+    let schedule: ESPNSchedule = espn_get("schedule", url).await
+        .map_err(|e| anyhow!("Could not get the schedule of {} : {}", team, e))?;
 
-    let matches: Vec<_> = schedule.events.iter().map(|e| {
+    let matches = schedule.events.iter().map(|e| {
         let hteam = &e.comp[0].teams[0];
         let ateam = &e.comp[0].teams[1];
         let match_date = e.comp[0].date.replace("Z", ":00Z");
@@ -297,15 +596,16 @@ pub async fn get_schedule(season: &u16, teamid: &i64) -> Vec<Option<Match>> {
         }
 
         //TODO: Find a way to avoid all the clones
-        (e.week.number, Match {
+        Ok((e.week.number, Match {
             id_event: e.comp[0].id.clone(),
-            away_team: ateam.team.abbreviation.clone(), home_team: hteam.team.abbreviation.clone(),
+            away_team: ateam.team.parse()?, home_team: hteam.team.parse()?,
             away_score, home_score,
             date: DateTime::parse_from_rfc3339(match_date.as_str())
                 .map(|dt| dt.with_timezone(&Utc))
-                .expect("![Football] Could not parse event's date"),
-        })
-    }).collect();
+                .map_err(|err| anyhow!("Could not parse the date of event {} : {}", e.comp[0].id, err))?,
+            away_record: None, home_record: None,
+        }))
+    }).collect::<Result<Vec<_>>>()?;
 
     let mut result = Vec::<Option<Match>>::new();
     result.resize(18, None);
@@ -313,15 +613,15 @@ pub async fn get_schedule(season: &u16, teamid: &i64) -> Vec<Option<Match>> {
         result[(idx - 1) as usize] = Some(m);
     }
 
-    return result;
+    Ok(result)
 }
 
 pub fn calc_blame(
-    _week: &i64,
+    _week: &Week,
     _matches: &[Match],
     _picks: &[WeekPicks],
     _poolerid: &i64,
-    _team: &str) -> i64 {
+    _team: &Team) -> i64 {
 
     return 0;
 }
@@ -344,7 +644,74 @@ impl Display for PickResults {
     }
 }
 
-pub async fn calc_results(week: &i64, matches: &[Match], picks: &[WeekPicks], feat: &Option<WeekFeature>) -> Vec<PickResults> {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoolMode {
+    Classic    = 0,
+    Confidence = 1,
+}
+
+impl PoolMode {
+    pub fn from_db(mode: i32) -> PoolMode {
+        match mode {
+            1 => PoolMode::Confidence,
+            _ => PoolMode::Classic,
+        }
+    }
+}
+
+// How the bot fills in the picks of a pooler who missed the week's first kickoff
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefaultPicks {
+    None         = 0,
+    HomeTeams    = 1,
+    FavTeamHome  = 2,
+    PoolMajority = 3,
+}
+
+impl DefaultPicks {
+    pub fn from_db(policy: i32) -> DefaultPicks {
+        match policy {
+            1 => DefaultPicks::HomeTeams,
+            2 => DefaultPicks::FavTeamHome,
+            3 => DefaultPicks::PoolMajority,
+            _ => DefaultPicks::None,
+        }
+    }
+}
+
+// Marks an automatic pick next to its team icon
+pub const AUTO_PICK_MARK: &str = "`A`";
+
+// Confidence ranks must be exactly 1..=N over the week's N matches
+pub fn validate_confidence(matches: &[Match], confidences: &HashMap<String, u32>) -> Result<()> {
+    if confidences.len() != matches.len() {
+        return Err(anyhow!("expected {} confidence ranks, got {}", matches.len(), confidences.len()));
+    }
+
+    let mut seen = vec![false; matches.len()];
+    for m in matches {
+        let Some(&rank) = confidences.get(&m.id_event) else {
+            return Err(anyhow!("no confidence rank for match {}", m.id_event));
+        };
+        if rank == 0 || rank as usize > matches.len() {
+            return Err(anyhow!("confidence rank {} out of range for match {} (1..={})", rank, m.id_event, matches.len()));
+        }
+        if std::mem::replace(&mut seen[rank as usize - 1], true) {
+            return Err(anyhow!("confidence rank {} used more than once", rank));
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn calc_results(
+    week: &Week,
+    matches: &[Match],
+    picks: &[WeekPicks],
+    feat: &Option<WeekFeature>,
+    mode: &PoolMode,
+    auto_weight: &u32) -> Vec<PickResults> {
+
     let now = Utc::now().checked_sub_signed(TimeDelta::hours(8)).unwrap();
     let week_complete = matches.iter().all(|m| {
         match m.date.cmp(&now) {
@@ -357,6 +724,7 @@ pub async fn calc_results(week: &i64, matches: &[Match], picks: &[WeekPicks], fe
         }
     });
 
+    let no_ranks = HashMap::new();
     let mut results = Vec::<PickResults>::new();
     for pick in picks {
         let pickid = pick.pickid;
@@ -388,24 +756,45 @@ pub async fn calc_results(week: &i64, matches: &[Match], picks: &[WeekPicks], fe
         };
 
         // Week's Score
+        metrics::record_cache("scores", pick.cached.is_some());
         let score = if let Some(cached_score) = pick.cached {
             cached_score
         }
         else {
+            let confidences = match mode {
+                PoolMode::Confidence => Some(pick.confidences.as_ref().unwrap_or(&no_ranks)),
+                PoolMode::Classic => None,
+            };
+            // Ranks that aren't a permutation of 1..=N score nothing for the week
+            let valid = confidences.is_none_or(|ranks| match validate_confidence(matches, ranks) {
+                Ok(()) => true,
+                Err(e) => {
+                    warn!("Invalid confidence ranks for pooler {} (week {}), scored 0 : {}", poolerid, week, e);
+                    false
+                },
+            });
             match (&pick.picks, &pick.counts) {
-                (Some(pooler_picks), Some(pooler_counts)) => calc_results_internal(&matches, &week, pooler_picks, pooler_counts),
+                (Some(pooler_picks), Some(pooler_counts)) if valid =>
+                    calc_results_internal(&matches, &week, pooler_picks, pooler_counts, confidences, &pick.autos, *auto_weight),
                 _ => 0,
             }
         };
 
         // Build Icons List
         let icons = if let Some(pick_map) = &pick.picks {
-            let invalid = "NA".to_owned();
             let mut temp = String::new();
 
             for m in matches {
-                let choice = pick_map.get(&m.id_event).unwrap_or(&invalid);
-                temp.push_str(format!("<:{}:{}>", choice, get_team_emoji(choice)).as_str());
+                let icon = pick_map.get(&m.id_event).map_or_else(get_missing_icon, Team::icon);
+                temp.push_str(icon.as_str());
+
+                let rank = pick.confidences.as_ref().and_then(|ranks| ranks.get(&m.id_event));
+                if let (PoolMode::Confidence, Some(rank)) = (mode, rank) {
+                    temp.push_str(format!("`{}`", rank).as_str());
+                }
+                if pick.autos.contains(&m.id_event) {
+                    temp.push_str(AUTO_PICK_MARK);
+                }
             }
             temp
         }
@@ -424,6 +813,105 @@ pub async fn calc_results(week: &i64, matches: &[Match], picks: &[WeekPicks], fe
     results
 }
 
+pub async fn calc_season(data_url: &str, season: &u16, weeks: &SeasonPicks, mode: &PoolMode, auto_weight: &u32) -> Result<Vec<(Week, Vec<PickResults>)>> {
+    let mut results = Vec::with_capacity(weeks.len());
+
+    for (week, feat, picks) in weeks {
+        // Fully cached weeks don't need the matches, skip the ESPN round trip
+        let matches = if picks.iter().all(|p| p.cached.is_some() && p.featcached.is_some()) {
+            Vec::new()
+        } else {
+            get_week(data_url, season, week).await?
+        };
+
+        results.push((*week, calc_results(week, &matches, picks, feat, mode, auto_weight).await));
+    }
+    Ok(results)
+}
+
+pub struct StandingsEntry {
+    pub poolerid: i64,
+    pub name: String,
+    pub scores: Vec<u32>,
+    pub totals: Vec<u32>,
+    pub ranks: Vec<usize>,
+}
+
+impl StandingsEntry {
+    pub fn total(&self) -> u32 {
+        self.totals.last().copied().unwrap_or(0)
+    }
+
+    pub fn rank(&self) -> usize {
+        self.ranks.last().copied().unwrap_or(0)
+    }
+
+    // Places gained (positive) or lost (negative) going into week index `i`
+    pub fn movement_at(&self, i: usize) -> i64 {
+        if i == 0 || i >= self.ranks.len() {
+            return 0;
+        }
+        self.ranks[i - 1] as i64 - self.ranks[i] as i64
+    }
+
+    pub fn movement(&self) -> i64 {
+        self.movement_at(self.ranks.len().saturating_sub(1))
+    }
+}
+
+impl Display for StandingsEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}] {} - {} (rank: {}, movement: {})",
+            self.poolerid, self.name, self.total(), self.rank(), self.movement())
+    }
+}
+
+// `capsule` points only weigh on the last week's rank, `totals` stay pick points
+pub fn calc_standings_history(season: &[(Week, Vec<PickResults>)], capsule: &HashMap<i64, u32>) -> Vec<StandingsEntry> {
+    let week_count = season.len();
+    let mut entries = Vec::<StandingsEntry>::new();
+
+    for (i, (_, results)) in season.iter().enumerate() {
+        for r in results {
+            let idx = match entries.iter().position(|e| e.poolerid == r.poolerid) {
+                Some(idx) => idx,
+                None => {
+                    entries.push(StandingsEntry {
+                        poolerid: r.poolerid,
+                        name: r.name.clone(),
+                        scores: vec![0; week_count],
+                        totals: vec![0; week_count],
+                        ranks: vec![0; week_count],
+                    });
+                    entries.len() - 1
+                },
+            };
+            entries[idx].scores[i] = r.score + r.featscore;
+        }
+    }
+
+    for i in 0..week_count {
+        for e in entries.iter_mut() {
+            let previous = if i == 0 { 0 } else { e.totals[i - 1] };
+            e.totals[i] = previous + e.scores[i];
+        }
+
+        let ranked = |e: &StandingsEntry| match i + 1 == week_count {
+            true => e.totals[i] + capsule.get(&e.poolerid).copied().unwrap_or(0),
+            false => e.totals[i],
+        };
+
+        // Ties share the same rank (1, 2, 2, 4, ...)
+        let totals: Vec<u32> = entries.iter().map(ranked).collect();
+        for (e, total) in entries.iter_mut().zip(totals.iter()) {
+            e.ranks[i] = 1 + totals.iter().filter(|&t| t > total).count();
+        }
+    }
+
+    entries.sort_by_key(|e| (e.rank(), e.poolerid));
+    entries
+}
+
 #[derive(Debug)]
 enum MatchOutcome {
     Win,
@@ -432,11 +920,19 @@ enum MatchOutcome {
     NotPlayed,
 }
 
-fn calc_results_internal(matches: &[Match], week: &i64, picks: &HashMap<String, String>, counts: &HashMap<String, i32>) -> u32 {
+fn calc_results_internal(
+    matches: &[Match],
+    week: &Week,
+    picks: &HashMap<String, Team>,
+    counts: &HashMap<String, i32>,
+    confidences: Option<&HashMap<String, u32>>,
+    autos: &HashSet<String>,
+    auto_weight: u32) -> u32 {
+
     let mut total = 0;
 
     for m in matches {
-        let Some(choice) = picks.get(&m.id_event).map(|p| String::as_str(p)) else {
+        let Some(&choice) = picks.get(&m.id_event) else {
             continue;
         };
 
@@ -450,29 +946,36 @@ fn calc_results_internal(matches: &[Match], week: &i64, picks: &HashMap<String,
             _                                                    => MatchOutcome::NotPlayed,
         };
 
-        total += get_score(&outcome, unique, week);
+        let points = match confidences {
+            Some(ranks) => get_confidence_score(&outcome, ranks.get(&m.id_event).copied().unwrap_or(0)),
+            None => get_score(&outcome, unique, week),
+        };
+        total += match autos.contains(&m.id_event) {
+            true => points * auto_weight / 100,
+            false => points,
+        };
     }
 
     total
 }
 
-fn get_score(outcome: &MatchOutcome, unique: bool, week: &i64) -> u32 {
+fn get_confidence_score(outcome: &MatchOutcome, rank: u32) -> u32 {
+    match outcome {
+        MatchOutcome::Win => rank,
+        MatchOutcome::Loss | MatchOutcome::NotPlayed => 0,
+        MatchOutcome::Tied => rank / 2,
+    }
+}
+
+fn get_score(outcome: &MatchOutcome, unique: bool, week: &Week) -> u32 {
     match outcome {
         MatchOutcome::Win => {
             match week {
-                1..=18 => if unique { 4 } else { 2 },
-                19 => if unique { 6 } else { 4 },
-                160 => if unique { 6 } else { 4 },
-
-                20 => if unique { 8 } else { 6 },
-                125 => if unique { 8 } else { 6 },
-
-                21 => if unique { 10 } else { 8 },
-                150 => if unique { 10 } else { 8 },
-
-                22 => if unique { 12 } else { 10 },
-                200 => if unique { 12 } else { 10 },
-                _ => if unique { 4 } else { 2 },
+                Week::Regular(_)  => if unique { 4 } else { 2 },
+                Week::WildCard    => if unique { 6 } else { 4 },
+                Week::Divisional  => if unique { 8 } else { 6 },
+                Week::Conference  => if unique { 10 } else { 8 },
+                Week::SuperBowl   => if unique { 12 } else { 10 },
             }
         },
         MatchOutcome::Loss | MatchOutcome::NotPlayed => 0,
@@ -487,6 +990,14 @@ struct ESPNTeam {
     displayName: String,
 }
 
+impl ESPNTeam {
+    // Every team coming from ESPN goes through here, a code we don't know is an error
+    fn parse(&self) -> Result<Team> {
+        self.abbreviation.parse()
+            .map_err(|e| anyhow!("ESPN team {} ({}) : {}", self.abbreviation, self.displayName, e))
+    }
+}
+
 #[derive(Deserialize, Debug)]
 struct ESPNStandings {
     children: Vec<ESPNConference>,
@@ -494,13 +1005,11 @@ struct ESPNStandings {
 
 #[derive(Deserialize, Debug)]
 struct ESPNConference {
-    abbreviation: String,
     children: Vec<ESPNDivision>,
 }
 
 #[derive(Deserialize, Debug)]
 struct ESPNDivision {
-    abbreviation: String,
     standings: ESPNStandingsBody,
 }
 
@@ -530,38 +1039,93 @@ pub enum Division {
     West  = 3,
 }
 
-impl Division {
-    pub fn from_espn(abbr: &str) -> Division {
-        match abbr {
-            "NORTH" => Division::North,
-            "SOUTH" => Division::South,
-            "EAST"  => Division::East,
-            "WEST"  => Division::West,
-            other   => panic!("![Football] Unknown division abbreviation from ESPN: {other}"),
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClinchStatus {
+    Clinched,
+    InHunt,
+    Eliminated,
+}
+
+impl ClinchStatus {
+    pub fn icon(&self) -> &'static str {
+        match self {
+            ClinchStatus::Clinched   => ":white_check_mark:",
+            ClinchStatus::InHunt     => ":hourglass:",
+            ClinchStatus::Eliminated => ":x:",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Clincher {
+    None,
+    Berth,
+    Wildcard,
+    Division,
+    TopSeed,
+    Eliminated,
+}
+
+impl Clincher {
+    // ESPN's `clincher` stat: x playoff berth, y wildcard, z division, * division and bye, e out
+    pub fn from_espn(clincher: Option<&str>) -> Clincher {
+        match clincher {
+            Some("x") => Clincher::Berth,
+            Some("y") => Clincher::Wildcard,
+            Some("z") => Clincher::Division,
+            Some("*") => Clincher::TopSeed,
+            Some("e") => Clincher::Eliminated,
+            _ => Clincher::None,
         }
     }
 }
 
+// Winners are indexed by `Division`, slots stay empty until ESPN seeds them
 #[derive(Debug, Default)]
 pub struct PlayoffPicture {
-    pub afc_winners: [String; 4],
-    pub nfc_winners: [String; 4],
-    pub afc_wildcards: Vec<String>,
-    pub nfc_wildcards: Vec<String>,
+    pub afc_winners: [Option<Team>; 4],
+    pub nfc_winners: [Option<Team>; 4],
+    pub afc_wildcards: Vec<Team>,
+    pub nfc_wildcards: Vec<Team>,
+    pub afc_top_seed: Option<Team>,
+    pub nfc_top_seed: Option<Team>,
+    pub champion: Option<Team>,
+    pub clinch: HashMap<Team, Clincher>,
     pub reg_season_over: bool,
 }
 
-pub async fn get_playoff_picture(season: u16) -> PlayoffPicture {
-    let standings_url = env::var("STANDINGS_URL")
-        .expect("![Football] Could not find 'STANDINGS_URL' env var");
+impl PlayoffPicture {
+    // A berth says nothing about the slot, the pick only settles once that slot is locked by someone
+    fn clinch_status(&self, team: &Team, slot: &CapsuleType) -> ClinchStatus {
+        let Some(&clincher) = self.clinch.get(team) else { return ClinchStatus::InHunt };
+        let rival_has = |clinchers: &[Clincher], same_division: bool| self.clinch.iter().any(|(t, c)| {
+            t != team
+                && clinchers.contains(c)
+                && t.conference() == team.conference()
+                && (!same_division || t.division() == team.division())
+        });
 
+        match (slot, clincher) {
+            (_, Clincher::Eliminated) => ClinchStatus::Eliminated,
+            (CapsuleType::DivisionWinner, Clincher::Division | Clincher::TopSeed) => ClinchStatus::Clinched,
+            (CapsuleType::DivisionWinner, Clincher::Wildcard) => ClinchStatus::Eliminated,
+            (CapsuleType::DivisionWinner, _) if rival_has(&[Clincher::Division, Clincher::TopSeed], true) => ClinchStatus::Eliminated,
+            (CapsuleType::Wildcard, Clincher::Wildcard) => ClinchStatus::Clinched,
+            (CapsuleType::Wildcard, Clincher::Division | Clincher::TopSeed) => ClinchStatus::Eliminated,
+            (CapsuleType::TopSeed, Clincher::TopSeed) => ClinchStatus::Clinched,
+            (CapsuleType::TopSeed, Clincher::Wildcard) => ClinchStatus::Eliminated,
+            (CapsuleType::TopSeed, _) if rival_has(&[Clincher::TopSeed], false) => ClinchStatus::Eliminated,
+            _ => ClinchStatus::InHunt,
+        }
+    }
+}
+
+// The Super Bowl comes from the scoreboard at `data_url`, once the bracket is set
+#[instrument(level = "debug", skip(standings_url, data_url))]
+pub async fn get_playoff_picture(standings_url: &str, data_url: &str, season: u16) -> Result<PlayoffPicture> {
     let url = format!("{}?season={}&type=0&level=3", standings_url, season);
-    let res = reqwest::get(url).await
-        .expect("![Football] Could not get standings reply")
-        .text().await
-        .expect("![Football] Could not retrieve standings text");
-    let standings: ESPNStandings = serde_json::from_str(&res)
-        .expect("![Football] Could not parse standings response");
+    let standings: ESPNStandings = espn_get("standings", url).await
+        .map_err(|e| anyhow!("Could not get the standings of {} : {}", season, e))?;
 
     let mut picture = PlayoffPicture::default();
 
@@ -577,13 +1141,10 @@ pub async fn get_playoff_picture(season: u16) -> PlayoffPicture {
 
     let mut reg_over = true;
 
-    for conference in &standings.children {
-        let conf = conference.abbreviation.as_str();
-        let mut wildcards: Vec<(u32, String)> = Vec::new();
+    let mut wildcards: Vec<(u32, Team)> = Vec::new();
 
+    for conference in &standings.children {
         for division in &conference.children {
-            let div_abbr = division.abbreviation.as_str();
-
             let entries = match &division.standings.entries {
                 Some(entries) => entries,
                 None => continue,
@@ -592,44 +1153,63 @@ pub async fn get_playoff_picture(season: u16) -> PlayoffPicture {
             for entry in entries {
                 if games_played(entry) < 17 { reg_over = false; }
 
+                let team = entry.team.parse()?;
+                let clincher = entry.stats.iter()
+                    .find(|s| s.name == "clincher")
+                    .map(|s| s.displayValue.as_str());
+                picture.clinch.insert(team, Clincher::from_espn(clincher));
+
                 let seed = entry.stats.iter()
                     .find(|s| s.name == "playoffSeed")
                     .and_then(|s| s.displayValue.parse::<u32>().ok());
 
-                if let Some(seed) = seed {
-                    let team = entry.team.abbreviation.clone();
-
-                    if (1..=4).contains(&seed) {
-                        let div = Division::from_espn(div_abbr);
-                        match conf {
-                            "AFC" => picture.afc_winners[div as usize] = team,
-                            "NFC" => picture.nfc_winners[div as usize] = team,
-                            _ => {}
+                let (winners, top_seed) = match team.conference() {
+                    Conference::AFC => (&mut picture.afc_winners, &mut picture.afc_top_seed),
+                    Conference::NFC => (&mut picture.nfc_winners, &mut picture.nfc_top_seed),
+                };
+                match seed {
+                    Some(seed @ 1..=4) => {
+                        if seed == 1 {
+                            *top_seed = Some(team);
                         }
-                    } else if (5..=7).contains(&seed) {
-                        wildcards.push((seed, team));
-                    }
+                        winners[team.division() as usize] = Some(team);
+                    },
+                    Some(seed @ 5..=7) => wildcards.push((seed, team)),
+                    _ => {},
                 }
             }
         }
+    }
 
-        wildcards.sort_by_key(|(seed, _)| *seed);
-        let wc_teams: Vec<String> = wildcards.into_iter().map(|(_, t)| t).collect();
-
-        match conf {
-            "AFC" => picture.afc_wildcards = wc_teams,
-            "NFC" => picture.nfc_wildcards = wc_teams,
-            _ => {}
+    wildcards.sort_by_key(|(seed, _)| *seed);
+    for (_, team) in wildcards {
+        match team.conference() {
+            Conference::AFC => picture.afc_wildcards.push(team),
+            Conference::NFC => picture.nfc_wildcards.push(team),
         }
     }
 
-    let complete = picture.afc_winners.iter().all(|t| !t.is_empty())
-        && picture.nfc_winners.iter().all(|t| !t.is_empty())
+    let complete = picture.afc_winners.iter().all(Option::is_some)
+        && picture.nfc_winners.iter().all(Option::is_some)
         && picture.afc_wildcards.len() == 3
         && picture.nfc_wildcards.len() == 3;
 
     picture.reg_season_over = reg_over && complete;
-    return picture;
+
+    // The Super Bowl is only worth looking up once the bracket is set
+    if picture.reg_season_over {
+        let final_after = Utc::now().checked_sub_signed(TimeDelta::hours(8)).unwrap();
+        picture.champion = get_week(data_url, &season, &Week::SuperBowl).await?
+            .into_iter()
+            .filter(|m| m.date < final_after)
+            .find_map(|m| match (m.away_score, m.home_score) {
+                (Some(a), Some(h)) if a > h => Some(m.away_team),
+                (Some(a), Some(h)) if h > a => Some(m.home_team),
+                _ => None,
+            });
+    }
+
+    Ok(picture)
 }
 
 #[derive(Debug, Default)]
@@ -639,12 +1219,15 @@ pub struct CapsuleResults {
     pub score: u32,
     pub icons: String,
     pub cache: bool,
+    pub provisional: bool,
 }
 
 #[derive(Debug)]
 enum CapsuleType {
     DivisionWinner,
     Wildcard,
+    TopSeed,
+    Champion,
 }
 
 fn get_capsule_score(state: CapsuleType, unique: bool) -> u32 {
@@ -653,69 +1236,257 @@ fn get_capsule_score(state: CapsuleType, unique: bool) -> u32 {
         (CapsuleType::DivisionWinner, false) => 2,
         (CapsuleType::Wildcard      , true)  => 2,
         (CapsuleType::Wildcard      , false) => 1,
+        (CapsuleType::TopSeed       , true)  => 4,
+        (CapsuleType::TopSeed       , false) => 2,
+        (CapsuleType::Champion      , true)  => 8,
+        (CapsuleType::Champion      , false) => 4,
     }
 }
 
-pub fn calc_playoff_picture(picture: &PlayoffPicture, picks: &HashMap<i64, CapsulePicks>) -> Vec<CapsuleResults> {
+// `repick_weight` is the percentage of the points a repicked slot is worth
+pub fn calc_playoff_picture(picture: &PlayoffPicture, picks: &HashMap<i64, CapsulePicks>, repick_weight: &u32) -> Vec<CapsuleResults> {
     let mut results = Vec::<CapsuleResults>::with_capacity(picks.len());
 
     for capsule in picks.values() {
         let mut score = 0;
+        // A champion pick keeps the capsule open until the Super Bowl is decided
+        let complete = picture.reg_season_over
+            && (capsule.champion.is_none() || picture.champion.is_some());
+        // Once graded, a capsule only gets rescored after its cache is cleared
+        let cache = complete && capsule.cached.is_none();
+
+        // While the season is live, flag each pick with its team's status for that slot
+        let icon = |team: Option<Team>, slot: CapsuleType| match team {
+            None => get_missing_icon(),
+            Some(team) if picture.reg_season_over => team.icon(),
+            Some(team) => format!("{}{}", team.icon(), picture.clinch_status(&team, &slot).icon()),
+        };
 
         let mut icons = String::new();
-        icons.push_str(&format!("<:AFC:{}>", get_afc_emoji()));
-        for team in &capsule.afc_wins {
-            icons.push_str(&format!("<:{}:{}>", team, get_team_emoji(team)));
+        icons.push_str(&Conference::AFC.icon());
+        for &team in &capsule.afc_wins {
+            icons.push_str(&icon(team, CapsuleType::DivisionWinner));
         }
         icons.push('|');
-        for team in &capsule.afc_wildcards {
-            icons.push_str(&format!("<:{}:{}>", team, get_team_emoji(team)));
+        for &team in &capsule.afc_wildcards {
+            icons.push_str(&icon(team, CapsuleType::Wildcard));
         }
-        icons.push_str(&format!(" - <:NFC:{}>", get_nfc_emoji()));
-        for team in &capsule.nfc_wins {
-            icons.push_str(&format!("<:{}:{}>", team, get_team_emoji(team)));
+        icons.push_str(&format!(" - {}", Conference::NFC.icon()));
+        for &team in &capsule.nfc_wins {
+            icons.push_str(&icon(team, CapsuleType::DivisionWinner));
         }
         icons.push('|');
-        for team in &capsule.nfc_wildcards {
-            icons.push_str(&format!("<:{}:{}>", team, get_team_emoji(team)));
+        for &team in &capsule.nfc_wildcards {
+            icons.push_str(&icon(team, CapsuleType::Wildcard));
+        }
+        if capsule.champion.is_some() {
+            icons.push_str(&format!(" - #1 {}{} :trophy: {}",
+                icon(capsule.afc_top_seed, CapsuleType::TopSeed), icon(capsule.nfc_top_seed, CapsuleType::TopSeed),
+                icon(capsule.champion, CapsuleType::Champion)));
         }
 
+        let weigh = |points: u32, repicked: bool| match repicked {
+            true => points * repick_weight / 100,
+            false => points,
+        };
+
         for i in 0..4 {
-            let nfc = &capsule.nfc_wins[i];
-            assert!(!nfc.is_empty(), "empty NFC winner: poolerid={}, div={}", capsule.poolerid, i);
-            if *nfc == picture.nfc_winners[i] {
-                score += get_capsule_score(CapsuleType::DivisionWinner, capsule.nfc_wins_counts[i] == 1);
+            let nfc = capsule.nfc_wins[i];
+            assert!(nfc.is_some(), "empty NFC winner: poolerid={}, div={}", capsule.poolerid, i);
+            if nfc == picture.nfc_winners[i] {
+                score += weigh(get_capsule_score(CapsuleType::DivisionWinner, capsule.nfc_wins_counts[i] == 1),
+                               capsule.nfc_wins_repicked[i]);
             }
 
-            let afc = &capsule.afc_wins[i];
-            assert!(!afc.is_empty(), "empty AFC winner: poolerid={}, div={}", capsule.poolerid, i);
-            if *afc == picture.afc_winners[i] {
-                score += get_capsule_score(CapsuleType::DivisionWinner, capsule.afc_wins_counts[i] == 1);
+            let afc = capsule.afc_wins[i];
+            assert!(afc.is_some(), "empty AFC winner: poolerid={}, div={}", capsule.poolerid, i);
+            if afc == picture.afc_winners[i] {
+                score += weigh(get_capsule_score(CapsuleType::DivisionWinner, capsule.afc_wins_counts[i] == 1),
+                               capsule.afc_wins_repicked[i]);
             }
         }
 
-        score += capsule.nfc_wildcards.iter().fold(0, |score, team| {
-            score + match picture.nfc_wildcards.contains(team) {
-                true => get_capsule_score(CapsuleType::Wildcard, false),
-                false => 0,
+        for i in 0..3 {
+            if capsule.nfc_wildcards[i].is_some_and(|t| picture.nfc_wildcards.contains(&t)) {
+                score += weigh(get_capsule_score(CapsuleType::Wildcard, capsule.nfc_wild_counts[i] == 1),
+                               capsule.nfc_wild_repicked[i]);
             }
-        });
-        score += capsule.afc_wildcards.iter().fold(0, |score, team| {
-            score + match picture.afc_wildcards.contains(team) {
-                true => get_capsule_score(CapsuleType::Wildcard, false),
-                false => 0,
+            if capsule.afc_wildcards[i].is_some_and(|t| picture.afc_wildcards.contains(&t)) {
+                score += weigh(get_capsule_score(CapsuleType::Wildcard, capsule.afc_wild_counts[i] == 1),
+                               capsule.afc_wild_repicked[i]);
             }
-        });
+        }
+
+        // Seeding is only settled once the regular season is over
+        if picture.reg_season_over {
+            if capsule.nfc_top_seed.is_some() && capsule.nfc_top_seed == picture.nfc_top_seed {
+                score += weigh(get_capsule_score(CapsuleType::TopSeed, capsule.nfc_top_seed_count == 1),
+                               capsule.nfc_top_seed_repicked);
+            }
+            if capsule.afc_top_seed.is_some() && capsule.afc_top_seed == picture.afc_top_seed {
+                score += weigh(get_capsule_score(CapsuleType::TopSeed, capsule.afc_top_seed_count == 1),
+                               capsule.afc_top_seed_repicked);
+            }
+        }
+        if picture.champion.is_some() && picture.champion == capsule.champion {
+            score += weigh(get_capsule_score(CapsuleType::Champion, capsule.champion_count == 1),
+                           capsule.champion_repicked);
+        }
 
         results.push(CapsuleResults {
             poolerid: capsule.poolerid,
             name: capsule.name.clone(),
-            score,
+            score: capsule.cached.unwrap_or(score),
             icons,
-            cache: false,
+            cache,
+            provisional: !complete && capsule.cached.is_none(),
         });
     }
 
     results.sort_unstable_by(|l, r| r.score.cmp(&l.score));
     results
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn week_numbers_round_trip() {
+        for n in 1..=22 {
+            assert_eq!(Week::try_from(n).unwrap().number(), n);
+        }
+        assert_eq!(Week::all().count(), 22);
+    }
+
+    #[test]
+    fn playoff_rounds_map_to_espn() {
+        let rounds = [
+            (Week::WildCard, (3, 1)),
+            (Week::Divisional, (3, 2)),
+            (Week::Conference, (3, 3)),
+            (Week::SuperBowl, (3, 5)),
+        ];
+        for (week, params) in rounds {
+            assert_eq!(week.espn_params(), params);
+            assert_eq!(Week::from_espn(params.0, params.1), Some(week));
+        }
+        assert_eq!(Week::Regular(7).espn_params(), (2, 7));
+        // Postseason week 4 is the Pro Bowl break
+        assert_eq!(Week::from_espn(3, 4), None);
+    }
+
+    #[test]
+    fn out_of_range_weeks_are_rejected() {
+        assert!(Week::try_from(0).is_err());
+        assert!(Week::try_from(23).is_err());
+        assert!("semaine".parse::<Week>().is_err());
+
+        assert!(Week::in_season(&2020, 18).is_err());
+        assert_eq!(Week::in_season(&2020, 17).unwrap(), Week::Regular(17));
+        assert_eq!(Week::in_season(&2020, 19).unwrap(), Week::WildCard);
+        assert_eq!(Week::in_season(&2024, 18).unwrap(), Week::Regular(18));
+    }
+
+    fn results(scores: &[(i64, u32)]) -> Vec<PickResults> {
+        scores.iter().map(|&(poolerid, score)| PickResults {
+            pickid: Some(poolerid),
+            poolerid,
+            name: format!("pooler {}", poolerid),
+            score,
+            featscore: 0,
+            icons: String::new(),
+            overunder: String::new(),
+            cache: false,
+        }).collect()
+    }
+
+    fn season() -> Vec<(Week, Vec<PickResults>)> {
+        vec![
+            (Week::Regular(1), results(&[(1, 10), (2, 8), (3, 8)])),
+            (Week::Regular(2), results(&[(1, 2), (2, 5), (3, 4)])),
+        ]
+    }
+
+    #[test]
+    fn standings_share_tied_ranks_and_track_movement() {
+        let standings = calc_standings_history(&season(), &HashMap::new());
+
+        let rows: Vec<_> = standings.iter()
+            .map(|e| (e.poolerid, e.total(), e.ranks.clone(), e.movement()))
+            .collect();
+        assert_eq!(rows, [
+            (2, 13, vec![2, 1], 1),
+            (1, 12, vec![1, 2], -1),
+            (3, 12, vec![2, 2], 0),
+        ]);
+    }
+
+    #[test]
+    fn capsule_points_only_rank_the_last_week() {
+        let standings = calc_standings_history(&season(), &HashMap::from([(3, 2)]));
+
+        let rows: Vec<_> = standings.iter()
+            .map(|e| (e.poolerid, e.total(), e.ranks.clone(), e.movement()))
+            .collect();
+        assert_eq!(rows, [
+            (3, 12, vec![2, 1], 1),
+            (2, 13, vec![2, 2], 0),
+            (1, 12, vec![1, 3], -2),
+        ]);
+    }
+
+    #[test]
+    fn repicked_seeds_and_champion_are_weighed() {
+        let picture = PlayoffPicture {
+            afc_top_seed: Some(Team::Chiefs),
+            nfc_top_seed: Some(Team::Lions),
+            champion: Some(Team::Chiefs),
+            reg_season_over: true,
+            ..Default::default()
+        };
+        let capsule = CapsulePicks {
+            poolerid: 1,
+            nfc_wins: [Some(Team::Cowboys); 4],
+            afc_wins: [Some(Team::Bills); 4],
+            afc_top_seed: Some(Team::Chiefs),
+            afc_top_seed_count: 1,
+            afc_top_seed_repicked: true,
+            nfc_top_seed: Some(Team::Lions),
+            nfc_top_seed_count: 2,
+            champion: Some(Team::Chiefs),
+            champion_count: 1,
+            champion_repicked: true,
+            ..Default::default()
+        };
+
+        let results = calc_playoff_picture(&picture, &HashMap::from([(1, capsule)]), &50);
+        // Half of the unique top seed (4) and champion (8), all of the shared top seed (2)
+        assert_eq!(results[0].score, 2 + 2 + 4);
+    }
+
+    #[test]
+    fn team_codes_and_aliases_parse() {
+        let codes = [
+            ("LAR", Team::Rams), ("LA", Team::Rams),
+            ("WSH", Team::Commanders), ("WAS", Team::Commanders),
+            ("Washington", Team::Commanders), ("Washington Redskins", Team::Commanders),
+            ("LV", Team::Raiders), ("OAK", Team::Raiders), ("Oakland Raiders", Team::Raiders),
+            ("kc", Team::Chiefs), ("Kansas City Chiefs", Team::Chiefs),
+        ];
+        for (code, team) in codes {
+            assert_eq!(code.parse::<Team>().unwrap(), team, "{}", code);
+        }
+        for team in Team::all() {
+            assert_eq!(team.code().parse::<Team>().unwrap(), team);
+            assert_eq!(team.name().parse::<Team>().unwrap(), team);
+        }
+    }
+
+    #[test]
+    fn unknown_team_codes_are_rejected() {
+        assert!("XYZ".parse::<Team>().is_err());
+        assert!("NA".parse::<Team>().is_err());
+        assert!("".parse::<Team>().is_err());
+    }
+}
@@ -0,0 +1,48 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use hyper::{Body, Method, Request, Response, StatusCode};
+use serde_json::json;
+use serenity::client::bridge::gateway::ShardManager;
+use serenity::gateway::ConnectionStage;
+use serenity::prelude::Mutex;
+
+use library::database::DB;
+use library::metrics;
+
+use crate::server;
+
+// Meant to be bound on localhost and scraped by the box's own monitoring
+pub async fn serve(addr: SocketAddr, shards: Arc<Mutex<ShardManager>>, db: DB) {
+    server::serve("health endpoint", addr, move |req| route(req, shards.clone(), db.clone())).await;
+}
+
+async fn route(req: Request<Body>, shards: Arc<Mutex<ShardManager>>, db: DB) -> Result<Response<Body>, Infallible> {
+    let res = match (req.method(), req.uri().path()) {
+        (&Method::GET, "/health") => health(&shards, &db).await,
+        (&Method::GET, "/metrics") => Response::builder()
+            .header("Content-Type", "text/plain; version=0.0.4")
+            .body(Body::from(metrics::render())),
+        _ => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty()),
+    };
+
+    Ok(res.unwrap_or_else(|_| Response::new(Body::empty())))
+}
+
+async fn health(shards: &Mutex<ShardManager>, db: &DB) -> hyper::http::Result<Response<Body>> {
+    let gateway = {
+        let manager = shards.lock().await;
+        let runners = manager.runners.lock().await;
+        !runners.is_empty() && runners.values().all(|r| r.stage == ConnectionStage::Connected)
+    };
+    let database = db.ping().await.is_ok();
+
+    let status = if gateway && database { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(json!({ "gateway": gateway, "database": database }).to_string()))
+}
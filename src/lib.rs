@@ -1,2 +1,11 @@
 pub mod database;
 pub mod football;
+pub mod awards;
+pub mod survivor;
+pub mod autopick;
+pub mod validation;
+pub mod standings;
+pub mod site;
+pub mod calendar;
+pub mod config;
+pub mod metrics;
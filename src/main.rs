@@ -1,21 +1,70 @@
 use dotenv::dotenv;
-use library::football::{list_emoji_names, sync_emojis};
+use futures::FutureExt;
+use library::football::{list_asset_emojis, list_emoji_names, sync_emojis};
 use serenity::utils::read_image;
 use std::env;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::panic::AssertUnwindSafe;
+use std::time::{Duration, Instant};
+use tokio::spawn;
+use tokio::sync::watch;
+use tracing::{Instrument, debug_span, error, info, info_span, warn};
+use tracing_subscriber::EnvFilter;
 
 use serenity::async_trait;
-use serenity::model::application::interaction::Interaction;
+use serenity::model::application::interaction::{Interaction, InteractionResponseType};
+use serenity::model::application::interaction::application_command::ApplicationCommandInteraction;
 use serenity::model::channel::Message;
 use serenity::model::gateway::Ready;
 use serenity::model::id::GuildId;
 use serenity::prelude::*;
 
+use library::autopick::fill_default_picks;
+use library::calendar::{current_season, current_week};
+use library::config::{Config, LogFormat};
 use library::database::DB;
+use library::metrics;
+use library::site::export_season;
+use library::survivor::resolve_survivor;
+use library::validation::check_season_picks;
 
+use commands::SlashCommand;
+use shutdown::{Shutdown, wait_for_signal};
+
+mod api;
 mod commands;
+mod health;
+mod public;
+mod server;
+mod shutdown;
+
+// Long enough for a /resultat to post all its messages
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Served as is by the web app, next to its own static files
+const ARCHIVE_DIR: &str = "web/public/archives";
 
 struct Bot {
+    config: &'static Config,
     database: DB,
+    jobs_started: AtomicBool,
+    shutdown: Arc<Shutdown>,
+    commands: Vec<Box<dyn SlashCommand>>,
+}
+
+impl Bot {
+    async fn run_command(&self, ctx: &Context, cmd: &ApplicationCommandInteraction) -> anyhow::Result<()> {
+        match self.commands.iter().find(|c| c.name() == cmd.data.name) {
+            Some(command) => command.run(ctx.clone(), cmd, &self.database, self.config).await,
+            None => {
+                // Registered by an older build, or disabled since
+                warn!(command = %cmd.data.name, "Unknown command");
+                reply(ctx, cmd, "Cette commande n'existe pas ou a été désactivée.").await;
+                Ok(())
+            },
+        }
+    }
 }
 
 #[async_trait]
@@ -24,64 +73,86 @@ impl EventHandler for Bot {
         if msg.content == "!allo" {
             let reply = format!("Salut, {}!", msg.author.name);
             if let Err(reason) = msg.channel_id.say(&ctx.http, reply).await {
-                println!("![Handler] Handler message error : {:?}", reason);
+                error!("Handler message error : {:?}", reason);
             }
         }
     }
 
     async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
         if let Interaction::ApplicationCommand(cmd) = interaction {
-            match cmd.data.name.as_str() {
-                "semaine"  => commands::matches::run(ctx, &cmd, &self.database).await,
-                "choix"    => commands::picks::run(ctx, &cmd, &self.database).await,
-                //"ping"   => commands::ping::run(ctx, &cmd).await,
-                "resultat" => commands::results::run(ctx, &cmd, &self.database).await,
-                "saison"   => commands::season::run(ctx, &cmd, &self.database).await,
-                "stats"    => commands::stats::run(ctx, &cmd, &self.database).await,
-                "equipe"   => commands::team::run(ctx, &cmd, &self.database).await,
-                "blame"    => commands::blame::run(ctx, &cmd, &self.database).await,
-                "features" => commands::features::run(ctx, &cmd, &self.database).await,
-                "capsule"         => commands::capsule::run(ctx, &cmd, &self.database).await,
-                "eliminatoires"   => commands::eliminatoires::run(ctx, &cmd, &self.database).await,
-                _                 => println!("![Handler] Command not implemented!"),
+            let span = info_span!("interaction",
+                id = %cmd.id, command = %cmd.data.name, user = %cmd.user.id, pool = self.config.pool_id);
+
+            let Some(_permit) = self.shutdown.track().await else {
+                info!(parent: &span, "Refused interaction, shutting down");
+                reply(&ctx, &cmd, "Le bot redémarre, réessaie dans une minute.").await;
+                return;
+            };
+            let started = Instant::now();
+
+            // A panic left in a command is still reported as the interaction's outcome
+            let outcome = AssertUnwindSafe(self.run_command(&ctx, &cmd))
+                .catch_unwind()
+                .instrument(span.clone())
+                .await;
+
+            let elapsed = started.elapsed();
+            let duration_ms = elapsed.as_millis() as u64;
+            let outcome = match outcome {
+                Ok(Ok(())) => {
+                    info!(parent: &span, duration_ms, outcome = "ok", "Interaction handled");
+                    "ok"
+                },
+                Ok(Err(e)) => {
+                    error!(parent: &span, duration_ms, outcome = "error", "Interaction failed : {:#}", e);
+                    reply_error(&ctx, &cmd).await;
+                    "error"
+                },
+                Err(_) => {
+                    error!(parent: &span, duration_ms, outcome = "panic", "Interaction panicked");
+                    reply_error(&ctx, &cmd).await;
+                    "panic"
+                },
             };
+            metrics::record_command(&cmd.data.name, outcome, elapsed);
+        }
+        else if let Interaction::Autocomplete(autocomplete) = interaction {
+            let span = debug_span!("autocomplete",
+                id = %autocomplete.id, command = %autocomplete.data.name, user = %autocomplete.user.id);
+
+            commands::autocomplete::run(ctx, &autocomplete, &self.database, self.config)
+                .instrument(span)
+                .await;
         }
     }
 
     async fn ready(&self, ctx: Context, ready: Ready) {
-        println!("[Handler] ({}) {} is connected w/ version: {}\n",
+        info!("({}) {} is connected w/ version: {}",
             ready.user.id,
             ready.user.name,
             ready.version
         );
 
-        let guild_id = GuildId(env::var("GUILD_ID")
-            .expect("![Handler] Could not find env var 'GUILD_ID'")
-            .parse()
-            .expect("![Handler] Could not parse guild_id to int")
-        );
+        let guild_id = GuildId(self.config.guild_id);
 
         let emojis = guild_id.emojis(&ctx.http).await.expect("![Handler] Could not fetch all server emojis");
         sync_emojis(&emojis);
 
         let commands = GuildId::set_application_commands(&guild_id, &ctx.http, |cmds| {
+            for command in &self.commands {
+                cmds.create_application_command(|cmd| commands::create(command.as_ref(), cmd));
+            }
             cmds
-                .create_application_command(|cmd| commands::matches::register(cmd))
-                .create_application_command(|cmd| commands::picks::register(cmd))
-                //.create_application_command(|cmd| commands::ping::register(cmd))
-                .create_application_command(|cmd| commands::results::register(cmd))
-                .create_application_command(|cmd| commands::season::register(cmd))
-                .create_application_command(|cmd| commands::stats::register(cmd))
-                .create_application_command(|cmd| commands::team::register(cmd))
-                .create_application_command(|cmd| commands::blame::register(cmd))
-                .create_application_command(|cmd| commands::features::register(cmd))
-                .create_application_command(|cmd| commands::capsule::register(cmd))
-                .create_application_command(|cmd| commands::eliminatoires::register(cmd))
         }).await.expect("![Handler] Could not set application commands in Discord Guild");
 
-        println!("[Handler] Here are the available commands:");
-        commands.iter()
-            .for_each(|c| println!("\t-{}", c.name));
+        let names: Vec<_> = commands.iter().map(|c| c.name.as_str()).collect();
+        info!(commands = ?names, "Registered {} application commands", names.len());
+
+        // `ready` fires again on every reconnect, only start the jobs once
+        if !self.jobs_started.swap(true, Ordering::SeqCst) {
+            let job = hourly_job(self.database.clone(), self.config, self.shutdown.subscribe());
+            self.shutdown.spawn_job(job.instrument(info_span!("hourly_job")));
+        }
 
         /*
         spawn(async move {
@@ -89,7 +160,7 @@ impl EventHandler for Bot {
 
             if let Ok(hook_url) = env::var("WEEKLY_WEBHOOK") {
                 let hook = Webhook::from_url(&ctx.http, hook_url.as_str()).await.unwrap();
-                println!("[Handler] Webhook created and ready to fire");
+                info!("Webhook created and ready to fire");
 
                 loop {
                     let now = Local::now();
@@ -98,11 +169,8 @@ impl EventHandler for Bot {
                         let poolid = env::var("POOL_ID")
                             .expect("![Handler] Could not find env var 'POOL_ID'").parse()
                             .expect("![Handler] Could not parse pool_id to int");
-                        let season = env::var("CONF_SEASON")
-                            .expect("![Handler] Cannot find 'CONF_SEASON' in env").parse()
-                            .expect("![Handler] Could not parse 'CONF_SEASON' to u16");
-                        let week = db.find_week(&poolid, &season).await
-                            .expect("![Handler] Could not find current week from DB");
+                        let (season, week) = current_week(&db).await
+                            .expect("![Handler] Could not find the current week");
 
                         let matches = weekly_matches_message(&season, &week).await;
                         let results = weekly_results_message(&db, &poolid, &season, &week).await;
@@ -127,8 +195,80 @@ impl EventHandler for Bot {
     }
 }
 
+async fn hourly_job(db: DB, config: &Config, mut stop: watch::Receiver<bool>) {
+    while !*stop.borrow() {
+        // Looked up every time so the job follows the season rollover
+        let resolved = match current_season(&db, &config.data_url).await {
+            Ok(season) => resolve_survivor(&db, &config.data_url, &config.pool_id, &season).await,
+            Err(e) => Err(e),
+        };
+
+        match resolved {
+            Ok(0) => {},
+            Ok(count) => info!("Resolved {} survivor picks", count),
+            Err(e) => error!("Could not resolve survivor picks : {}", e),
+        }
+
+        let filled = match current_week(&db, &config.data_url).await {
+            Ok((season, week)) => fill_default_picks(&db, &config.data_url, &config.pool_id, &season, &week).await,
+            Err(e) => Err(e),
+        };
+
+        match filled {
+            Ok(0) => {},
+            Ok(count) => info!("Filled in default picks for {} poolers", count),
+            Err(e) => error!("Could not fill in default picks : {}", e),
+        }
+
+        match db.purge_pick_tokens().await {
+            Ok(0) => {},
+            Ok(count) => info!("Purged {} expired pick tokens", count),
+            Err(e) => error!("Could not purge expired pick tokens : {}", e),
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(3600)) => {},
+            _ = stop.changed() => {},
+        }
+    }
+}
+
+async fn reply(ctx: &Context, cmd: &ApplicationCommandInteraction, content: &str) {
+    if let Err(reason) = cmd.create_interaction_response(&ctx.http, |res| {
+        res
+            .kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|m| m
+                .ephemeral(true)
+                .content(content)
+            )
+    })
+    .await {
+        error!("Cannot respond to slash command : {:?}", reason);
+    }
+}
+
+// Deferred commands already answered "Calcul ...", that answer is replaced instead
+async fn reply_error(ctx: &Context, cmd: &ApplicationCommandInteraction) {
+    let content = format!("Une erreur s'est produite avec la commande `/{}`.", cmd.data.name);
+    let created = cmd.create_interaction_response(&ctx.http, |res| {
+        res
+            .kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|m| m
+                .ephemeral(true)
+                .content(&content)
+            )
+    })
+    .await;
+
+    if created.is_err() {
+        if let Err(reason) = cmd.edit_original_interaction_response(&ctx.http, |res| res.content(&content)).await {
+            error!("Cannot respond to slash command : {:?}", reason);
+        }
+    }
+}
+
 /*
-async fn weekly_matches_message(season: &u16, week: &i64) -> String {
+async fn weekly_matches_message(season: &u16, week: &Week) -> String {
     const VS_EMOJI: &str = "<:VS:1102123108187525130>";
     let matches = get_week(&season, &week).await;
 
@@ -158,77 +298,199 @@ async fn weekly_matches_message(season: &u16, week: &i64) -> String {
 */
 
 /*
-async fn weekly_results_message(_db: &DB, _poolid: &i64, _season: &u16, _week: &i64) -> String {
+async fn weekly_results_message(_db: &DB, _poolid: &i64, _season: &u16, _week: &Week) -> String {
     //TODO: Bring changes from results.rs
     String::new()
 }
 */
 
-async fn reset_emojis(token: &str) {
-    let http = serenity::http::Http::new(token);
-    let guild = GuildId(env::var("GUILD_ID")
-        .expect("![Handler] Could not find env var 'GUILD_ID'")
-        .parse()
-        .expect("![Handler] Could not parse guild_id to int")
-    );
+async fn reset_emojis(config: &Config) {
+    let http = serenity::http::Http::new(&config.discord_token);
+    let guild = GuildId(config.guild_id);
 
     let emojis: Vec<_> = guild.emojis(&http).await
        .expect("![Handler] Could not fetch all server emojis");
 
     let names = list_emoji_names();
+    let assets = list_asset_emojis();
     let filtered: Vec<_> = emojis
-       .iter().filter(|&e| names.contains(&e.name.as_str()))
+       .iter().filter(|&e| names.contains(&e.name.as_str()) || assets.iter().any(|(n, _)| *n == e.name))
        .collect();
 
     for e in &filtered {
         match guild.delete_emoji(&http, e.id).await {
-            Ok(_) => println!("[SYNC-EMOJIS] Successfully deleted emoji {} -> {}", e.id, e.name),
-            Err(err) => println!("[SYNC-EMOJIS] Could not delete emoji {} -> {} : {err}", e.id, e.name),
+            Ok(_) => info!("Successfully deleted emoji {} -> {}", e.id, e.name),
+            Err(err) => warn!("Could not delete emoji {} -> {} : {err}", e.id, e.name),
         }
     }
 
     let folder = "./web/public/teams";
-    let prefix = env::var("EMOJI_PREFIX")
-        .expect("![MAIN] Cannot find 'EMOJI_PREFIX' in env");
+    let prefix = &config.emoji_prefix;
 
-    for &name in names {
+    for name in names {
         let path = format!("{folder}/{prefix}{name}.png");
         let img = match read_image(&path) {
             Ok(img) => img,
-            Err(e) => { println!("[SYNC-EMOJIS] Skipping {name}, can't read {path} : {e}"); continue; },
+            Err(e) => { warn!("Skipping {name}, can't read {path} : {e}"); continue; },
         };
 
         match guild.create_emoji(&http, name, &img).await {
-            Ok(emoji) => println!("[SYNC-EMOJIS] Successfully added new emoji {} -> {}", emoji.id, emoji.name),
-            Err(err) => println!("[SYNC-EMOJIS] Could not create emoji {} : {err}", name),
+            Ok(emoji) => info!("Successfully added new emoji {} -> {}", emoji.id, emoji.name),
+            Err(err) => warn!("Could not create emoji {} : {err}", name),
         }
     }
-    return;
+
+    for &(name, path) in assets {
+        let img = match read_image(path) {
+            Ok(img) => img,
+            Err(e) => { warn!("Skipping {name}, can't read {path} : {e}"); continue; },
+        };
+
+        match guild.create_emoji(&http, name, &img).await {
+            Ok(emoji) => info!("Successfully added new emoji {} -> {}", emoji.id, emoji.name),
+            Err(err) => warn!("Could not create emoji {} : {err}", name),
+        }
+    }
+}
+
+// Reports every saved submission that breaks the pick rules, the current season by default
+async fn check_picks(config: &Config, season: Option<u16>) -> bool {
+    let db = DB::new(&config.database_url).await;
+    let season = match season {
+        Some(season) => season,
+        None => current_season(&db, &config.data_url).await
+            .expect("![MAIN] Could not find the current season"),
+    };
+
+    match check_season_picks(&db, &config.data_url, &config.pool_id, &season).await {
+        Ok(issues) if issues.is_empty() => {
+            info!("All picks are valid for season {}", season);
+            true
+        },
+        Ok(issues) => {
+            for issue in &issues {
+                warn!("{} - {} (pick {}) : {}", issue.week, issue.name, issue.pickid, issue.error);
+            }
+            warn!("{} problems found in the picks of season {}", issues.len(), season);
+            false
+        },
+        Err(e) => {
+            error!("Could not check the picks of season {} : {}", season, e);
+            false
+        },
+    }
+}
+
+// Static copy of the season under `web/public/archives`, viewable without the bot
+async fn export_site(config: &Config, season: Option<u16>) -> bool {
+    let db = DB::new(&config.database_url).await;
+    let season = match season {
+        Some(season) => season,
+        None => current_season(&db, &config.data_url).await
+            .expect("![MAIN] Could not find the current season"),
+    };
+
+    let dir = std::path::Path::new(ARCHIVE_DIR);
+    match export_season(&db, config, &config.pool_id, &season, dir).await {
+        Ok(count) => {
+            info!("Exported {} files for season {} to {}/{}", count, season, ARCHIVE_DIR, season);
+            true
+        },
+        Err(e) => {
+            error!("Could not export season {} : {}", season, e);
+            false
+        },
+    }
+}
+
+fn init_tracing(config: &Config) {
+    // Already validated when the config was loaded
+    let filter = EnvFilter::new(&config.log_level);
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+
+    match config.log_format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder.json().with_current_span(true).with_span_list(true).init(),
+    }
 }
 
 #[tokio::main]
 async fn main() {
     dotenv().ok(); // Include .env file to environment
 
-    let token = env::var("DISCORD_TOKEN")
-        .expect("![MAIN] Cannot find 'DISCORD_TOKEN' in env");
+    let config = match Config::load() {
+        // Lives as long as the process, shared by the bot and the HTTP servers
+        Ok(config) => &*Box::leak(Box::new(config)),
+        Err(e) => {
+            // Logging isn't set up yet, the level and format are part of the config
+            eprintln!("![MAIN] {}", e);
+            std::process::exit(1);
+        },
+    };
+    init_tracing(config);
 
     if env::args().any(|v| v == "--sync-emojis") {
-        reset_emojis(&token).await;
+        reset_emojis(config).await;
         return;
     }
 
+    if let Some(pos) = env::args().position(|v| v == "--check-picks") {
+        let season = env::args().nth(pos + 1).and_then(|s| s.parse().ok());
+        let valid = check_picks(config, season).await;
+        std::process::exit(if valid { 0 } else { 1 });
+    }
+
+    if let Some(pos) = env::args().position(|v| v == "--export-site") {
+        let season = env::args().nth(pos + 1).and_then(|s| s.parse().ok());
+        let exported = export_site(config, season).await;
+        std::process::exit(if exported { 0 } else { 1 });
+    }
+
     let intents = GatewayIntents::GUILD_MESSAGES
         | GatewayIntents::DIRECT_MESSAGES
         | GatewayIntents::MESSAGE_CONTENT
         | GatewayIntents::GUILD_EMOJIS_AND_STICKERS;
 
-    let mut client = Client::builder(token, intents)
-        .event_handler(Bot { database: DB::new().await })
+    let database = DB::new(&config.database_url).await;
+    let shutdown = Shutdown::new();
+    let bot = Bot {
+        config,
+        database: database.clone(),
+        jobs_started: AtomicBool::new(false),
+        shutdown: shutdown.clone(),
+        commands: commands::registry(config),
+    };
+    let mut client = Client::builder(&config.discord_token, intents)
+        .event_handler(bot)
         .await
         .expect("![MAIN] Could not create client");
 
+    if let Some(addr) = config.health_addr {
+        spawn(health::serve(addr, client.shard_manager.clone(), database.clone()));
+    }
+    if let Some(addr) = config.api_addr {
+        spawn(api::serve(addr, database.clone(), config, shutdown.clone()));
+    }
+    if let Some(addr) = config.public_addr {
+        spawn(public::serve(addr, database.clone(), config));
+    }
+
+    // New interactions are refused first, then the in-flight ones and the jobs get
+    // to finish before the gateway and the DB pool go away. `client.start()` keeps
+    // running meanwhile, its shard monitor is what lets `shutdown_all` return early.
+    let shard_manager = client.shard_manager.clone();
+    spawn(async move {
+        wait_for_signal().await;
+        if shutdown.drain(SHUTDOWN_TIMEOUT).await {
+            info!("Every interaction and job finished");
+        }
+        shard_manager.lock().await.shutdown_all().await;
+    });
+
     if let Err(reason) = client.start().await {
-        println!("![MAIN] Client error : {:?}", reason);
+        error!("Client error : {:?}", reason);
     }
+
+    database.close().await;
+    info!("Shut down");
 }
@@ -0,0 +1,114 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+// Upper bounds of the command latency histogram, in seconds
+const LATENCY_BUCKETS: [f64; 8] = [0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+// Counters are process-wide, the football fetch functions record into them too
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry::new());
+
+struct Registry {
+    commands: BTreeMap<String, CommandStats>,
+    espn: BTreeMap<&'static str, (u64, u64)>,
+    caches: BTreeMap<&'static str, (u64, u64)>,
+}
+
+#[derive(Default)]
+struct CommandStats {
+    outcomes: BTreeMap<&'static str, u64>,
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Registry {
+    const fn new() -> Registry {
+        Registry { commands: BTreeMap::new(), espn: BTreeMap::new(), caches: BTreeMap::new() }
+    }
+}
+
+pub fn record_command(command: &str, outcome: &'static str, duration: Duration) {
+    let mut registry = REGISTRY.lock().unwrap();
+    let stats = registry.commands.entry(command.to_owned()).or_default();
+
+    let secs = duration.as_secs_f64();
+    *stats.outcomes.entry(outcome).or_default() += 1;
+    for (bucket, le) in stats.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+        if secs <= le {
+            *bucket += 1;
+        }
+    }
+    stats.sum += secs;
+    stats.count += 1;
+}
+
+pub fn record_espn(endpoint: &'static str, ok: bool) {
+    let mut registry = REGISTRY.lock().unwrap();
+    let (requests, failures) = registry.espn.entry(endpoint).or_default();
+
+    *requests += 1;
+    if !ok {
+        *failures += 1;
+    }
+}
+
+pub fn record_cache(cache: &'static str, hit: bool) {
+    let mut registry = REGISTRY.lock().unwrap();
+    let (hits, misses) = registry.caches.entry(cache).or_default();
+
+    if hit {
+        *hits += 1;
+    } else {
+        *misses += 1;
+    }
+}
+
+// Prometheus text exposition format
+pub fn render() -> String {
+    let registry = REGISTRY.lock().unwrap();
+    let mut out = String::new();
+
+    header(&mut out, "grebball_commands_total", "counter", "Slash commands handled, by outcome");
+    for (command, stats) in &registry.commands {
+        for (outcome, count) in &stats.outcomes {
+            let _ = writeln!(out, "grebball_commands_total{{command=\"{}\",outcome=\"{}\"}} {}", command, outcome, count);
+        }
+    }
+
+    header(&mut out, "grebball_command_duration_seconds", "histogram", "Time spent handling a slash command");
+    for (command, stats) in &registry.commands {
+        for (bucket, le) in stats.buckets.iter().zip(LATENCY_BUCKETS) {
+            let _ = writeln!(out, "grebball_command_duration_seconds_bucket{{command=\"{}\",le=\"{}\"}} {}", command, le, bucket);
+        }
+        let _ = writeln!(out, "grebball_command_duration_seconds_bucket{{command=\"{}\",le=\"+Inf\"}} {}", command, stats.count);
+        let _ = writeln!(out, "grebball_command_duration_seconds_sum{{command=\"{}\"}} {}", command, stats.sum);
+        let _ = writeln!(out, "grebball_command_duration_seconds_count{{command=\"{}\"}} {}", command, stats.count);
+    }
+
+    header(&mut out, "grebball_espn_requests_total", "counter", "Requests sent to ESPN, by endpoint");
+    for (endpoint, (requests, _)) in &registry.espn {
+        let _ = writeln!(out, "grebball_espn_requests_total{{endpoint=\"{}\"}} {}", endpoint, requests);
+    }
+    header(&mut out, "grebball_espn_failures_total", "counter", "ESPN requests that failed or returned an unreadable reply");
+    for (endpoint, (_, failures)) in &registry.espn {
+        let _ = writeln!(out, "grebball_espn_failures_total{{endpoint=\"{}\"}} {}", endpoint, failures);
+    }
+
+    header(&mut out, "grebball_cache_hits_total", "counter", "Lookups served from a DB cache");
+    for (cache, (hits, _)) in &registry.caches {
+        let _ = writeln!(out, "grebball_cache_hits_total{{cache=\"{}\"}} {}", cache, hits);
+    }
+    header(&mut out, "grebball_cache_misses_total", "counter", "Lookups that had to be computed or fetched");
+    for (cache, (_, misses)) in &registry.caches {
+        let _ = writeln!(out, "grebball_cache_misses_total{{cache=\"{}\"}} {}", cache, misses);
+    }
+
+    out
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
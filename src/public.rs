@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use hyper::{Body, Method, Request, Response, StatusCode};
+use serde_json::{Value, json};
+use tokio::sync::Mutex;
+use tracing::error;
+
+use library::calendar::current_season;
+use library::config::Config;
+use library::database::DB;
+use library::football::Week;
+use library::standings::{capsule_grades, season_standings, week_results};
+
+use crate::server;
+
+// Every standings request walks the whole season on ESPN, keep answers a little while
+const CACHE_TTL: Duration = Duration::from_secs(300);
+// Well above the pool's seasons times their weeks, only reached by someone probing
+const CACHE_SIZE: usize = 512;
+
+// A miss is built once, concurrent requests for it wait on its lock and read the result
+#[derive(Default)]
+struct Cache {
+    views: Mutex<HashMap<String, (Instant, Value)>>,
+    building: Mutex<HashMap<String, Arc<Mutex<()>>>>,
+}
+
+impl Cache {
+    async fn get(&self, key: &str) -> Option<Value> {
+        self.views.lock().await
+            .get(key)
+            .filter(|(at, _)| at.elapsed() < CACHE_TTL)
+            .map(|(_, value)| value.clone())
+    }
+
+    // Expired views go first, the oldest one when all are still fresh
+    async fn insert(&self, key: String, value: Value) {
+        let mut views = self.views.lock().await;
+        if views.len() >= CACHE_SIZE {
+            views.retain(|_, (at, _)| at.elapsed() < CACHE_TTL);
+        }
+        if views.len() >= CACHE_SIZE {
+            let oldest = views.iter().min_by_key(|(_, (at, _))| *at).map(|(k, _)| k.clone());
+            if let Some(oldest) = oldest {
+                views.remove(&oldest);
+            }
+        }
+        views.insert(key, (Instant::now(), value));
+    }
+
+    async fn building(&self, key: &str) -> Arc<Mutex<()>> {
+        self.building.lock().await.entry(key.to_owned()).or_default().clone()
+    }
+
+    // The last one out drops the lock, the map only holds views being built
+    async fn built(&self, key: &str, lock: Arc<Mutex<()>>) {
+        let mut building = self.building.lock().await;
+        drop(lock);
+        if building.get(key).is_some_and(|l| Arc::strong_count(l) == 1) {
+            building.remove(key);
+        }
+    }
+}
+
+// Read-only, safe to put behind a public reverse proxy unlike `API_ADDR`
+pub async fn serve(addr: SocketAddr, db: DB, config: &'static Config) {
+    let cache = Arc::new(Cache::default());
+    server::serve("public endpoints", addr, move |req| route(req, db.clone(), config, cache.clone())).await;
+}
+
+async fn route(req: Request<Body>, db: DB, config: &Config, cache: Arc<Cache>) -> Result<Response<Body>, Infallible> {
+    if req.method() != Method::GET {
+        return Ok(respond(StatusCode::METHOD_NOT_ALLOWED, json!({ "error": "Lecture seulement." })));
+    }
+
+    let path = req.uri().path().trim_matches('/').to_owned();
+    let segments: Vec<_> = path.split('/').collect();
+    let (kind, season, week) = match segments.as_slice() {
+        [kind @ ("standings" | "capsules")] => match current_season(&db, &config.data_url).await {
+            Ok(season) => (*kind, season, None),
+            Err(e) => return Ok(failed(&path, e)),
+        },
+        [kind @ ("standings" | "capsules"), season] => match season.parse::<u16>() {
+            Ok(season) => (*kind, season, None),
+            Err(_) => return Ok(respond(StatusCode::BAD_REQUEST, json!({ "error": "Saison invalide." }))),
+        },
+        ["results", season, week] => match season.parse::<u16>().map(|s| (s, week.parse::<i64>().ok().and_then(|w| Week::in_season(&s, w).ok()))) {
+            Ok((season, Some(week))) => ("results", season, Some(week)),
+            _ => return Ok(respond(StatusCode::BAD_REQUEST, json!({ "error": "Saison ou semaine invalide." }))),
+        },
+        _ => return Ok(respond(StatusCode::NOT_FOUND, json!({ "error": "Route inconnue." }))),
+    };
+
+    let key = match week {
+        Some(week) => format!("{}/{}/{}", kind, season, week.number()),
+        None => format!("{}/{}", kind, season),
+    };
+    if let Some(value) = cache.get(&key).await {
+        return Ok(respond(StatusCode::OK, value));
+    }
+
+    // A season in the path has to be one the pool played before ESPN gets asked,
+    // the bare routes follow the current one
+    if segments.len() > 1 {
+        match db.fetch_pool_seasons(&config.pool_id).await {
+            Ok(seasons) if seasons.contains(&season) => {},
+            Ok(_) => return Ok(respond(StatusCode::NOT_FOUND, json!({ "error": "Saison inconnue." }))),
+            Err(e) => return Ok(failed(&path, e)),
+        }
+    }
+
+    let lock = cache.building(&key).await;
+    let guard = lock.lock().await;
+    let res = match cache.get(&key).await {
+        Some(value) => Ok(value),
+        None => match view(&db, config, kind, season, week).await {
+            Ok(value) => {
+                cache.insert(key.clone(), value.clone()).await;
+                Ok(value)
+            },
+            Err(e) => Err(e),
+        },
+    };
+    drop(guard);
+    cache.built(&key, lock).await;
+
+    Ok(match res {
+        Ok(value) => respond(StatusCode::OK, value),
+        Err(e) => failed(&path, e),
+    })
+}
+
+fn failed(path: &str, e: anyhow::Error) -> Response<Body> {
+    error!("Could not build the public view for /{} : {}", path, e);
+    respond(StatusCode::INTERNAL_SERVER_ERROR, json!({ "error": "Une erreur s'est produite." }))
+}
+
+async fn view(db: &DB, config: &Config, kind: &str, season: u16, week: Option<Week>) -> anyhow::Result<Value> {
+    Ok(match (kind, week) {
+        ("results", Some(week)) => week_results(db, config, &config.pool_id, &season, &week).await?.to_json(),
+        ("capsules", _) => capsule_grades(db, config, &config.pool_id, &season).await?.to_json(),
+        _ => season_standings(db, config, &config.pool_id, &season).await?.to_json(),
+    })
+}
+
+fn respond(status: StatusCode, body: Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        // Meant to be read by any page, there's nothing private in it
+        .header("Access-Control-Allow-Origin", "*")
+        .body(Body::from(body.to_string()))
+        .unwrap_or_else(|_| Response::new(Body::empty()))
+}
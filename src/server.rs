@@ -0,0 +1,32 @@
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+
+use hyper::{Body, Request, Response, Server};
+use hyper::service::{make_service_fn, service_fn};
+use tracing::{error, info};
+
+// Binds `addr` and hands every request to `handle`, `name` is only for the logs
+pub async fn serve<F, R>(name: &str, addr: SocketAddr, handle: F)
+where
+    F: Fn(Request<Body>) -> R + Clone + Send + 'static,
+    R: Future<Output = Result<Response<Body>, Infallible>> + Send + 'static,
+{
+    let make_service = make_service_fn(move |_| {
+        let handle = handle.clone();
+        async move { Ok::<_, Infallible>(service_fn(handle)) }
+    });
+
+    let server = match Server::try_bind(&addr) {
+        Ok(builder) => builder.serve(make_service),
+        Err(e) => {
+            error!("Could not bind the {} on {} : {}", name, addr, e);
+            return;
+        },
+    };
+
+    info!("The {} is listening on http://{}", name, addr);
+    if let Err(e) = server.await {
+        error!("The {} stopped : {}", name, e);
+    }
+}
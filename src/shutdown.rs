@@ -0,0 +1,102 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use tokio::sync::{Semaphore, SemaphorePermit, watch};
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, timeout_at};
+use tracing::{info, warn};
+
+// Far more than the bot ever handles at once, draining waits for all of them
+const MAX_IN_FLIGHT: u32 = 1024;
+
+// Shared by the handlers, the background jobs and `main`, which drains it on SIGTERM / Ctrl-C
+pub struct Shutdown {
+    stopping: AtomicBool,
+    in_flight: Semaphore,
+    signal: watch::Sender<bool>,
+    jobs: std::sync::Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl Shutdown {
+    pub fn new() -> Arc<Shutdown> {
+        Arc::new(Shutdown {
+            stopping: AtomicBool::new(false),
+            in_flight: Semaphore::new(MAX_IN_FLIGHT as usize),
+            signal: watch::channel(false).0,
+            jobs: std::sync::Mutex::new(Vec::new()),
+        })
+    }
+
+    // Held for the whole interaction, `None` once the bot is stopping
+    pub async fn track(&self) -> Option<SemaphorePermit<'_>> {
+        if self.stopping.load(Ordering::SeqCst) {
+            return None;
+        }
+        self.in_flight.acquire().await.ok()
+    }
+
+    // Flips to `true` when background jobs should wrap up
+    pub fn subscribe(&self) -> watch::Receiver<bool> {
+        self.signal.subscribe()
+    }
+
+    pub fn spawn_job<F>(&self, job: F)
+    where F: Future<Output = ()> + Send + 'static {
+        self.jobs.lock().unwrap().push(tokio::spawn(job));
+    }
+
+    // Returns false when something was still running at the deadline
+    pub async fn drain(&self, timeout: Duration) -> bool {
+        self.stopping.store(true, Ordering::SeqCst);
+        self.signal.send_replace(true);
+        let deadline = Instant::now() + timeout;
+
+        info!("Waiting for in-flight interactions");
+        let drained = timeout_at(deadline, self.in_flight.acquire_many(MAX_IN_FLIGHT)).await;
+        // A handler that got past `stopping` but still waits for a permit is turned
+        // away, the shards and the DB pool are about to close under it
+        self.in_flight.close();
+        match drained {
+            Ok(Ok(permits)) => permits.forget(),
+            _ => {
+                warn!("{} interactions still running after {:?}",
+                    MAX_IN_FLIGHT as usize - self.in_flight.available_permits(), timeout);
+                return false;
+            },
+        }
+
+        let jobs: Vec<_> = self.jobs.lock().unwrap().drain(..).collect();
+        info!("Waiting for {} background jobs", jobs.len());
+        for job in jobs {
+            if timeout_at(deadline, job).await.is_err() {
+                warn!("Background job still running after {:?}", timeout);
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+pub async fn wait_for_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c().await
+            .expect("![MAIN] Could not listen for Ctrl-C");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("![MAIN] Could not listen for SIGTERM")
+            .recv().await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => info!("Received Ctrl-C"),
+        _ = terminate => info!("Received SIGTERM"),
+    }
+}
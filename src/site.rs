@@ -0,0 +1,169 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::config::Config;
+use crate::database::DB;
+use crate::football::{Team, Week};
+use crate::standings::{CapsuleGrades, CapsuleState, SeasonStandings, WeekResults, capsule_grades, season_standings, week_results};
+
+// Plain pages with no script or stylesheet to fetch, so the archive can be
+// hosted anywhere, or opened straight from the disk
+
+const STYLE: &str = "
+    body { font-family: system-ui, sans-serif; margin: 2rem auto; max-width: 72rem; padding: 0 1rem; }
+    nav a { margin-right: .75rem; }
+    table { border-collapse: collapse; margin: 1rem 0; }
+    th, td { border: 1px solid #ccc; padding: .25rem .5rem; text-align: center; }
+    td.name { text-align: left; }
+    .note { color: #666; font-style: italic; }
+    .auto { color: #888; }
+";
+
+// Writes the season's pages and their JSON under `dir/<season>/`, returns how many files
+pub async fn export_season(db: &DB, config: &Config, poolid: &i64, season: &u16, dir: &Path) -> Result<usize> {
+    let out = dir.join(season.to_string());
+    fs::create_dir_all(&out)?;
+
+    let standings = season_standings(db, config, poolid, season).await?;
+    let capsules = capsule_grades(db, config, poolid, season).await?;
+
+    let mut written = 0;
+    let mut write = |name: &str, html: String, json: Value| -> Result<()> {
+        fs::write(out.join(format!("{}.html", name)), html)?;
+        fs::write(out.join(format!("{}.json", name)), serde_json::to_string_pretty(&json)?)?;
+        written += 2;
+        Ok(())
+    };
+
+    write("index", render_standings(&standings), standings.to_json())?;
+    write("capsules", render_capsules(&capsules, &standings.weeks), capsules.to_json())?;
+    for week in &standings.weeks {
+        let results = week_results(db, config, poolid, season, week).await?;
+        write(&week_page(week), render_week(&results, &standings.weeks), results.to_json())?;
+    }
+
+    Ok(written)
+}
+
+fn week_page(week: &Week) -> String {
+    format!("semaine-{:02}", week.number())
+}
+
+fn page(season: u16, title: &str, weeks: &[Week], body: &str) -> String {
+    let nav = weeks.iter().fold(
+        "<a href=\"index.html\">Classement</a><a href=\"capsules.html\">Capsules</a>".to_owned(),
+        |nav, w| format!("{}<a href=\"{}.html\">{}</a>", nav, week_page(w), w.short_label()),
+    );
+
+    format!("<!doctype html>
+<html lang=\"fr\">
+<head>
+    <meta charset=\"UTF-8\">
+    <title>Grebball {season} - {title}</title>
+    <style>{STYLE}</style>
+</head>
+<body>
+    <h1>Grebball {season} - {title}</h1>
+    <nav>{nav}</nav>
+    {body}
+</body>
+</html>
+", title = escape(title))
+}
+
+fn render_standings(standings: &SeasonStandings) -> String {
+    let weeks = standings.weeks.iter()
+        .fold(String::new(), |h, w| format!("{}<th>{}</th>", h, w.short_label()));
+    let cap_header = match standings.capsule {
+        CapsuleState::Final => "<th>Capsule</th>",
+        CapsuleState::Provisional => "<th>Capsule ~</th>",
+    };
+
+    let rows = standings.rows.iter().fold(String::new(), |rows, r| {
+        let scores = r.scores.iter().fold(String::new(), |s, score| format!("{}<td>{}</td>", s, score));
+        format!("{}\n<tr><td>{}</td><td class=\"name\">{}</td><td><b>{}</b></td>{}<td>{}</td><td>{:+}</td></tr>",
+            rows, r.rank, escape(&r.name), r.total(standings.capsule), scores, r.capsule, r.movement)
+    });
+
+    let note = match standings.capsule {
+        CapsuleState::Final => "",
+        CapsuleState::Provisional => "<p class=\"note\">~ : points de capsule provisoires selon le classement actuel, pas encore dans le total.</p>",
+    };
+
+    let body = format!("<table>
+<tr><th>#</th><th>Pooler</th><th>Total</th>{}{}<th>+/-</th></tr>{}
+</table>
+{}", weeks, cap_header, rows, note);
+    page(standings.season, "Classement", &standings.weeks, &body)
+}
+
+fn render_week(results: &WeekResults, weeks: &[Week]) -> String {
+    let header = results.matches.iter().fold(String::new(), |h, m| {
+        let score = match (m.away_score, m.home_score) {
+            (Some(a), Some(h)) => format!("<br>{} - {}", a, h),
+            _ => String::new(),
+        };
+        format!("{}<th>{} @ {}{}</th>", h, m.away_team, m.home_team, score)
+    });
+
+    let rows = results.rows.iter().fold(String::new(), |rows, r| {
+        let picks = r.picks.iter().fold(String::new(), |p, (matchid, team)| {
+            match team {
+                Some(team) if r.autos.contains(matchid) => format!("{}<td class=\"auto\">{} (A)</td>", p, team),
+                Some(team) => format!("{}<td>{}</td>", p, team),
+                None => format!("{}<td>-</td>", p),
+            }
+        });
+        format!("{}\n<tr><td class=\"name\">{}</td><td><b>{}</b></td><td>{}</td>{}</tr>",
+            rows, escape(&r.name), r.score + r.featscore, r.featscore, picks)
+    });
+
+    let feature = results.feature.as_ref()
+        .and_then(|f| results.matches.iter().find(|m| m.id_event == f.matchid).map(|m| (f, m)))
+        .map_or(String::new(), |(f, m)| format!("<p>Over/under: {} @ {}, {}.5 points</p>",
+            m.away_team, m.home_team, f.target));
+
+    let body = format!("{}
+<table>
+<tr><th>Pooler</th><th>Points</th><th>O/U</th>{}</tr>{}
+</table>
+<p class=\"note\">(A) : choix fait automatiquement. Les choix d'un match paraissent une fois le match commencé.</p>",
+        feature, header, rows);
+    page(results.season, &results.week.label(), weeks, &body)
+}
+
+fn render_capsules(grades: &CapsuleGrades, weeks: &[Week]) -> String {
+    let team = |team: &Option<Team>| team.as_ref().map_or("", Team::code);
+    let teams = |teams: &[Option<Team>]| teams.iter().map(team).collect::<Vec<_>>().join(" ");
+
+    let rows = grades.rows.iter().fold(String::new(), |rows, r| {
+        let picks = match &r.picks {
+            Some(c) => format!("<td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{} / {}</td><td>{}</td>",
+                teams(&c.afc_wins), teams(&c.afc_wildcards), teams(&c.nfc_wins), teams(&c.nfc_wildcards),
+                team(&c.afc_top_seed), team(&c.nfc_top_seed), team(&c.champion)),
+            None => "<td colspan=\"6\">-</td>".to_owned(),
+        };
+        format!("{}\n<tr><td class=\"name\">{}</td><td><b>{}</b></td>{}</tr>", rows, escape(&r.name), r.score, picks)
+    });
+
+    let note = match grades.state {
+        CapsuleState::Final => "",
+        CapsuleState::Provisional => "<p class=\"note\">Points provisoires, selon le classement actuel.</p>",
+    };
+
+    let body = format!("<table>
+<tr><th>Pooler</th><th>Points</th><th>AFC divisions</th><th>AFC wild cards</th><th>NFC divisions</th><th>NFC wild cards</th><th>#1 AFC / NFC</th><th>Champion</th></tr>{}
+</table>
+{}", rows, note);
+    page(grades.season, "Capsules", weeks, &body)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use chrono::{SecondsFormat, Utc};
+use serde_json::{Value, json};
+
+use crate::config::Config;
+use crate::database::{CapsulePicks, DB, WeekFeature};
+use crate::football::{
+    Match, PoolMode, Team, TeamRecord, Week, calc_playoff_picture, calc_results, calc_season, calc_standings_history,
+    get_playoff_picture, get_week,
+};
+
+// Read-only views of the pool for people outside Discord, served by the public
+// endpoints and rendered by the static site export. Nothing here writes to the DB.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CapsuleState {
+    // Regular season still running, nothing counts yet
+    Provisional,
+    Final,
+}
+
+impl CapsuleState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CapsuleState::Provisional => "provisional",
+            CapsuleState::Final => "final",
+        }
+    }
+}
+
+pub struct StandingsRow {
+    pub name: String,
+    pub rank: usize,
+    pub scores: Vec<u32>,
+    pub picks_total: u32,
+    pub capsule: u32,
+    pub movement: i64,
+}
+
+impl StandingsRow {
+    // Capsule points only count once they're final, like in /saison
+    pub fn total(&self, capsule: CapsuleState) -> u32 {
+        match capsule {
+            CapsuleState::Final => self.picks_total + self.capsule,
+            CapsuleState::Provisional => self.picks_total,
+        }
+    }
+}
+
+pub struct SeasonStandings {
+    pub season: u16,
+    pub weeks: Vec<Week>,
+    pub capsule: CapsuleState,
+    pub rows: Vec<StandingsRow>,
+}
+
+pub struct WeekRow {
+    pub name: String,
+    pub score: u32,
+    pub featscore: u32,
+    // `None` until the game kicks off, picks aren't public before that
+    pub picks: Vec<(String, Option<Team>)>,
+    pub autos: Vec<String>,
+}
+
+pub struct WeekResults {
+    pub season: u16,
+    pub week: Week,
+    pub matches: Vec<Match>,
+    pub feature: Option<WeekFeature>,
+    pub rows: Vec<WeekRow>,
+}
+
+pub struct CapsuleRow {
+    pub poolerid: i64,
+    pub name: String,
+    pub score: u32,
+    // `None` until the capsules lock, they're secret before that
+    pub picks: Option<CapsulePicks>,
+}
+
+pub struct CapsuleGrades {
+    pub season: u16,
+    pub state: CapsuleState,
+    pub rows: Vec<CapsuleRow>,
+}
+
+pub async fn season_standings(db: &DB, config: &Config, poolid: &i64, season: &u16) -> Result<SeasonStandings> {
+    let (weeks, _) = db.fetch_season(poolid, season).await?;
+    let grades = capsule_grades(db, config, poolid, season).await?;
+    let capsule_scores: HashMap<_, _> = grades.rows.iter()
+        .map(|r| (r.poolerid, r.score))
+        .collect();
+
+    let mode = db.fetch_pool_mode(poolid).await.unwrap_or(PoolMode::Classic);
+    let auto_weight = db.fetch_auto_pick_weight(poolid).await.unwrap_or(100);
+    let season_results = calc_season(&config.data_url, season, &weeks, &mode, &auto_weight).await?;
+
+    let counted = match grades.state {
+        CapsuleState::Final => capsule_scores.clone(),
+        CapsuleState::Provisional => HashMap::new(),
+    };
+    // Already in rank order, capsule points included once they count
+    let rows: Vec<_> = calc_standings_history(&season_results, &counted)
+        .into_iter()
+        .map(|entry| StandingsRow {
+            capsule: capsule_scores.get(&entry.poolerid).copied().unwrap_or(0),
+            rank: entry.rank(),
+            picks_total: entry.total(),
+            movement: entry.movement(),
+            scores: entry.scores,
+            name: entry.name,
+        })
+        .collect();
+
+    Ok(SeasonStandings {
+        season: *season,
+        weeks: season_results.iter().map(|(week, _)| *week).collect(),
+        capsule: grades.state,
+        rows,
+    })
+}
+
+pub async fn week_results(db: &DB, config: &Config, poolid: &i64, season: &u16, week: &Week) -> Result<WeekResults> {
+    let picks = db.fetch_picks(poolid, season, &week.number()).await?;
+    let matches = get_week(&config.data_url, season, week).await?;
+    let feature = db.fetch_feature(*season, week.number()).await.ok();
+    let mode = db.fetch_pool_mode(poolid).await.unwrap_or(PoolMode::Classic);
+    let auto_weight = db.fetch_auto_pick_weight(poolid).await.unwrap_or(100);
+
+    let now = Utc::now();
+    let rows = calc_results(week, &matches, &picks, &feature, &mode, &auto_weight).await
+        .into_iter()
+        .filter(|r| r.pickid.is_some())
+        .map(|r| {
+            let submitted = picks.iter().find(|p| p.poolerid == r.poolerid);
+            let chosen = submitted.and_then(|p| p.picks.as_ref());
+            WeekRow {
+                name: r.name,
+                score: r.score,
+                featscore: r.featscore,
+                picks: matches.iter()
+                    .map(|m| {
+                        let team = chosen.and_then(|c| c.get(&m.id_event)).filter(|_| m.date <= now);
+                        (m.id_event.clone(), team.copied())
+                    })
+                    .collect(),
+                autos: submitted.map(|p| p.autos.iter().cloned().collect()).unwrap_or_default(),
+            }
+        })
+        .collect();
+
+    Ok(WeekResults { season: *season, week: *week, matches, feature, rows })
+}
+
+pub async fn capsule_grades(db: &DB, config: &Config, poolid: &i64, season: &u16) -> Result<CapsuleGrades> {
+    let capsules = db.fetch_capsule(season, poolid).await?;
+    let revealed = db.fetch_capsule_lock(season).await?
+        .is_some_and(|locked_at| locked_at <= Utc::now());
+
+    // Every capsule already graded: final scores come from the DB, no need for ESPN
+    let graded = !capsules.is_empty() && capsules.values().all(|c| c.cached.is_some());
+    let (state, scores): (CapsuleState, HashMap<i64, u32>) = if graded || capsules.is_empty() {
+        (CapsuleState::Final, capsules.values().map(|c| (c.poolerid, c.cached.unwrap_or(0))).collect())
+    } else {
+        let picture = get_playoff_picture(&config.standings_url, &config.data_url, *season).await?;
+        let repick_weight = db.fetch_repick_weight(poolid).await.unwrap_or(100);
+        let state = if picture.reg_season_over { CapsuleState::Final } else { CapsuleState::Provisional };
+        (state, calc_playoff_picture(&picture, &capsules, &repick_weight).iter().map(|r| (r.poolerid, r.score)).collect())
+    };
+
+    let mut rows: Vec<_> = capsules.into_values()
+        .map(|c| CapsuleRow {
+            poolerid: c.poolerid,
+            name: c.name.clone(),
+            score: scores.get(&c.poolerid).copied().unwrap_or(0),
+            picks: revealed.then_some(c),
+        })
+        .collect();
+    rows.sort_by(|l, r| r.score.cmp(&l.score).then_with(|| l.name.cmp(&r.name)));
+
+    Ok(CapsuleGrades { season: *season, state, rows })
+}
+
+pub fn match_json(m: &Match) -> Value {
+    json!({
+        "id": m.id_event,
+        "away_team": m.away_team,
+        "home_team": m.home_team,
+        "away_name": m.away_team.name(),
+        "home_name": m.home_team.name(),
+        "away_score": m.away_score,
+        "home_score": m.home_score,
+        "date": m.date.to_rfc3339_opts(SecondsFormat::Secs, true),
+        "locked": m.date <= Utc::now(),
+        "away_record": m.away_record.as_ref().map(record_json),
+        "home_record": m.home_record.as_ref().map(record_json),
+    })
+}
+
+fn record_json(r: &TeamRecord) -> Value {
+    json!({ "overall": r.overall, "venue": r.venue, "venue_name": r.venue_name })
+}
+
+impl SeasonStandings {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "season": self.season,
+            "weeks": self.weeks.iter().map(|w| w.number()).collect::<Vec<_>>(),
+            "capsule": self.capsule.as_str(),
+            "standings": self.rows.iter().map(|r| json!({
+                "name": r.name,
+                "rank": r.rank,
+                "total": r.total(self.capsule),
+                "picks": r.picks_total,
+                "capsule": r.capsule,
+                "weeks": r.scores,
+                "movement": r.movement,
+            })).collect::<Vec<_>>(),
+        })
+    }
+}
+
+impl WeekResults {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "season": self.season,
+            "week": self.week.number(),
+            "label": self.week.label(),
+            "matches": self.matches.iter().map(match_json).collect::<Vec<_>>(),
+            "feature": self.feature.as_ref().map(|f| json!({ "match": f.matchid, "target": f.target })),
+            "results": self.rows.iter().map(|r| json!({
+                "name": r.name,
+                "score": r.score,
+                "featscore": r.featscore,
+                "total": r.score + r.featscore,
+                "picks": r.picks.iter().cloned().collect::<HashMap<_, _>>(),
+                "auto": r.autos,
+            })).collect::<Vec<_>>(),
+        })
+    }
+}
+
+impl CapsuleGrades {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "season": self.season,
+            "state": self.state.as_str(),
+            "capsules": self.rows.iter().map(|r| json!({
+                "name": r.name,
+                "score": r.score,
+                "picks": r.picks.as_ref().map(|c| json!({
+                    "afc": { "winners": c.afc_wins, "wildcards": c.afc_wildcards, "top_seed": c.afc_top_seed },
+                    "nfc": { "winners": c.nfc_wins, "wildcards": c.nfc_wildcards, "top_seed": c.nfc_top_seed },
+                    "champion": c.champion,
+                })),
+            })).collect::<Vec<_>>(),
+        })
+    }
+}
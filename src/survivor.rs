@@ -0,0 +1,294 @@
+use std::collections::BTreeMap;
+use core::fmt::Display;
+
+use anyhow::Result;
+use chrono::{ TimeDelta, Utc };
+
+use crate::calendar::season_calendar;
+use crate::database::{DB, SurvivorPick};
+use crate::football::{Match, Team, Week, get_week};
+
+#[derive(Debug)]
+pub enum SurvivorError {
+    Eliminated(i64),
+    TeamUsed(Team, i64),
+    TeamNotPlaying(Team),
+    GameStarted(Team),
+}
+
+impl Display for SurvivorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SurvivorError::Eliminated(week) => write!(f, "Tu as été éliminé à la semaine {}.", week),
+            SurvivorError::TeamUsed(team, week) => write!(f, "Tu as déjà choisi {} à la semaine {}.", team, week),
+            SurvivorError::TeamNotPlaying(team) => write!(f, "{} ne joue pas cette semaine.", team),
+            SurvivorError::GameStarted(team) => write!(f, "Le match de {} est déjà commencé.", team),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SurvivorStatus {
+    Alive,
+    Eliminated(i64),
+}
+
+pub struct SurvivorEntry {
+    pub poolerid: i64,
+    pub name: String,
+    pub status: SurvivorStatus,
+    // None for a missed week
+    pub teams: Vec<Option<Team>>,
+}
+
+pub fn survivor_status(picks: &[SurvivorPick], poolerid: i64) -> SurvivorStatus {
+    picks.iter()
+        .filter(|p| p.poolerid == poolerid && p.outcome == Some(0))
+        .map(|p| p.week)
+        .min()
+        .map_or(SurvivorStatus::Alive, SurvivorStatus::Eliminated)
+}
+
+pub fn survivor_standings(picks: &[SurvivorPick]) -> Vec<SurvivorEntry> {
+    let mut by_pooler = BTreeMap::<i64, SurvivorEntry>::new();
+    for pick in picks {
+        let entry = by_pooler.entry(pick.poolerid).or_insert_with(|| SurvivorEntry {
+            poolerid: pick.poolerid,
+            name: pick.name.clone(),
+            status: survivor_status(picks, pick.poolerid),
+            teams: Vec::new(),
+        });
+        entry.teams.push(pick.team);
+    }
+
+    let mut entries: Vec<_> = by_pooler.into_values().collect();
+    // Still alive first, then whoever lasted the longest
+    entries.sort_by_key(|e| match e.status {
+        SurvivorStatus::Alive => i64::MIN,
+        SurvivorStatus::Eliminated(week) => -week,
+    });
+    entries
+}
+
+pub fn validate_survivor_pick<'a>(
+    picks: &[SurvivorPick],
+    poolerid: i64,
+    week: i64,
+    team: Team,
+    matches: &'a [Match]) -> Result<&'a Match, SurvivorError> {
+
+    if let SurvivorStatus::Eliminated(w) = survivor_status(picks, poolerid) {
+        return Err(SurvivorError::Eliminated(w));
+    }
+
+    // Swapping this week's pick is fine, reusing a team from another week is not
+    if let Some(used) = picks.iter().find(|p| p.poolerid == poolerid && p.team == Some(team) && p.week != week) {
+        return Err(SurvivorError::TeamUsed(team, used.week));
+    }
+
+    let game = matches.iter()
+        .find(|m| m.away_team == team || m.home_team == team)
+        .ok_or(SurvivorError::TeamNotPlaying(team))?;
+
+    let now = Utc::now();
+    if game.date <= now {
+        return Err(SurvivorError::GameStarted(team));
+    }
+
+    // The previous pick for the week stays locked once its game is underway
+    if let Some(current) = picks.iter().find(|p| p.poolerid == poolerid && p.week == week) {
+        if let (Some(m), Some(current_team)) = (matches.iter().find(|m| m.id_event == current.matchid), current.team) {
+            if m.date <= now {
+                return Err(SurvivorError::GameStarted(current_team));
+            }
+        }
+    }
+
+    Ok(game)
+}
+
+// A tie doesn't win the game, so it eliminates just like a loss
+fn survivor_outcome(m: &Match, team: Team) -> Option<u32> {
+    let final_after = Utc::now().checked_sub_signed(TimeDelta::hours(8)).unwrap();
+    if m.date >= final_after {
+        return None;
+    }
+
+    match (m.away_score, m.home_score) {
+        (Some(0), Some(0)) => None,
+        (Some(a), Some(h)) if a > h && m.away_team == team => Some(1),
+        (Some(a), Some(h)) if h > a && m.home_team == team => Some(1),
+        (Some(_), Some(_)) => Some(0),
+        _ => None,
+    }
+}
+
+pub async fn resolve_survivor(db: &DB, data_url: &str, poolid: &i64, season: &u16) -> Result<usize> {
+    let pending = db.fetch_pending_survivor(season, poolid).await?;
+
+    let mut by_week = BTreeMap::<i64, Vec<&SurvivorPick>>::new();
+    for pick in &pending {
+        by_week.entry(pick.week).or_default().push(pick);
+    }
+
+    let mut resolved = 0;
+    for (week, picks) in by_week {
+        let matches = get_week(data_url, season, &Week::try_from(week)?).await?;
+
+        for pick in picks {
+            let Some(team) = pick.team else { continue };
+            let outcome = matches.iter()
+                .find(|m| m.id_event == pick.matchid)
+                .and_then(|m| survivor_outcome(m, team));
+
+            if let Some(outcome) = outcome {
+                if db.resolve_survivor_pick(&pick.id, &outcome).await? {
+                    resolved += 1;
+                }
+            }
+        }
+    }
+
+    Ok(resolved + eliminate_missed(db, data_url, poolid, season).await?)
+}
+
+// Once in, a pooler has to pick every regular season week until eliminated
+async fn eliminate_missed(db: &DB, data_url: &str, poolid: &i64, season: &u16) -> Result<usize> {
+    let now = Utc::now();
+    let finished: Vec<i64> = season_calendar(db, data_url, season).await?
+        .iter()
+        .filter(|w| !w.week.is_playoffs() && w.end < now)
+        .map(|w| w.week.number())
+        .collect();
+
+    let picks = db.fetch_survivor_picks(season, poolid).await?;
+
+    let mut eliminated = 0;
+    for entry in survivor_standings(&picks) {
+        if entry.status != SurvivorStatus::Alive {
+            continue;
+        }
+
+        let weeks: Vec<i64> = picks.iter()
+            .filter(|p| p.poolerid == entry.poolerid)
+            .map(|p| p.week)
+            .collect();
+        let Some(first) = weeks.iter().min() else { continue };
+
+        let missed = finished.iter().find(|w| *w >= first && !weeks.contains(w));
+        if let Some(week) = missed {
+            if db.eliminate_survivor_missed(season, week, &entry.poolerid).await? {
+                eliminated += 1;
+            }
+        }
+    }
+
+    Ok(eliminated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::DateTime;
+
+    fn game(id: &str, away: Team, home: Team, kickoff: DateTime<Utc>) -> Match {
+        Match {
+            id_event: id.to_owned(),
+            away_team: away,
+            home_team: home,
+            away_score: None,
+            home_score: None,
+            date: kickoff,
+            away_record: None,
+            home_record: None,
+        }
+    }
+
+    fn pick(poolerid: i64, week: i64, team: Option<Team>, outcome: Option<u32>) -> SurvivorPick {
+        SurvivorPick {
+            id: poolerid * 100 + week,
+            season: 2024,
+            week,
+            poolerid,
+            name: format!("pooler {}", poolerid),
+            matchid: format!("{}", week),
+            team,
+            outcome,
+        }
+    }
+
+    fn week() -> Vec<Match> {
+        let now = Utc::now();
+        vec![
+            game("1", Team::Chiefs, Team::Ravens, now - TimeDelta::hours(1)),
+            game("2", Team::Patriots, Team::Bills, now + TimeDelta::days(2)),
+        ]
+    }
+
+    #[test]
+    fn accepts_an_unused_team_before_kickoff() {
+        let matches = week();
+        let game = validate_survivor_pick(&[], 1, 3, Team::Bills, &matches).unwrap();
+        assert_eq!(game.id_event, "2");
+    }
+
+    #[test]
+    fn refuses_a_team_used_another_week() {
+        let picks = [pick(1, 1, Some(Team::Bills), Some(1))];
+        let matches = week();
+
+        let error = validate_survivor_pick(&picks, 1, 3, Team::Bills, &matches).unwrap_err();
+        assert!(matches!(error, SurvivorError::TeamUsed(Team::Bills, 1)));
+
+        // Another pooler's picks don't count
+        assert!(validate_survivor_pick(&picks, 2, 3, Team::Bills, &matches).is_ok());
+    }
+
+    #[test]
+    fn refuses_a_started_game() {
+        let matches = week();
+        let error = validate_survivor_pick(&[], 1, 3, Team::Chiefs, &matches).unwrap_err();
+        assert!(matches!(error, SurvivorError::GameStarted(Team::Chiefs)));
+    }
+
+    #[test]
+    fn refuses_a_team_not_playing() {
+        let matches = week();
+        let error = validate_survivor_pick(&[], 1, 3, Team::Cowboys, &matches).unwrap_err();
+        assert!(matches!(error, SurvivorError::TeamNotPlaying(Team::Cowboys)));
+    }
+
+    #[test]
+    fn status_is_the_first_losing_week() {
+        let picks = [
+            pick(1, 1, Some(Team::Bills), Some(1)),
+            pick(1, 2, Some(Team::Chiefs), Some(0)),
+            pick(2, 1, Some(Team::Ravens), Some(1)),
+            pick(2, 2, Some(Team::Lions), None),
+        ];
+        assert_eq!(survivor_status(&picks, 1), SurvivorStatus::Eliminated(2));
+        assert_eq!(survivor_status(&picks, 2), SurvivorStatus::Alive);
+        assert_eq!(survivor_status(&picks, 3), SurvivorStatus::Alive);
+    }
+
+    #[test]
+    fn standings_put_survivors_first_then_the_longest_lasting() {
+        let picks = [
+            pick(1, 1, Some(Team::Bills), Some(0)),
+            pick(2, 1, Some(Team::Ravens), Some(1)),
+            pick(2, 2, Some(Team::Lions), Some(1)),
+            pick(3, 1, Some(Team::Chiefs), Some(1)),
+            // A missed week is stored as an "NA" pick and eliminates
+            pick(3, 2, None, Some(0)),
+        ];
+        let standings = survivor_standings(&picks);
+
+        let order: Vec<_> = standings.iter().map(|e| (e.poolerid, e.status)).collect();
+        assert_eq!(order, [
+            (2, SurvivorStatus::Alive),
+            (3, SurvivorStatus::Eliminated(2)),
+            (1, SurvivorStatus::Eliminated(1)),
+        ]);
+        assert_eq!(standings[1].teams, [Some(Team::Chiefs), None]);
+    }
+}
@@ -0,0 +1,472 @@
+use std::collections::HashMap;
+use core::fmt::Display;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+
+use crate::calendar::season_calendar;
+use crate::database::{CapsuleSlot, DB, WeekPicks};
+use crate::football::{Conference, Division, Match, PoolMode, Team, Week, get_week, validate_confidence};
+
+#[derive(Debug)]
+pub enum PickError {
+    MissingPick(String),
+    UnknownMatch(String),
+    UnknownTeam(String, String),
+    InvalidTeam(String, Team),
+    FavTeamIgnored(Team, Team),
+    InvalidConfidence(String),
+    GameStarted(String),
+}
+
+impl Display for PickError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PickError::MissingPick(matchid) => write!(f, "Aucun choix pour le match {}.", matchid),
+            PickError::UnknownMatch(matchid) => write!(f, "Le match {} ne fait pas partie de la semaine.", matchid),
+            PickError::UnknownTeam(matchid, team) => write!(f, "Équipe inconnue pour le match {}: {}", matchid, team),
+            PickError::InvalidTeam(matchid, team) => write!(f, "{} ne joue pas dans le match {}.", team, matchid),
+            PickError::FavTeamIgnored(favteam, team) => write!(f, "Le match de {} doit être choisi pour {}, pas {}.", favteam, favteam, team),
+            PickError::InvalidConfidence(reason) => write!(f, "Les rangs de confiance ne couvrent pas tous les matchs de 1 à N ({}).", reason),
+            PickError::GameStarted(matchid) => write!(f, "Le match {} est déjà commencé.", matchid),
+        }
+    }
+}
+
+// Checks a whole submission against the week's matches. `favteam` is the team
+// whose game is forced, `ranks` are only given in confidence pools. With a
+// `deadline`, games kicked off by then need no pick and any pick sent for them
+// is refused, saved picks pass none.
+pub fn validate_picks(
+    matches: &[Match],
+    picks: &HashMap<String, Team>,
+    favteam: Option<Team>,
+    ranks: Option<&HashMap<String, u32>>,
+    deadline: Option<DateTime<Utc>>) -> Result<(), Vec<PickError>> {
+
+    let mut errors = Vec::new();
+
+    for m in matches {
+        if deadline.is_some_and(|deadline| m.date <= deadline) {
+            if picks.contains_key(&m.id_event) {
+                errors.push(PickError::GameStarted(m.id_event.clone()));
+            }
+            continue;
+        }
+
+        let Some(&team) = picks.get(&m.id_event) else {
+            errors.push(PickError::MissingPick(m.id_event.clone()));
+            continue;
+        };
+
+        if team != m.away_team && team != m.home_team {
+            errors.push(PickError::InvalidTeam(m.id_event.clone(), team));
+        }
+        else if let Some(fav) = favteam.filter(|&fav| m.away_team == fav || m.home_team == fav) {
+            if team != fav {
+                errors.push(PickError::FavTeamIgnored(fav, team));
+            }
+        }
+    }
+
+    let mut stray: Vec<_> = picks.keys()
+        .filter(|matchid| !matches.iter().any(|m| m.id_event == **matchid))
+        .collect();
+    stray.sort();
+    errors.extend(stray.into_iter().map(|matchid| PickError::UnknownMatch(matchid.clone())));
+
+    if let Some(ranks) = ranks {
+        if let Err(e) = validate_confidence(matches, ranks) {
+            errors.push(PickError::InvalidConfidence(e.to_string()));
+        }
+    }
+
+    if errors.is_empty() { Ok(()) } else { Err(errors) }
+}
+
+// Picks as sent by the pick page, matchid to team code. Every code has to be
+// a team before the picks go through `validate_picks`.
+pub fn parse_picks(raw: &HashMap<String, String>) -> Result<HashMap<String, Team>, Vec<PickError>> {
+    let mut picks = HashMap::new();
+    let mut errors = Vec::new();
+
+    for (matchid, code) in raw {
+        match code.parse::<Team>() {
+            Ok(team) => { picks.insert(matchid.clone(), team); },
+            Err(_) => errors.push(PickError::UnknownTeam(matchid.clone(), code.clone())),
+        }
+    }
+
+    if errors.is_empty() { Ok(picks) } else { Err(errors) }
+}
+
+// Same checks on picks already saved, against the favorite team they were
+// submitted with since /equipe can change it later. Older picks didn't record
+// it and skip that rule, so do games the bot filled in by default since the
+// pool's policy picked them.
+pub fn validate_week_picks(matches: &[Match], pick: &WeekPicks, mode: &PoolMode) -> Result<(), Vec<PickError>> {
+    let empty = HashMap::new();
+    let picks = pick.picks.as_ref().unwrap_or(&empty);
+
+    let forced_auto = |fav: &Team| matches.iter()
+        .find(|m| m.away_team == *fav || m.home_team == *fav)
+        .is_some_and(|m| pick.autos.contains(&m.id_event));
+    let favteam = pick.favteam.filter(|fav| !forced_auto(fav));
+
+    let no_ranks = HashMap::new();
+    let ranks = match mode {
+        PoolMode::Confidence => Some(pick.confidences.as_ref().unwrap_or(&no_ranks)),
+        PoolMode::Classic => None,
+    };
+
+    validate_picks(matches, picks, favteam, ranks, None)
+}
+
+// Capsule rows, see `populate_capsule` for how they're read back
+pub const CAPSULE_WINNER: i32 = 0;
+pub const CAPSULE_WILDCARD: i32 = 1;
+pub const CAPSULE_TOP_SEED: i32 = 2;
+pub const CAPSULE_CHAMPION: i32 = 3;
+
+const DIVISIONS: [Division; 4] = [Division::North, Division::South, Division::East, Division::West];
+
+#[derive(Debug)]
+pub enum CapsuleError {
+    MissingTeam,
+    UnknownTeam(String),
+    WrongDivision(Team, Conference, Division),
+    WrongConference(Team, Conference),
+    Duplicate(Team),
+}
+
+impl Display for CapsuleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CapsuleError::MissingTeam => write!(f, "Il manque une équipe dans la capsule."),
+            CapsuleError::UnknownTeam(team) => write!(f, "Équipe inconnue: {}", team),
+            CapsuleError::WrongDivision(team, conf, div) => write!(f, "{} n'est pas dans la division {} {:?}.", team, conf.code(), div),
+            CapsuleError::WrongConference(team, conf) => write!(f, "{} n'est pas dans la {}.", team, conf.code()),
+            CapsuleError::Duplicate(team) => write!(f, "{} est choisi plus d'une fois.", team),
+        }
+    }
+}
+
+// Teams as sent by the pick page, codes or full names
+pub struct ConferenceCapsule {
+    // In `Division` order: North, South, East, West
+    pub winners: [String; 4],
+    pub wildcards: [String; 3],
+    pub top_seed: String,
+}
+
+pub struct CapsuleSubmission {
+    pub afc: ConferenceCapsule,
+    pub nfc: ConferenceCapsule,
+    pub champion: String,
+}
+
+// The 17 rows to file, or everything wrong with the capsule
+pub fn validate_capsule(capsule: &CapsuleSubmission) -> Result<Vec<CapsuleSlot>, Vec<CapsuleError>> {
+    let mut errors = Vec::new();
+    let mut slots = Vec::new();
+
+    for (conf, picks) in [(Conference::AFC, &capsule.afc), (Conference::NFC, &capsule.nfc)] {
+        // (type, division, slot, team)
+        let mut rows = Vec::new();
+
+        for (div, name) in DIVISIONS.iter().zip(&picks.winners) {
+            let Some(team) = parse_team(name, &mut errors) else { continue };
+            if team.conference() != conf || team.division() != *div {
+                errors.push(CapsuleError::WrongDivision(team, conf, *div));
+            }
+            rows.push((CAPSULE_WINNER, *div as i32, 0, team));
+        }
+
+        for (index, name) in picks.wildcards.iter().enumerate() {
+            let Some(team) = parse_team(name, &mut errors) else { continue };
+            if team.conference() != conf {
+                errors.push(CapsuleError::WrongConference(team, conf));
+            }
+            rows.push((CAPSULE_WILDCARD, 0, index as i32, team));
+        }
+
+        // The 7 playoff teams of a conference are all different
+        for (i, (_, _, _, team)) in rows.iter().enumerate() {
+            if rows[..i].iter().any(|r| r.3 == *team) {
+                errors.push(CapsuleError::Duplicate(*team));
+            }
+        }
+
+        if let Some(team) = parse_team(&picks.top_seed, &mut errors) {
+            if team.conference() != conf {
+                errors.push(CapsuleError::WrongConference(team, conf));
+            }
+            rows.push((CAPSULE_TOP_SEED, 0, 0, team));
+        }
+
+        slots.extend(rows.into_iter().map(|(kind, division, slot, team)| CapsuleSlot {
+            kind,
+            conference: conf as i32,
+            division,
+            slot,
+            team,
+        }));
+    }
+
+    // Filed under its own conference
+    if let Some(team) = parse_team(&capsule.champion, &mut errors) {
+        slots.push(CapsuleSlot {
+            kind: CAPSULE_CHAMPION,
+            conference: team.conference() as i32,
+            division: 0,
+            slot: 0,
+            team,
+        });
+    }
+
+    if errors.is_empty() { Ok(slots) } else { Err(errors) }
+}
+
+// Slots of `new` whose team differs from the filed capsule. The champion is
+// matched whatever its conference, slots never filed can't be repicked.
+pub fn capsule_changes(current: &[CapsuleSlot], new: &[CapsuleSlot]) -> Vec<CapsuleSlot> {
+    let same_slot = |a: &CapsuleSlot, b: &CapsuleSlot| a.kind == b.kind
+        && a.division == b.division
+        && a.slot == b.slot
+        && (a.kind == CAPSULE_CHAMPION || a.conference == b.conference);
+
+    new.iter()
+        .filter(|s| current.iter().any(|c| same_slot(c, s) && c.team != s.team))
+        .cloned()
+        .collect()
+}
+
+fn parse_team(name: &str, errors: &mut Vec<CapsuleError>) -> Option<Team> {
+    if name.trim().is_empty() {
+        // Once is enough, an empty form would list every slot
+        if !errors.iter().any(|e| matches!(e, CapsuleError::MissingTeam)) {
+            errors.push(CapsuleError::MissingTeam);
+        }
+        return None;
+    }
+
+    match name.parse::<Team>() {
+        Ok(team) => Some(team),
+        Err(_) => {
+            errors.push(CapsuleError::UnknownTeam(name.to_owned()));
+            None
+        },
+    }
+}
+
+pub struct PickIssue {
+    pub week: Week,
+    pub pickid: i64,
+    pub name: String,
+    pub error: PickError,
+}
+
+// Walks every saved submission of the season, for the `--check-picks` CLI
+pub async fn check_season_picks(db: &DB, data_url: &str, poolid: &i64, season: &u16) -> Result<Vec<PickIssue>> {
+    let mode = db.fetch_pool_mode(poolid).await?;
+    let calendar = season_calendar(db, data_url, season).await?;
+
+    let mut issues = Vec::new();
+    for dates in calendar {
+        let week = dates.week;
+        let submitted: Vec<_> = db.fetch_picks(poolid, season, &week.number()).await?
+            .into_iter()
+            .filter(|p| p.pickid.is_some())
+            .collect();
+        if submitted.is_empty() {
+            continue;
+        }
+
+        let matches = get_week(data_url, season, &week).await?;
+        for pick in submitted {
+            if let Err(errors) = validate_week_picks(&matches, &pick, &mode) {
+                issues.extend(errors.into_iter().map(|error| PickIssue {
+                    week,
+                    pickid: pick.pickid.unwrap_or_default(),
+                    name: pick.name.clone(),
+                    error,
+                }));
+            }
+        }
+    }
+
+    Ok(issues)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeDelta;
+
+    fn game(id: &str, away: Team, home: Team, kickoff: DateTime<Utc>) -> Match {
+        Match {
+            id_event: id.to_owned(),
+            away_team: away,
+            home_team: home,
+            away_score: None,
+            home_score: None,
+            date: kickoff,
+            away_record: None,
+            home_record: None,
+        }
+    }
+
+    fn week() -> Vec<Match> {
+        let sunday = Utc::now() + TimeDelta::days(3);
+        vec![
+            game("1", Team::Chiefs, Team::Ravens, sunday),
+            game("2", Team::Patriots, Team::Bills, sunday + TimeDelta::hours(3)),
+        ]
+    }
+
+    fn picks(entries: &[(&str, Team)]) -> HashMap<String, Team> {
+        entries.iter().map(|&(id, team)| (id.to_string(), team)).collect()
+    }
+
+    #[test]
+    fn accepts_a_full_week() {
+        let picks = picks(&[("1", Team::Chiefs), ("2", Team::Patriots)]);
+        assert!(validate_picks(&week(), &picks, Some(Team::Patriots), None, Some(Utc::now())).is_ok());
+    }
+
+    #[test]
+    fn reports_every_problem_at_once() {
+        let picks = picks(&[("1", Team::Cowboys), ("9", Team::Chiefs)]);
+        let errors = validate_picks(&week(), &picks, None, None, None).unwrap_err();
+
+        assert!(matches!(errors.as_slice(), [
+            PickError::InvalidTeam(id, Team::Cowboys),
+            PickError::MissingPick(missing),
+            PickError::UnknownMatch(stray),
+        ] if id == "1" && missing == "2" && stray == "9"));
+    }
+
+    #[test]
+    fn unknown_codes_are_refused_before_validation() {
+        let raw = HashMap::from([("1".to_owned(), "KC".to_owned()), ("2".to_owned(), "XYZ".to_owned())]);
+        let errors = parse_picks(&raw).unwrap_err();
+
+        assert!(matches!(errors.as_slice(), [PickError::UnknownTeam(id, code)] if id == "2" && code == "XYZ"));
+    }
+
+    #[test]
+    fn favorite_team_game_is_forced() {
+        let picks = picks(&[("1", Team::Chiefs), ("2", Team::Bills)]);
+        let errors = validate_picks(&week(), &picks, Some(Team::Patriots), None, None).unwrap_err();
+
+        assert!(matches!(errors.as_slice(), [PickError::FavTeamIgnored(Team::Patriots, Team::Bills)]));
+    }
+
+    #[test]
+    fn started_games_are_refused_past_the_deadline() {
+        let mut matches = week();
+        matches[0].date = Utc::now() - TimeDelta::minutes(5);
+        let picks = picks(&[("1", Team::Chiefs), ("2", Team::Patriots)]);
+
+        let errors = validate_picks(&matches, &picks, None, None, Some(Utc::now())).unwrap_err();
+        assert!(matches!(errors.as_slice(), [PickError::GameStarted(id)] if id == "1"));
+        assert!(validate_picks(&matches, &picks, None, None, None).is_ok());
+    }
+
+    #[test]
+    fn late_picks_only_cover_the_games_left() {
+        let mut matches = week();
+        let deadline = matches[0].date + TimeDelta::hours(1);
+        matches.push(game("3", Team::Lions, Team::Bears, deadline + TimeDelta::hours(1)));
+
+        let late = picks(&[("2", Team::Patriots), ("3", Team::Lions)]);
+        assert!(validate_picks(&matches, &late, None, None, Some(deadline)).is_ok());
+
+        let errors = validate_picks(&matches, &picks(&[("3", Team::Lions)]), None, None, Some(deadline)).unwrap_err();
+        assert!(matches!(errors.as_slice(), [PickError::MissingPick(id)] if id == "2"));
+    }
+
+    #[test]
+    fn confidence_ranks_cover_one_to_n() {
+        let picks = picks(&[("1", Team::Chiefs), ("2", Team::Patriots)]);
+        let valid = HashMap::from([("1".to_owned(), 2), ("2".to_owned(), 1)]);
+        let repeated = HashMap::from([("1".to_owned(), 1), ("2".to_owned(), 1)]);
+
+        assert!(validate_picks(&week(), &picks, None, Some(&valid), None).is_ok());
+        let errors = validate_picks(&week(), &picks, None, Some(&repeated), None).unwrap_err();
+        assert!(matches!(errors.as_slice(), [PickError::InvalidConfidence(_)]));
+    }
+
+    fn conference(winners: [&str; 4], wildcards: [&str; 3], top_seed: &str) -> ConferenceCapsule {
+        ConferenceCapsule {
+            winners: winners.map(str::to_owned),
+            wildcards: wildcards.map(str::to_owned),
+            top_seed: top_seed.to_owned(),
+        }
+    }
+
+    fn capsule() -> CapsuleSubmission {
+        CapsuleSubmission {
+            afc: conference(["BAL", "HOU", "BUF", "KC"], ["PIT", "LAC", "DEN"], "KC"),
+            nfc: conference(["DET", "TB", "PHI", "LAR"], ["MIN", "GB", "WSH"], "DET"),
+            champion: "PHI".to_owned(),
+        }
+    }
+
+    #[test]
+    fn files_seventeen_slots() {
+        let slots = validate_capsule(&capsule()).unwrap();
+
+        assert_eq!(slots.len(), 17);
+        let champion = slots.iter().find(|s| s.kind == CAPSULE_CHAMPION).unwrap();
+        assert_eq!((champion.team, champion.conference), (Team::Eagles, Conference::NFC as i32));
+    }
+
+    #[test]
+    fn full_names_are_accepted() {
+        let mut capsule = capsule();
+        capsule.afc.winners[3] = "Kansas City Chiefs".to_owned();
+
+        let slots = validate_capsule(&capsule).unwrap();
+        assert!(slots.iter().any(|s| s.kind == CAPSULE_WINNER && s.team == Team::Chiefs));
+    }
+
+    #[test]
+    fn capsule_checks_divisions_conferences_and_duplicates() {
+        let mut capsule = capsule();
+        capsule.afc.winners[1] = "CIN".to_owned();
+        capsule.afc.wildcards[0] = "DAL".to_owned();
+        capsule.nfc.wildcards[2] = "MIN".to_owned();
+        capsule.nfc.top_seed = "KC".to_owned();
+
+        let errors = validate_capsule(&capsule).unwrap_err();
+        assert!(matches!(errors.as_slice(), [
+            CapsuleError::WrongDivision(Team::Bengals, Conference::AFC, Division::South),
+            CapsuleError::WrongConference(Team::Cowboys, Conference::AFC),
+            CapsuleError::Duplicate(Team::Vikings),
+            CapsuleError::WrongConference(Team::Chiefs, Conference::NFC),
+        ]));
+    }
+
+    #[test]
+    fn missing_teams_are_reported_once() {
+        let mut capsule = capsule();
+        capsule.afc.wildcards = Default::default();
+        capsule.champion = "XYZ".to_owned();
+
+        let errors = validate_capsule(&capsule).unwrap_err();
+        assert!(matches!(errors.as_slice(), [CapsuleError::MissingTeam, CapsuleError::UnknownTeam(team)] if team == "XYZ"));
+    }
+
+    #[test]
+    fn repicks_only_count_changed_slots() {
+        let current = validate_capsule(&capsule()).unwrap();
+        let mut repicked = capsule();
+        repicked.afc.wildcards[1] = "CIN".to_owned();
+        repicked.champion = "KC".to_owned();
+
+        let changes = capsule_changes(&current, &validate_capsule(&repicked).unwrap());
+        let teams: Vec<_> = changes.iter().map(|s| s.team).collect();
+        assert_eq!(teams, [Team::Bengals, Team::Chiefs]);
+    }
+}
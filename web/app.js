@@ -15,401 +15,227 @@ app.use(express.static('public'));
 app.use(express.json());
 app.use(express.urlencoded({ extended: false }));
 
-const LoadDB = require('./database');
 const e_prefix = process.env.EMOJI_PREFIX || '';
 
-app.get('/:token', async (req, res) => {
-    const token = req.params['token'];
-
-    LoadDB((db) => {
-        const sql = `
-            SELECT t.season, t.week,
-                   u.avatar, po.name, po.favteam,
-                   ft.match AS feat_id, ft.target AS feat_val
-            FROM pick_tokens AS t
-                JOIN poolers AS po ON po.id = t.poolerid
-                JOIN users   AS u  ON u.id  = po.userid
-                LEFT JOIN features AS ft ON ft.season = t.season AND ft.week = t.week
-            WHERE t.token = ?
-        `;
-        db.get(sql, token, async (err, row) => {
-            // Bad/expired token
-            if (err || !row) {
-                if (err) {
-                    console.log('Could not query DB for token, err: ', err.message);
-                }
-                res.render('error.html');
-                return;
-            }
-
-            const avatar = row['avatar'];
-            const username = row['name'];
-            const favteam = row['favteam'];
-            const season = row['season'];
-            const week = row['week'];
-
-            const feat_id = row['feat_id'];
-            const feat_val = row['feat_val'];
-
-            var w = week;
-            var stype = 2;
-            if (week == 19 || week == '19') { w = 1; stype = 3; }
-            else if (week == 20 || week == '20') { w = 2; stype = 3; }
-            else if (week == 21 || week == '21') { w = 3; stype = 3; }
-            else if (week == 22 || week == '22') { w = 5; stype = 3; }
-
-            const partial_url = "https://site.api.espn.com/apis/site/v2/sports/football/nfl/scoreboard";
-            const url = `${partial_url}?dates=${season}&seasontype=${stype}&week=${w}`;
-            const result = await fetch(url);
-            const json = await result.json();
-
-            let matches = [];
-            let forcedid = 0;
-            if (json['events']) {
-                matches = json['events'].map((m) => {
-                    const match = {};
-                    match['idEvent'] = m['id'];
-                    match['date'] = new Date(m['date']);
-
-                    const teams = m['competitions'][0]['competitors'];
-                    const hteam = teams[0];
-                    const ateam = teams[1];
-
-
-                    match['homeTeam'] = hteam['team']['abbreviation'];
-                    match['awayTeam'] = ateam['team']['abbreviation'];
-                    match['strHomeTeam'] = hteam['team']['displayName'];
-                    match['strAwayTeam'] = ateam['team']['displayName'];
-
-                    match['homeRecordAll'] = (hteam['records']) ? hteam['records'][0] : 0;
-                    match['homeRecordAlt'] = (hteam['records']) ? hteam['records'][1] : 0;
-                    match['awayRecordAll'] = (ateam['records']) ? ateam['records'][0] : 0;
-                    match['awayRecordAlt'] = (ateam['records']) ? ateam['records'][2] : 0;
-
-                    if (m['id'] == feat_id) {
-                        match['featured'] = true;
-                    }
-
-                    if (match['awayTeam'] === favteam || match['homeTeam'] === favteam) {
-                        forcedid = m['id'];
-                    }
-                    return match;
-                });
-            }
-
-            res.render('picks.html', {
-                season, week,
-                token,
-                username, favteam, avatar,
-                matches, forcedid,
-                feat_val, e_prefix
-            });
+// Links, games and submissions all go through the bot's API (`API_ADDR`),
+// which checks them against the week's games and the pool rules
+const api_url = `http://${process.env.API_ADDR || '127.0.0.1:9200'}`;
+
+// `null` when the API refuses (bad/expired token) or can't be reached
+async function apiGet(route) {
+    try {
+        const result = await fetch(`${api_url}${route}`);
+        if (result.ok) {
+            return await result.json();
+        }
+        console.log(`API refused GET ${route}: `, await result.json().catch(() => ({})));
+    } catch (err) {
+        console.log('Could not reach the API, err: ', err.message);
+    }
+    return null;
+}
+
+async function submitToApi(res, route, body) {
+    try {
+        const result = await fetch(`${api_url}${route}`, {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify(body),
         });
+        if (result.ok) {
+            res.render('success.html');
+            return;
+        }
+
+        const json = await result.json().catch(() => ({}));
+        console.log('Submission refused by the API: ', json);
+        res.render('error.html', { details: json['details'] || (json['error'] ? [json['error']] : []) });
+    } catch (err) {
+        console.log('Could not reach the API, err: ', err.message);
+        res.render('error.html');
+    }
+}
+
+// Confidence ranks come in as `conf_<matchid>`, split them from the team picks
+function splitConfidences(fields) {
+    const picks = {};
+    const confidences = {};
+    for (const [key, value] of Object.entries(fields)) {
+        if (key.startsWith('conf_')) {
+            confidences[key.slice('conf_'.length)] = Number(value);
+        } else {
+            picks[key] = value;
+        }
+    }
+    return { picks, confidences };
+}
+
+// The week's games in the shape picks.html reads, `null` if the API is down
+async function fetchWeekMatches(season, week, feat_id, favteam) {
+    const json = await apiGet(`/api/matches/${season}/${week}`);
+    if (!json) {
+        return null;
+    }
+
+    const record = (r, venue) => r ? { summary: venue ? r['venue'] : r['overall'], name: venue ? r['venue_name'] : '' } : {};
+    let forcedid = 0;
+    const matches = json['matches'].map((m) => {
+        if (m['away_team'] === favteam || m['home_team'] === favteam) {
+            forcedid = m['id'];
+        }
+        return {
+            idEvent: m['id'],
+            date: new Date(m['date']),
+            // Kicked-off games can't be picked or edited anymore
+            locked: m['locked'],
+            awayTeam: m['away_team'],
+            homeTeam: m['home_team'],
+            strAwayTeam: m['away_name'] || m['away_team'],
+            strHomeTeam: m['home_name'] || m['home_team'],
+            awayRecordAll: record(m['away_record'], false),
+            awayRecordAlt: record(m['away_record'], true),
+            homeRecordAll: record(m['home_record'], false),
+            homeRecordAlt: record(m['home_record'], true),
+            featured: m['id'] == feat_id,
+        };
     });
-});
 
-app.post('/submit/:token', (req, res) => {
-    const token = req.params['token'];
-    const { matchids, favteam, forcedid, feat_pick, ...picks } = req.body;
+    return { matches, forcedid };
+}
 
-    if (forcedid && forcedid !== '0') {
-        picks[forcedid] = favteam;
+// Weekly picks, or the edit page once they're in. Only games that haven't
+// kicked off can change, the others keep their pick and rank.
+async function renderPicks(req, res, purpose) {
+    const token = req.params['token'];
+    const link = await apiGet(`/api/tokens/${token}?purpose=${purpose}`);
+    if (!link) {
+        res.render('error.html');
+        return;
     }
 
-    const entries = Object.entries(picks);
-    if (entries.length === 0) {
-        console.log('Empty pick submission, refusing to insert');
+    const { season, week, pooler, feature } = link;
+    const feat_id = feature ? feature['match'] : null;
+    const weekMatches = await fetchWeekMatches(season, week, feat_id, pooler['favteam']);
+    if (!weekMatches) {
         res.render('error.html');
         return;
     }
 
-    LoadDB((db) => {
-        db.get('SELECT poolerid, season, week FROM pick_tokens WHERE token = ?', token, (err, row) => {
-            if (err || !row) {
-                console.log(err);
-                res.render('error.html');
-                return;
-            }
-            const { poolerid, season, week } = row;
-
-            db.run('BEGIN', (err) => {
-                if (err) {
-                    console.log(err);
-                    res.render('error.html');
-                    return;
-                }
-
-                const parentInsert = `
-                    INSERT INTO picks (season, week, poolerid, featurepick)
-                    VALUES (?, ?, ?, ?)
-                `;
-                db.run(parentInsert, [season, week, poolerid, Number(feat_pick)], function (err) {
-                    if (err) {
-                        db.run('ROLLBACK');
-                        console.log(err);
-                        res.render('error.html');
-                        return;
-                    }
-
-                    const pickid = this.lastID;
-                    const placeholders = entries.map(() => '(?, ?, ?)').join(', ');
-                    const params = entries.flatMap(([matchid, team]) => [pickid, matchid, team]);
-
-                    const childInsert = `
-                        INSERT INTO match_picks (pickid, matchid, team)
-                        VALUES ${placeholders}
-                    `;
-                    db.run(childInsert, params, (err) => {
-                        if (err) {
-                            db.run('ROLLBACK');
-                            console.log(err);
-                            res.render('error.html');
-                            return;
-                        }
-
-                        db.run('DELETE FROM pick_tokens WHERE token = ?', token, () => {
-                            db.run('COMMIT', () => res.render('success.html'));
-                        });
-                    });
-                });
-            });
-        });
+    const edit = purpose === 'picks-edit';
+    res.render('picks.html', {
+        season, week,
+        token,
+        username: pooler['name'], favteam: pooler['favteam'], avatar: pooler['avatar'],
+        matches: weekMatches.matches, forcedid: weekMatches.forcedid,
+        feat_val: feature ? feature['target'] : null,
+        confidence: link['mode'] === 'confidence',
+        e_prefix,
+        edit,
+        current: edit ? link['current']['picks'] : {},
+        current_feat: edit ? link['current']['featpick'] : null,
     });
+}
+
+app.get('/:token', (req, res) => renderPicks(req, res, 'picks'));
+
+app.post('/submit/:token', async (req, res) => {
+    const token = req.params['token'];
+    const { matchids, favteam, forcedid, feat_pick, ...submitted } = req.body;
+    const { picks, confidences } = splitConfidences(submitted);
+
+    // The bot fills in the favorite team's game and checks the rest against the week
+    const featpick = (feat_pick === '0' || feat_pick === '1') ? Number(feat_pick) : null;
+    await submitToApi(res, `/api/picks/${token}`, { picks, confidences, featpick });
 });
 
-app.get('/capsule/:token', (req, res) => {
+// Edit links are handed out by /choix once the picks exist
+app.get('/edit/:token', (req, res) => renderPicks(req, res, 'picks-edit'));
+
+app.post('/edit-submit/:token', async (req, res) => {
     const token = req.params['token'];
+    const { feat_pick, ...submitted } = req.body;
+    const { picks, confidences } = splitConfidences(submitted);
 
-    LoadDB((db) => {
-        db.get('SELECT season FROM pick_tokens WHERE token = ?', token, (err, row) => {
-            if (err || !row) {        // bad/expired token
-                if (err) console.log(err);
-                res.render('error.html');
-                return;
-            }
-
-            res.render('playoffs.html', {
-                afcTeams,
-                nfcTeams,
-                token,
-                season: row['season'],
-                e_prefix
-            });
-        });
+    // The bot keeps started games as they were and checks the ranks again
+    const featpick = (feat_pick === '0' || feat_pick === '1') ? Number(feat_pick) : null;
+    await submitToApi(res, `/api/picks-edit/${token}`, { picks, confidences, featpick });
+});
+
+app.get('/capsule/:token', async (req, res) => {
+    const token = req.params['token'];
+    const link = await apiGet(`/api/tokens/${token}?purpose=capsule`);
+    if (!link) {
+        res.render('error.html');
+        return;
+    }
+
+    res.render('playoffs.html', {
+        afcTeams,
+        nfcTeams,
+        token,
+        season: link['season'],
+        e_prefix
     });
 });
 
-const CAP_TYPE = { DIVISION_WIN: 0, WILDCARD: 1 };
-const CAP_CONF = { NFC: 0, AFC: 1 };
-const DIV_ORDER = ['North', 'South', 'East', 'West']; // index = division column value
-const TEAM_IDX = 6; // position of the team short name in a capsule row
+// The capsule pages send each field as a JSON string, `null` if one doesn't parse
+function parseCapsule(body) {
+    const { afcWinners, nfcWinners, afcWildcards, nfcWildcards, afcTopSeed, nfcTopSeed, champion } = body;
+    try {
+        return {
+            afc: { winners: JSON.parse(afcWinners), wildcards: JSON.parse(afcWildcards), top_seed: afcTopSeed },
+            nfc: { winners: JSON.parse(nfcWinners), wildcards: JSON.parse(nfcWildcards), top_seed: nfcTopSeed },
+            champion,
+        };
+    } catch (err) {
+        console.log(err);
+        return null;
+    }
+}
 
-app.post('/capsule-submit/:token', (req, res) => {
+app.post('/capsule-submit/:token', async (req, res) => {
     const token = req.params['token'];
-    const { afcWinners, nfcWinners, afcWildcards, nfcWildcards } = req.body;
-
-    // Parse the JSON strings from the form (pick payload only)
-    const afcWinnersObj = JSON.parse(afcWinners);
-    const nfcWinnersObj = JSON.parse(nfcWinners);
-    const afcWildcardsArr = JSON.parse(afcWildcards);
-    const nfcWildcardsArr = JSON.parse(nfcWildcards);
-
-    LoadDB((db) => {
-        // Identity (poolerid, season) comes from the token, never the body
-        db.get('SELECT poolerid, season FROM pick_tokens WHERE token = ?', token, (err, row) => {
-            if (err || !row) {
-                if (err) console.log(err);
-                res.render('error.html');
-                return;
-            }
-
-            const { poolerid, season } = row;
-
-            const rows = [];
-            DIV_ORDER.forEach((div, divIdx) => {
-                rows.push([season, poolerid, CAP_TYPE.DIVISION_WIN, CAP_CONF.AFC, divIdx, 0, GetTeamShortName(afcWinnersObj[div])]);
-                rows.push([season, poolerid, CAP_TYPE.DIVISION_WIN, CAP_CONF.NFC, divIdx, 0, GetTeamShortName(nfcWinnersObj[div])]);
-            });
-            afcWildcardsArr.forEach((name, slot) => {
-                rows.push([season, poolerid, CAP_TYPE.WILDCARD, CAP_CONF.AFC, 0, slot, GetTeamShortName(name)]);
-            });
-            nfcWildcardsArr.forEach((name, slot) => {
-                rows.push([season, poolerid, CAP_TYPE.WILDCARD, CAP_CONF.NFC, 0, slot, GetTeamShortName(name)]);
-            });
-
-            if (rows.length !== 14 || rows.some(r => !r[TEAM_IDX])) {
-                console.log('Incomplete capsule submission, refusing to insert');
-                res.render('error.html');
-                return;
-            }
-
-            const placeholders = rows.map(() => '(?, ?, ?, ?, ?, ?, ?)').join(', ');
-            const insert = `
-                INSERT INTO capsules (season, poolerid, type, conference, division, slot, team)
-                VALUES ${placeholders}
-            `;
-            db.run(insert, rows.flat(), (err) => {
-                if (err) {
-                    console.log(err);
-                    res.render('error.html');
-                    return;
-                }
-                // Consume the token so the link can't be replayed or double-submitted
-                db.run('DELETE FROM pick_tokens WHERE token = ?', token, () => res.render('success.html'));
-            });
-        });
-    });
+    const capsule = parseCapsule(req.body);
+    if (!capsule) {
+        res.render('error.html');
+        return;
+    }
+
+    // The bot checks the lock date and every team, full names are fine
+    await submitToApi(res, `/api/capsules/${token}`, capsule);
 });
 
-app.get('/capsule-repicks/:token', (req, res) => {
+app.get('/capsule-repicks/:token', async (req, res) => {
     const token = req.params['token'];
+    const link = await apiGet(`/api/tokens/${token}?purpose=capsule-repicks`);
+    if (!link || Object.keys(link['current']['capsule']['afc']['winners']).length === 0) {
+        res.render('error.html');
+        return;
+    }
 
-    LoadDB((db) => {
-        const sql = `
-            SELECT t.season, p.repicks, c.type, c.conference, c.division, c.slot, c.team
-            FROM pick_tokens AS t
-            JOIN poolers AS p
-                ON p.id = t.poolerid
-            JOIN capsules AS c
-                ON c.poolerid = t.poolerid AND c.season = t.season
-            WHERE token = ?
-        `;
-        db.all(sql, token, (err, rows) => {
-            if (err || !rows || rows.length === 0) {
-                if (err) console.log(err);
-                res.render('error.html');
-                return;
-            }
-
-            const afcWinners = rows
-                .filter(e => e.type === 0 && e.conference === 1)
-                .reduce((acc, e) => {
-                    acc[DIV_ORDER[e.division]] = e.team;
-                    return acc;
-                }, {});
-
-            const nfcWinners = rows
-                .filter(e => e.type === 0 && e.conference === 0)
-                .reduce((acc, e) => {
-                    acc[DIV_ORDER[e.division]] = e.team;
-                    return acc;
-                }, {});
-
-            let afcWildcards = rows.filter((e) => {
-                return e.type === 1 && e.conference === 1;
-            });
-            afcWildcards.sort((a, b) => a.slot - b.slot);
-            afcWildcards = afcWildcards.map((e) => e.team);
-
-            let nfcWildcards = rows.filter((e) => {
-                return e.type === 1 && e.conference === 0;
-            });
-            nfcWildcards.sort((a, b) => a.slot - b.slot);
-            nfcWildcards = nfcWildcards.map((e) => e.team);
-
-            res.render('playoff-repicks.html', {
-                afcWinners,
-                nfcWinners,
-                afcWildcards,
-                nfcWildcards,
-                repicks: rows[0]['repicks'],
-                afcTeams, nfcTeams,
-                token, season: rows[0]['season'],
-                e_prefix
-            });
-        });
+    const { capsule, repicks } = link['current'];
+    res.render('playoff-repicks.html', {
+        afcWinners: capsule['afc']['winners'],
+        nfcWinners: capsule['nfc']['winners'],
+        afcWildcards: capsule['afc']['wildcards'],
+        nfcWildcards: capsule['nfc']['wildcards'],
+        repicks,
+        afcTeams, nfcTeams,
+        token, season: link['season'],
+        e_prefix
     });
 });
 
-app.post('/capsule-repicks-submit/:token', (req, res) => {
+app.post('/capsule-repicks-submit/:token', async (req, res) => {
     const token = req.params['token'];
-    const { afcWinners, nfcWinners, afcWildcards, nfcWildcards } = req.body;
-
-    // Parse the JSON strings from the form (pick payload only)
-    const afcWinnersObj = JSON.parse(afcWinners);
-    const nfcWinnersObj = JSON.parse(nfcWinners);
-    const afcWildcardsArr = JSON.parse(afcWildcards);
-    const nfcWildcardsArr = JSON.parse(nfcWildcards);
-
-    LoadDB((db) => {
-        const sql = `
-            SELECT pt.poolerid, pt.season, pl.repicks
-            FROM pick_tokens AS pt
-            JOIN poolers AS pl
-                ON pt.poolerid = pl.id
-            WHERE token = ?
-        `;
-        db.get(sql, token, (err, row) => {
-            if (err || !row) {
-                if (err) console.log(err);
-                res.render('error.html');
-                return;
-            }
-
-            const { poolerid, season, repicks } = row;
-
-            db.all('SELECT id, type, conference, division, slot, team FROM capsules WHERE season = ? AND poolerid = ?', season, poolerid, (err, rows) => {
-                if (err || !rows) { if (err) console.log(err); res.render('error.html'); return; }
-
-                const changes = {};
-                for (const r of rows) {
-                    const is_win = r['type'] === 0;
-                    const is_afc = r['conference'] === 1;
-
-                    let new_team = '';
-                    if (is_win) {
-                        new_team = (is_afc) ? afcWinnersObj[DIV_ORDER[r['division']]] : nfcWinnersObj[DIV_ORDER[r['division']]];
-                    }
-                    else {
-                        new_team = (is_afc) ? afcWildcardsArr[r['slot']] : nfcWildcardsArr[r['slot']];
-                    }
-
-                    if (!new_team) {
-                        console.log('Incomplete re-pick submission, refusing to apply');
-                        res.render('error.html');
-                        return;
-                    }
-
-                    if (r['team'] !== new_team) {
-                        changes[r['id']] = new_team;
-                    }
-                }
-
-                const ids = Object.keys(changes);
-                if (ids.length > repicks) {
-                    console.log("Trying to change more than {repicks} capsule picks");
-                    res.render('error.html');
-                    return;
-                }
-                if (ids.length <= 0) {
-                    res.render('success.html');
-                    return;
-                }
-
-                db.serialize(() => {
-                    db.run('BEGIN');
-
-                    const stmt = db.prepare('UPDATE capsules SET team = ? WHERE id = ?');
-                    for (const id of ids) {
-                        stmt.run(changes[id], id);
-                    }
-                    stmt.finalize();
-
-                    db.run('UPDATE poolers SET repicks = repicks - ? WHERE id = ?', ids.length, poolerid);
-                    db.run('DELETE FROM pick_tokens WHERE token = ?', token);
-
-                    db.run('COMMIT', (err) => {
-                        if (err) {
-                            console.log(err);
-                            res.render('error.html');
-                            return;
-                        }
-                        res.render('success.html');
-                    });
-                });
-            });
-        });
-    });
+    const capsule = parseCapsule(req.body);
+    if (!capsule) {
+        res.render('error.html');
+        return;
+    }
+
+    // The bot counts the changed slots against the pooler's repicks
+    await submitToApi(res, `/api/capsule-repicks/${token}`, capsule);
 });
 
 
@@ -456,46 +282,3 @@ const nfcTeams = [
   { sname: 'SEA', name: 'Seattle Seahawks', division: 'West' },
   { sname: 'ARI', name: 'Arizona Cardinals', division: 'West' }
 ];
-
-const lNameMap = {
-    'Arizona Cardinals'    : 'ARI',
-    'Atlanta Falcons'      : 'ATL',
-    'Baltimore Ravens'     : 'BAL',
-    'Buffalo Bills'        : 'BUF',
-    'Carolina Panthers'    : 'CAR',
-    'Chicago Bears'        : 'CHI',
-    'Cincinnati Bengals'   : 'CIN',
-    'Cleveland Browns'     : 'CLE',
-    'Dallas Cowboys'       : 'DAL',
-    'Denver Broncos'       : 'DEN',
-    'Detroit Lions'        : 'DET',
-    'Green Bay Packers'    : 'GB',
-    'Houston Texans'       : 'HOU',
-    'Indianapolis Colts'   : 'IND',
-    'Jacksonville Jaguars' : 'JAX',
-    'Kansas City Chiefs'   : 'KC',
-    'Los Angeles Rams'     : 'LAR',
-    'St. Louis Rams'       : 'LAR',
-    'Los Angeles Chargers' : 'LAC',
-    'Las Vegas Raiders'    : 'LV',
-    'Oakland Raiders'      : 'LV',
-    'Miami Dolphins'       : 'MIA',
-    'Minnesota Vikings'    : 'MIN',
-    'New England Patriots' : 'NE',
-    'New Orleans Saints'   : 'NO',
-    'New York Giants'      : 'NYG',
-    'New York Jets'        : 'NYJ',
-    'Philadelphia Eagles'  : 'PHI',
-    'Pittsburgh Steelers'  : 'PIT',
-    'Seattle Seahawks'     : 'SEA',
-    'San Francisco 49ers'  : 'SF',
-    'Tampa Bay Buccaneers' : 'TB',
-    'Tennessee Titans'     : 'TEN',
-    'Washington'           : 'WSH',
-    'Washington Commanders': 'WSH',
-    'Washington Redskins'  : 'WSH'
-};
-
-const GetTeamShortName = (longname) => {
-    return lNameMap[longname];
-};
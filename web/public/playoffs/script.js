@@ -30,6 +30,9 @@ let afcWinners = {};
 let nfcWinners = {};
 let afcWildcards = [];
 let nfcWildcards = [];
+let afcTopSeed = null;
+let nfcTopSeed = null;
+let champion = null;
 
 const divisions = [ 'North', 'South', 'East', 'West' ];
 
@@ -58,12 +61,31 @@ function toggleWildcard(conference, teamName) {
   render();
 }
 
+function toggleTopSeed(conference, teamName) {
+  if (conference === 'AFC') {
+    afcTopSeed = afcTopSeed === teamName ? null : teamName;
+  } else {
+    nfcTopSeed = nfcTopSeed === teamName ? null : teamName;
+  }
+
+  render();
+}
+
+function toggleChampion(teamName) {
+  champion = champion === teamName ? null : teamName;
+  render();
+}
+
 function allWinnersSelected() {
   return divisions.every(d => afcWinners[d] && nfcWinners[d]);
 }
 
+function allWildcardsSelected() {
+  return afcWildcards.length === 3 && nfcWildcards.length === 3;
+}
+
 function canSubmit() {
-  return phase === 'wildcards' && afcWildcards.length === 3 && nfcWildcards.length === 3;
+  return phase === 'seeds' && allWildcardsSelected() && afcTopSeed && nfcTopSeed && champion;
 }
 
 function proceedToWildcards() {
@@ -71,11 +93,84 @@ function proceedToWildcards() {
   render();
 }
 
+function proceedToSeeds() {
+  // Drop earlier seed picks that are no longer among the chosen playoff teams
+  const afc = playoffTeams(afcTeams, 'AFC');
+  const nfc = playoffTeams(nfcTeams, 'NFC');
+  if (!afc.winners.some(t => t.name === afcTopSeed)) afcTopSeed = null;
+  if (!nfc.winners.some(t => t.name === nfcTopSeed)) nfcTopSeed = null;
+  if (![...afc.all, ...nfc.all].some(t => t.name === champion)) champion = null;
+
+  phase = 'seeds';
+  render();
+}
+
 function backToWinners() {
   phase = 'winners';
   render();
 }
 
+function backToWildcards() {
+  phase = 'wildcards';
+  render();
+}
+
+// Seeds and champion are picked among the playoff teams chosen in the previous steps
+function playoffTeams(teams, conference) {
+  const winners = Object.values(conference === 'AFC' ? afcWinners : nfcWinners);
+  const wildcards = conference === 'AFC' ? afcWildcards : nfcWildcards;
+  return {
+    winners: teams.filter(t => winners.includes(t.name)),
+    all: teams.filter(t => winners.includes(t.name) || wildcards.includes(t.name)),
+  };
+}
+
+function renderPickCard(title, icon, teams, selected, onclick) {
+  let html = `
+    <div class="col-12">
+      <div class="card">
+        <div class="card-body p-2 p-md-3">
+          <div class="d-flex align-items-center mb-2">
+            <i class="bi ${icon} text-warning me-2"></i>
+            <h6 class="mb-0 fw-bold text-uppercase small">${title}</h6>
+          </div>
+          <div class="d-grid gap-2">
+  `;
+
+  teams.forEach(team => {
+    const isSelected = selected === team.name;
+    const btnClass = isSelected ? 'btn-winner-selected' : 'btn-outline-secondary';
+
+    html += `
+      <button class="btn ${btnClass} btn-sm text-start d-flex align-items-center"
+              onclick="${onclick(team)}">
+        <img src="/teams/${e_prefix}${team.sname}.png" alt="${team.name}" class="team-logo me-2">
+        <span class="flex-grow-1 team-name">${team.name}</span>
+        ${isSelected ? '<i class="bi bi-check-lg"></i>' : ''}
+      </button>
+    `;
+  });
+
+  html += `
+          </div>
+        </div>
+      </div>
+    </div>
+  `;
+
+  return html;
+}
+
+function renderSeeds(teams, conference) {
+  const { winners, all } = playoffTeams(teams, conference);
+  const topSeed = conference === 'AFC' ? afcTopSeed : nfcTopSeed;
+
+  return renderPickCard('Tête de série #1', 'bi-1-circle-fill', winners, topSeed,
+                        t => `toggleTopSeed('${conference}', '${t.name}')`)
+       + renderPickCard('Champion du Super Bowl', 'bi-trophy-fill', all, champion,
+                        t => `toggleChampion('${t.name}')`);
+}
+
 function renderDivisionWinners(teams, division, conference) {
   const divisionTeams = teams.filter(t => t.division === division);
   const winners = conference === 'AFC' ? afcWinners : nfcWinners;
@@ -190,7 +285,7 @@ function render() {
     actionBtn.disabled = !allWinnersSelected();
     actionBtn.onclick = proceedToWildcards;
     backBtn.style.display = 'none';
-  } else {
+  } else if (phase === 'wildcards') {
     phaseDesc.textContent = 'Étape 2: Choisir les équipes Wild Cards (3 par conférence)';
     afcDiv.innerHTML = divisions.map(d => renderDivisionWildcards(afcTeams, d, 'AFC')).join('');
     nfcDiv.innerHTML = divisions.map(d => renderDivisionWildcards(nfcTeams, d, 'NFC')).join('');
@@ -200,11 +295,28 @@ function render() {
     afcCounter.className = afcWildcards.length === 3 ? 'badge bg-success' : 'badge bg-secondary';
     nfcCounter.className = nfcWildcards.length === 3 ? 'badge bg-success' : 'badge bg-secondary';
     
+    actionBtn.textContent = 'Continuer aux Têtes de série';
+    actionBtn.disabled = !allWildcardsSelected();
+    actionBtn.onclick = proceedToSeeds;
+    backBtn.textContent = 'Retour aux Gagnants de Division';
+    backBtn.style.display = 'inline-block';
+    backBtn.onclick = backToWinners;
+  } else {
+    phaseDesc.textContent = 'Étape 3: Choisir la tête de série #1 de chaque conférence et le champion';
+    afcDiv.innerHTML = renderSeeds(afcTeams, 'AFC');
+    nfcDiv.innerHTML = renderSeeds(nfcTeams, 'NFC');
+
+    afcCounter.textContent = afcTopSeed ? '1 / 1' : '0 / 1';
+    nfcCounter.textContent = nfcTopSeed ? '1 / 1' : '0 / 1';
+    afcCounter.className = afcTopSeed ? 'badge bg-success' : 'badge bg-secondary';
+    nfcCounter.className = nfcTopSeed ? 'badge bg-success' : 'badge bg-secondary';
+
     actionBtn.textContent = 'Soumettre la capsule';
     actionBtn.disabled = !canSubmit();
     actionBtn.onclick = submitForm;
+    backBtn.textContent = 'Retour aux Wild Cards';
     backBtn.style.display = 'inline-block';
-    backBtn.onclick = backToWinners;
+    backBtn.onclick = backToWildcards;
   }
 }
 
@@ -213,6 +325,9 @@ function submitForm() {
   document.getElementById('nfc-winners-input').value = JSON.stringify(nfcWinners);
   document.getElementById('afc-wildcards-input').value = JSON.stringify(afcWildcards);
   document.getElementById('nfc-wildcards-input').value = JSON.stringify(nfcWildcards);
+  document.getElementById('afc-top-seed-input').value = afcTopSeed;
+  document.getElementById('nfc-top-seed-input').value = nfcTopSeed;
+  document.getElementById('champion-input').value = champion;
   document.getElementById('playoff-form').submit();
 }
 